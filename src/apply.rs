@@ -0,0 +1,602 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    ops::Range,
+    path::Path,
+};
+
+use eyre::{bail, Context, ContextCompat};
+use flate2::read::MultiGzDecoder;
+use itertools::Itertools;
+use log::debug;
+use noodles::{
+    bed::{
+        self,
+        record::{Builder, OptionalFields},
+    },
+    core::Position,
+    fasta::record::Definition,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    breaks::{split_at_breaks, write_breaks},
+    false_dupe::Repeat,
+    io::{read_bed_records, FastaWriter, TruthWriter},
+    misjoin::RemovedSequence,
+    utils::{reverse_complement, SegmentedSequence, SequenceType},
+};
+
+/// Structural variant to apply in 0-based, half-open original coordinates.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Variant {
+    Deletion(Range<usize>),
+    /// Deletion replaced with Ns.
+    Gap(Range<usize>),
+    /// Sequence inserted before `pos`.
+    Insertion {
+        pos: usize,
+        seq: String,
+    },
+    /// Tandem duplication repeated `count` times in total.
+    Duplication {
+        #[serde(flatten)]
+        range: Range<usize>,
+        count: usize,
+    },
+    Inversion(Range<usize>),
+    /// Record split before `pos`.
+    Break {
+        pos: usize,
+    },
+}
+
+impl Variant {
+    pub fn range(&self) -> Range<usize> {
+        match self {
+            Variant::Deletion(range)
+            | Variant::Gap(range)
+            | Variant::Duplication { range, .. }
+            | Variant::Inversion(range) => range.clone(),
+            Variant::Insertion { pos, .. } | Variant::Break { pos } => *pos..*pos,
+        }
+    }
+
+    /// Type of misassembly the variant introduces.
+    pub fn sequence_type(&self) -> SequenceType {
+        match self {
+            Variant::Deletion(_) => SequenceType::Misjoin,
+            Variant::Gap(_) => SequenceType::Gap,
+            Variant::Insertion { .. } => SequenceType::Insertion,
+            Variant::Duplication { .. } => SequenceType::FalseDuplication,
+            Variant::Inversion(_) => SequenceType::Inversion,
+            Variant::Break { .. } => SequenceType::Break,
+        }
+    }
+
+    /// Number of original bases affected or inserted. Breaks have no length.
+    pub fn length(&self) -> usize {
+        match self {
+            Variant::Insertion { seq, .. } => seq.len(),
+            _ => self.range().len(),
+        }
+    }
+}
+
+/// Variant with the unique ID of the misassembly it introduces, if any.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Event {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(flatten)]
+    pub variant: Variant,
+}
+
+/// Unique IDs of events numbered from 1 in output order. ex. misasim.3
+#[derive(Debug, Default)]
+pub struct EventIds {
+    num_events: usize,
+}
+
+impl EventIds {
+    pub fn next_id(&mut self) -> String {
+        self.num_events += 1;
+        format!("misasim.{}", self.num_events)
+    }
+}
+
+/// Applied variant written to the truth BED file.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum AppliedVariant<'a> {
+    Deletion(RemovedSequence<'a>),
+    Duplication(Repeat),
+    /// Inserted sequence after the base at `pos`.
+    Insertion {
+        pos: usize,
+        seq: String,
+    },
+    Inversion(Range<usize>),
+}
+
+impl<'a> TryFrom<AppliedVariant<'a>> for Builder<3> {
+    type Error = eyre::Error;
+
+    fn try_from(variant: AppliedVariant<'a>) -> Result<Self, Self::Error> {
+        let (start, end, fields) = match variant {
+            AppliedVariant::Deletion(rem_seq) => return rem_seq.try_into(),
+            AppliedVariant::Duplication(repeat) => return Ok(repeat.into()),
+            AppliedVariant::Insertion { pos, seq } => {
+                (pos, pos + 1, vec!["Inserted".to_owned(), seq])
+            }
+            AppliedVariant::Inversion(range) => {
+                (range.start, range.end, vec!["Inverted".to_owned()])
+            }
+        };
+        Ok(bed::Record::<3>::builder()
+            .set_start_position(Position::new(start + 1).context("Invalid start position")?)
+            .set_end_position(Position::new(end).context("Zero end position")?)
+            .set_optional_fields(OptionalFields::from(fields)))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct AppliedSequence<'a> {
+    pub seq: SegmentedSequence<'a>,
+    pub variants: Vec<AppliedVariant<'a>>,
+    /// Ranges of misassembled bases in the new sequence and their type.
+    pub misassembled_ranges: Vec<(SequenceType, Range<usize>)>,
+}
+
+/// Read DEL, INS, DUP, and INV records from an uncompressed or gzipped VCF file.
+/// Other records are skipped.
+pub fn read_vcf(vcf: impl AsRef<Path>) -> eyre::Result<HashMap<String, Vec<Variant>>> {
+    let vcf = vcf.as_ref();
+    let file = File::open(vcf).with_context(|| format!("Cannot open VCF: {vcf:?}"))?;
+    let reader: Box<dyn BufRead> = if vcf.extension().is_some_and(|ext| ext == "gz") {
+        Box::new(BufReader::new(MultiGzDecoder::new(file)))
+    } else {
+        Box::new(BufReader::new(file))
+    };
+
+    let mut variants: HashMap<String, Vec<Variant>> = HashMap::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.starts_with('#') || line.is_empty() {
+            continue;
+        }
+        let Some((chrom, pos, _, ref_allele, alt_allele, _, _, info)) =
+            line.split('\t').next_tuple()
+        else {
+            bail!("Invalid VCF record on line {}: {line}", i + 1)
+        };
+        match parse_variant(pos, ref_allele, alt_allele, info)
+            .with_context(|| format!("Invalid VCF record on line {}: {line}", i + 1))?
+        {
+            Some(variant) => variants.entry(chrom.to_owned()).or_default().push(variant),
+            None => log::warn!("Skipping unsupported VCF record on line {}.", i + 1),
+        }
+    }
+    Ok(variants)
+}
+
+fn parse_variant(
+    pos: &str,
+    ref_allele: &str,
+    alt_allele: &str,
+    info: &str,
+) -> eyre::Result<Option<Variant>> {
+    if alt_allele.contains(',') {
+        bail!("Multiallelic records aren't supported.")
+    }
+    let pos: usize = pos.parse()?;
+    if pos == 0 {
+        bail!("POS must be at least 1.")
+    }
+    let info: HashMap<&str, &str> = info
+        .split(';')
+        .filter_map(|field| field.split_once('='))
+        .collect();
+
+    // Sequence-resolved alleles sharing a padding base.
+    if !alt_allele.starts_with('<') {
+        if alt_allele.contains(['[', ']']) {
+            return Ok(None);
+        }
+        if ref_allele.as_bytes().first() != alt_allele.as_bytes().first() {
+            log::warn!("REF ({ref_allele}) and ALT ({alt_allele}) don't share a padding base.");
+            return Ok(None);
+        }
+        let start = pos - 1;
+        let variant = match ref_allele.len().cmp(&alt_allele.len()) {
+            std::cmp::Ordering::Greater => {
+                Variant::Deletion(start + alt_allele.len()..start + ref_allele.len())
+            }
+            std::cmp::Ordering::Less => Variant::Insertion {
+                pos: start + ref_allele.len(),
+                seq: alt_allele[ref_allele.len()..].to_owned(),
+            },
+            std::cmp::Ordering::Equal => return Ok(None),
+        };
+        return Ok(Some(variant));
+    }
+
+    // Symbolic alleles. ex. <DEL> or <DUP:TANDEM>
+    let svtype = info.get("SVTYPE").copied().unwrap_or_else(|| {
+        alt_allele
+            .trim_matches(['<', '>'])
+            .split(':')
+            .next()
+            .unwrap_or_default()
+    });
+    let end = if let Some(end) = info.get("END") {
+        Some(end.parse::<usize>()?)
+    } else if let Some(svlen) = info.get("SVLEN") {
+        Some(
+            pos.checked_add(svlen.parse::<isize>()?.unsigned_abs())
+                .context("SVLEN out of bounds.")?,
+        )
+    } else {
+        None
+    };
+    if end.is_some_and(|end| end < pos) {
+        bail!("END must not be before POS.")
+    }
+    // Symbolic alleles start after the padding base.
+    let range = || end.map(|end| pos..end).context("No END or SVLEN.");
+    let variant = match svtype {
+        "DEL" => Variant::Deletion(range()?),
+        "DUP" => Variant::Duplication {
+            range: range()?,
+            count: 2,
+        },
+        "INV" => Variant::Inversion(range()?),
+        "INS" => Variant::Insertion {
+            pos,
+            seq: info
+                .get("SVINSSEQ")
+                .context("No SVINSSEQ for symbolic insertion.")?
+                .to_string(),
+        },
+        _ => return Ok(None),
+    };
+    Ok(Some(variant))
+}
+
+/// Read exact misassemblies from a BED file with the misassembly type in the 4th column.
+/// Rows without a type are `default_type`. ex. misjoin, gap, false_duplication, break, or inversion
+///
+/// The 5th column is the copy number of false duplications, 2 by default,
+/// or the sequence inserted before the start of insertions.
+pub fn read_replay_bed(
+    bed: impl AsRef<Path>,
+    default_type: Option<SequenceType>,
+) -> eyre::Result<HashMap<String, Vec<Variant>>> {
+    let mut variants: HashMap<String, Vec<Variant>> = HashMap::new();
+    for rec in read_bed_records(bed)? {
+        let rec = rec?;
+        let range = usize::from(rec.start_position()) - 1..usize::from(rec.end_position());
+        let fields = rec.optional_fields();
+        let mtype = match fields.first() {
+            Some(mtype) => mtype.parse()?,
+            None => default_type.with_context(|| format!("No misassembly type: {rec}"))?,
+        };
+        let variant = exact_variant(mtype, range, fields.get(1).map(String::as_str))
+            .with_context(|| format!("Invalid misassembly: {rec}"))?;
+        variants
+            .entry(rec.reference_sequence_name().to_owned())
+            .or_default()
+            .push(variant);
+    }
+    Ok(variants)
+}
+
+/// Read exact misassemblies of one type from regions. ex. chr1:1000-2000 or chr1:1000
+/// Positions are 1-based and inclusive. Regions without an end are a single base.
+pub fn read_exact_regions(
+    regions: &[String],
+    mtype: SequenceType,
+) -> eyre::Result<HashMap<String, Vec<Variant>>> {
+    let mut variants: HashMap<String, Vec<Variant>> = HashMap::new();
+    for region in regions {
+        let (name, positions) = region
+            .rsplit_once(':')
+            .with_context(|| format!("Invalid region: {region:?}. Expected ctg:pos[-end]"))?;
+        let (start, end) = match positions.split_once('-') {
+            Some((start, end)) => (start, end),
+            None => (positions, positions),
+        };
+        let (start, end): (usize, usize) = (
+            start.replace(',', "").parse()?,
+            end.replace(',', "").parse()?,
+        );
+        if start == 0 || start > end {
+            bail!("Invalid region: {region:?}. Start must be at least 1 and at most the end.")
+        }
+        let variant = exact_variant(mtype, start - 1..end, None)
+            .with_context(|| format!("Invalid misassembly: {region:?}"))?;
+        variants.entry(name.to_owned()).or_default().push(variant);
+    }
+    Ok(variants)
+}
+
+/// Variant of a misassembly at an exact 0-based `range`.
+/// `field` is the copy number of false duplications, 2 by default, or the sequence inserted before the start of insertions.
+fn exact_variant(
+    mtype: SequenceType,
+    range: Range<usize>,
+    field: Option<&str>,
+) -> eyre::Result<Variant> {
+    Ok(match mtype {
+        SequenceType::Misjoin => Variant::Deletion(range),
+        SequenceType::Gap => Variant::Gap(range),
+        SequenceType::FalseDuplication => Variant::Duplication {
+            range,
+            count: field.map_or(Ok(2), str::parse)?,
+        },
+        SequenceType::Break => Variant::Break { pos: range.start },
+        SequenceType::Inversion => Variant::Inversion(range),
+        SequenceType::Insertion => Variant::Insertion {
+            pos: range.start,
+            seq: field.context("No inserted sequence")?.to_owned(),
+        },
+    })
+}
+
+/// Write a record with variants applied and its misassemblies.
+/// Records with breaks are split and can't have other variants.
+pub fn write_variants<O: Write, B: Write>(
+    definition: Definition,
+    seq: &[u8],
+    variants: &[Variant],
+    soft_mask: bool,
+    writer_fa: &mut FastaWriter<O>,
+    output_bed: &mut Option<TruthWriter<B>>,
+) -> eyre::Result<()> {
+    if variants.iter().all(|v| matches!(v, Variant::Break { .. })) {
+        let positions = variants
+            .iter()
+            .map(|v| v.range().start)
+            .sorted()
+            .dedup()
+            .collect_vec();
+        if positions.iter().any(|pos| *pos == 0 || *pos >= seq.len()) {
+            bail!("Break out of bounds: {positions:?}")
+        }
+        return write_breaks(
+            std::str::from_utf8(definition.name())?,
+            definition.description(),
+            split_at_breaks(seq, positions),
+            writer_fa,
+            output_bed,
+        );
+    }
+
+    let applied_seq = apply_variants(seq, variants, soft_mask)?;
+    debug!("{} variant(s) applied.", applied_seq.variants.len());
+    if let Some(writer_bed) = output_bed.as_mut() {
+        writer_bed.write_misassemblies(
+            std::str::from_utf8(definition.name())?,
+            applied_seq.variants,
+            &applied_seq.misassembled_ranges,
+            applied_seq.seq.len(),
+        )?;
+    }
+    let misassembled_ranges = applied_seq
+        .misassembled_ranges
+        .into_iter()
+        .map(|(_, range)| range)
+        .collect_vec();
+    writer_fa.write_record_chunks(
+        &definition,
+        applied_seq.seq.len(),
+        applied_seq.seq.segments().iter().map(eyre::Ok),
+        &misassembled_ranges,
+    )
+}
+
+/// Apply variants to a sequence. Misassembled sequence is lowercased if `soft_mask`.
+pub fn apply_variants<'a>(
+    seq: &'a [u8],
+    variants: &[Variant],
+    soft_mask: bool,
+) -> eyre::Result<AppliedSequence<'a>> {
+    let mut new_seq = SegmentedSequence::default();
+    let mut applied = Vec::with_capacity(variants.len());
+    let mut misassembled_ranges = Vec::with_capacity(variants.len());
+
+    let mut pos = 0;
+    for variant in variants
+        .iter()
+        .sorted_by_key(|v| (v.range().start, v.range().end))
+    {
+        let range = variant.range();
+        if range.start > range.end {
+            bail!("Variant ends before it starts: {variant:?}")
+        }
+        if range.start < pos || range.end > seq.len() {
+            bail!("Variant overlaps another variant or is out of bounds: {variant:?}")
+        }
+        new_seq.push(&seq[pos..range.start]);
+        let new_start = new_seq.len();
+        let mask = |seq: Cow<'a, [u8]>| {
+            if soft_mask {
+                Cow::Owned(seq.to_ascii_lowercase())
+            } else {
+                seq
+            }
+        };
+        let (mtype, applied_variant) = match variant {
+            Variant::Deletion(range) | Variant::Gap(range) => {
+                let mtype = if let Variant::Gap(_) = variant {
+                    new_seq.push(mask(vec![b'N'; range.len()].into()));
+                    SequenceType::Gap
+                } else {
+                    SequenceType::Misjoin
+                };
+                (
+                    mtype,
+                    AppliedVariant::Deletion(RemovedSequence {
+                        start: range.start,
+                        end: range.end,
+                        seq: &seq[range.clone()],
+                    }),
+                )
+            }
+            Variant::Insertion { pos, seq: ins_seq } => {
+                new_seq.push(mask(ins_seq.as_bytes().to_vec().into()));
+                (
+                    SequenceType::Insertion,
+                    AppliedVariant::Insertion {
+                        pos: pos.saturating_sub(1),
+                        seq: ins_seq.clone(),
+                    },
+                )
+            }
+            Variant::Duplication { range, count } => {
+                let dup_seq = mask(seq[range.clone()].into());
+                for _ in 0..*count {
+                    new_seq.push(dup_seq.clone());
+                }
+                (
+                    SequenceType::FalseDuplication,
+                    AppliedVariant::Duplication(Repeat {
+                        seq: seq[range.clone()].to_vec(),
+                        start: range.start,
+                        count: *count,
+                    }),
+                )
+            }
+            Variant::Inversion(range) => {
+                new_seq.push(mask(reverse_complement(&seq[range.clone()]).into()));
+                (
+                    SequenceType::Inversion,
+                    AppliedVariant::Inversion(range.clone()),
+                )
+            }
+            Variant::Break { .. } => bail!("Breaks can't be applied with other variants."),
+        };
+        misassembled_ranges.push((mtype, new_start..new_seq.len()));
+        applied.push(applied_variant);
+        pos = range.end;
+    }
+    new_seq.push(&seq[pos..]);
+
+    Ok(AppliedSequence {
+        seq: new_seq,
+        variants: applied,
+        misassembled_ranges,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_variant() {
+        assert_eq!(
+            parse_variant("3", "GTT", "G", ".").unwrap(),
+            Some(Variant::Deletion(3..5))
+        );
+        assert_eq!(
+            parse_variant("3", "G", "GAA", ".").unwrap(),
+            Some(Variant::Insertion {
+                pos: 3,
+                seq: "AA".to_owned()
+            })
+        );
+        assert_eq!(
+            parse_variant("3", "G", "<DUP:TANDEM>", "END=10").unwrap(),
+            Some(Variant::Duplication {
+                range: 3..10,
+                count: 2
+            })
+        );
+        assert_eq!(
+            parse_variant("3", "G", "<INV>", "SVTYPE=INV;SVLEN=-7").unwrap(),
+            Some(Variant::Inversion(3..10))
+        );
+        assert_eq!(parse_variant("3", "G", "A", ".").unwrap(), None);
+        assert!(parse_variant("3", "G", "<DEL>", ".").is_err());
+        assert!(parse_variant("10", "G", "<DEL>", "END=5").is_err());
+        assert!(parse_variant("0", "G", "GAA", ".").is_err());
+        assert!(parse_variant(&usize::MAX.to_string(), "G", "<DEL>", "SVLEN=-1").is_err());
+        assert_eq!(parse_variant("3", "G", "TAA", ".").unwrap(), None);
+    }
+
+    #[test]
+    fn test_read_exact_regions() {
+        let regions = ["chr1:101-200", "chr1:1,000", "HLA:A:5-6"].map(String::from);
+        let variants = read_exact_regions(&regions, SequenceType::Misjoin).unwrap();
+        assert_eq!(
+            variants["chr1"],
+            [Variant::Deletion(100..200), Variant::Deletion(999..1000)]
+        );
+        assert_eq!(variants["HLA:A"], [Variant::Deletion(4..6)]);
+        let variants = read_exact_regions(&regions[..1], SequenceType::Break).unwrap();
+        assert_eq!(variants["chr1"], [Variant::Break { pos: 100 }]);
+        for region in ["chr1", "chr1:0-10", "chr1:20-10", "chr1:a-b"] {
+            assert!(read_exact_regions(&[region.to_owned()], SequenceType::Gap).is_err());
+        }
+    }
+
+    #[test]
+    fn test_apply_variants() {
+        let seq = b"AAAAGGGGCCCCTTTTACGT";
+        let variants = [
+            Variant::Inversion(16..20),
+            Variant::Deletion(4..8),
+            Variant::Insertion {
+                pos: 2,
+                seq: "NN".to_owned(),
+            },
+            Variant::Duplication {
+                range: 12..16,
+                count: 2,
+            },
+        ];
+        let applied = apply_variants(seq, &variants, false).unwrap();
+        assert_eq!(
+            applied.seq,
+            SegmentedSequence::from(b"AANNAACCCCTTTTTTTTACGT".as_slice())
+        );
+        assert_eq!(
+            applied.misassembled_ranges,
+            [
+                (SequenceType::Insertion, 2..4),
+                (SequenceType::Misjoin, 6..6),
+                (SequenceType::FalseDuplication, 10..18),
+                (SequenceType::Inversion, 18..22),
+            ]
+        );
+        assert!(apply_variants(
+            seq,
+            &[Variant::Deletion(4..8), Variant::Inversion(6..10)],
+            false
+        )
+        .is_err());
+        assert!(
+            apply_variants(seq, &[Variant::Deletion(Range { start: 8, end: 4 })], false).is_err()
+        );
+    }
+
+    #[test]
+    fn test_apply_variants_soft_mask() {
+        let seq = b"AAAAGGGGCCCCTTTT";
+        let variants = [
+            Variant::Gap(4..8),
+            Variant::Duplication {
+                range: 12..16,
+                count: 3,
+            },
+        ];
+        let applied = apply_variants(seq, &variants, true).unwrap();
+        assert_eq!(
+            applied.seq,
+            SegmentedSequence::from(b"AAAAnnnnCCCCtttttttttttt".as_slice())
+        );
+    }
+}
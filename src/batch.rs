@@ -0,0 +1,200 @@
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+
+use eyre::{bail, Context};
+use rayon::prelude::*;
+
+use crate::{
+    cli::Cli,
+    config::{Config, OutputFormat},
+    generate_misassemblies,
+};
+
+/// Row of a samplesheet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sample {
+    pub fasta: PathBuf,
+    /// File of misasim arguments. ex. "misjoin -n 5 -l 5000 --soft-mask"
+    pub config: PathBuf,
+    pub seed: Option<u64>,
+    /// Prefix of the output files, {prefix}.fa and {prefix}.bed.
+    pub output_prefix: PathBuf,
+}
+
+/// Read a TSV samplesheet with the columns: fasta, config, seed, and output prefix.
+/// Empty lines, lines starting with "#", and a header starting with "fasta" are skipped.
+/// Seeds of "." or empty seeds are generated. Output prefixes must be unique.
+pub fn read_samplesheet(path: impl AsRef<Path>) -> eyre::Result<Vec<Sample>> {
+    let path = path.as_ref();
+    let file = File::open(path).with_context(|| format!("Cannot open samplesheet: {path:?}"))?;
+    let mut samples = vec![];
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || line.starts_with('#') || line.starts_with("fasta\t") {
+            continue;
+        }
+        let [fasta, config, seed, output_prefix] = line.split('\t').collect::<Vec<_>>()[..] else {
+            bail!("Expected 4 columns at {path:?}:{}: {line:?}", i + 1)
+        };
+        let seed = match seed {
+            "" | "." => None,
+            seed => Some(
+                seed.parse()
+                    .with_context(|| format!("Invalid seed at {path:?}:{}: {seed:?}", i + 1))?,
+            ),
+        };
+        samples.push(Sample {
+            fasta: fasta.into(),
+            config: config.into(),
+            seed,
+            output_prefix: output_prefix.into(),
+        });
+    }
+    let mut prefixes = HashSet::new();
+    if let Some(sample) = samples
+        .iter()
+        .find(|sample| !prefixes.insert(&sample.output_prefix))
+    {
+        bail!("Duplicate output prefix: {:?}", sample.output_prefix)
+    }
+    Ok(samples)
+}
+
+/// Read the whitespace-separated arguments of a config file. Text after "#" is ignored.
+pub fn read_config_args(path: impl AsRef<Path>) -> eyre::Result<Vec<String>> {
+    let path = path.as_ref();
+    let contents =
+        std::fs::read_to_string(path).with_context(|| format!("Cannot read config: {path:?}"))?;
+    Ok(contents
+        .lines()
+        .flat_map(|line| {
+            line.split('#')
+                .next()
+                .unwrap_or_default()
+                .split_whitespace()
+        })
+        .map(str::to_owned)
+        .collect())
+}
+
+impl Sample {
+    /// Build the arguments of a sample from its config. `force` and `resume` are added to them.
+    /// The input, seed, and output files of the sample replace any in the config.
+    pub fn cli(&self, force: bool, resume: bool) -> eyre::Result<Cli> {
+        let args = read_config_args(&self.config)?;
+        let mut cli = Cli::try_parse_with_preset(std::iter::once("misasim".to_owned()).chain(args))
+            .with_context(|| format!("Invalid arguments in config: {:?}", self.config))?;
+        let ext = match cli.output_format {
+            OutputFormat::Fasta => "fa",
+            OutputFormat::Fastq => "fq",
+        };
+        cli.infile = Some(self.fasta.clone());
+        cli.seed = self.seed.or(cli.seed);
+        if cli.split_output_dir.is_none() {
+            cli.outfile = Some(self.output_path(ext));
+        }
+        cli.outbedfile = Some(self.output_path("bed"));
+        // Optional outputs are written per sample if requested in the config.
+        if cli.copy_number_bedgraph.is_some() {
+            cli.copy_number_bedgraph = Some(self.output_path("copy_number.bedgraph"));
+        }
+        if cli.stats_json.is_some() {
+            cli.stats_json = Some(self.output_path("stats.json"));
+        }
+        if cli.igv_session.is_some() {
+            cli.igv_session = Some(self.output_path("igv.xml"));
+        }
+        cli.force |= force;
+        cli.resume |= resume;
+        Ok(cli)
+    }
+
+    fn output_path(&self, ext: &str) -> PathBuf {
+        let mut path = self.output_prefix.clone().into_os_string();
+        path.push(format!(".{ext}"));
+        path.into()
+    }
+}
+
+/// Misassemble every sample with its config. Samples are run at the same time if `parallel`.
+pub fn run_samples(samples: &[Sample], configs: Vec<Config>, parallel: bool) -> eyre::Result<()> {
+    if samples.len() != configs.len() {
+        bail!("Expected a config for each of {} samples.", samples.len())
+    }
+    let run = |(sample, config): (&Sample, Config)| {
+        log::info!(
+            "Misassembling {:?} with {:?} to {:?}.",
+            sample.fasta,
+            sample.config,
+            sample.output_prefix
+        );
+        generate_misassemblies(config)
+            .with_context(|| format!("Failed to misassemble sample {:?}", sample.output_prefix))
+    };
+    if parallel {
+        samples.par_iter().zip(configs).try_for_each(run)
+    } else {
+        samples.iter().zip(configs).try_for_each(run)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        commands::{Commands, MisassemblyCommands},
+        test_utils::TestDir,
+    };
+
+    #[test]
+    fn test_read_samplesheet() {
+        let dir = TestDir::new("samplesheet");
+        let config = dir.join("misjoin.txt");
+        std::fs::write(
+            &config,
+            "# Two misjoins\nmisjoin -n 2\n-l 50 --soft-mask # masked\n",
+        )
+        .unwrap();
+        let samplesheet = dir.join("samples.tsv");
+        std::fs::write(
+            &samplesheet,
+            format!(
+                "fasta\tconfig\tseed\toutput_prefix\nHG002.fa\t{0}\t42\tout/HG002\n\nHG005.fa\t{0}\t.\tout/HG005\n",
+                config.display()
+            ),
+        )
+        .unwrap();
+
+        let samples = read_samplesheet(&samplesheet).unwrap();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0].seed, Some(42));
+        assert_eq!(samples[1].seed, None);
+
+        let cli = samples[0].cli(false, false).unwrap();
+        assert_eq!(
+            cli.command,
+            Commands::Misassembly(MisassemblyCommands::Misjoin {
+                number: 2,
+                length: 50
+            })
+        );
+        assert!(cli.soft_mask);
+        assert_eq!(cli.seed, Some(42));
+        assert_eq!(cli.infile, Some(PathBuf::from("HG002.fa")));
+        assert_eq!(cli.outfile, Some(PathBuf::from("out/HG002.fa")));
+        assert_eq!(cli.outbedfile, Some(PathBuf::from("out/HG002.bed")));
+
+        std::fs::write(
+            &samplesheet,
+            "HG002.fa\tmisjoin.txt\t42\tout/HG002\nHG005.fa\tmisjoin.txt\t.\tout/HG002\n",
+        )
+        .unwrap();
+        assert!(read_samplesheet(&samplesheet).is_err());
+        std::fs::write(&samplesheet, "HG002.fa\tmisjoin.txt\t42\n").unwrap();
+        assert!(read_samplesheet(&samplesheet).is_err());
+    }
+}
@@ -1,24 +1,25 @@
-use eyre::ContextCompat;
-use iset::IntervalSet;
-use itertools::Itertools;
 use noodles::{
     bed::{
         self,
         record::{Builder, OptionalFields},
     },
     core::Position,
-    fasta::{record::Definition, Writer},
+    fasta::record::Definition,
 };
-use std::{fs::File, io::Write};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
 
-use crate::utils::{generate_random_seq_ranges, write_misassembly};
+use crate::{
+    io::{FastaWriter, TruthWriter},
+    utils::{write_misassembly, SegmentedSequence, SequenceType},
+};
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct BrokenSequence {
-    // Start of broken sequence.
-    start: usize,
-    // End of broken sequence.
-    end: usize,
+    /// 1-based start of broken sequence.
+    pub start: usize,
+    /// 1-based, inclusive end of broken sequence.
+    pub end: usize,
 }
 
 impl From<BrokenSequence> for Builder<3> {
@@ -31,58 +32,38 @@ impl From<BrokenSequence> for Builder<3> {
     }
 }
 
-pub fn generate_breaks<'a>(
-    seq: &'a str,
-    regions: &IntervalSet<Position>,
-    number: usize,
-    seed: Option<u64>,
-) -> eyre::Result<(Vec<&'a str>, Vec<BrokenSequence>)> {
+/// Split a sequence before each sorted, 0-based break position.
+pub fn split_at_breaks(
+    seq: &[u8],
+    positions: impl IntoIterator<Item = usize>,
+) -> (Vec<&[u8]>, Vec<BrokenSequence>) {
     // Number of seqs is equal to number of breaks + 1.
     // Start (-|-|-) Stop
-    let mut seqs = Vec::with_capacity(number + 1);
+    let mut seqs = vec![];
     let mut breaks: Vec<BrokenSequence> = vec![];
-    let seq_segments = generate_random_seq_ranges(seq.len(), regions, 1, number, seed, true)?
-        .context("No sequence segments")?
-        .collect_vec();
-    let mut seq_iter = seq_segments.into_iter().peekable();
-
-    // Add starting sequence before first break.
-    if let Some((_, _, brange)) = seq_iter.peek() {
-        let segment = &seq[..brange.start];
+    let mut start = 0;
+    for pos in positions.into_iter().chain(std::iter::once(seq.len())) {
+        seqs.push(&seq[start..pos]);
         breaks.push(BrokenSequence {
-            start: 1,
-            end: brange.start + 1,
+            start: start + 1,
+            end: pos,
         });
-        seqs.push(segment);
-    };
-
-    while let Some((_, _, brange)) = seq_iter.next() {
-        if let Some((_, _, next_brange)) = seq_iter.peek() {
-            seqs.push(&seq[brange.start..next_brange.start]);
-            breaks.push(BrokenSequence {
-                start: brange.start + 1,
-                end: next_brange.start + 1,
-            })
-        } else {
-            seqs.push(&seq[brange.start..seq.len()]);
-            breaks.push(BrokenSequence {
-                start: brange.start + 1,
-                end: seq.len() + 1,
-            })
-        }
+        start = pos;
     }
-
-    Ok((seqs, breaks))
+    (seqs, breaks)
 }
 
-pub fn write_breaks<O, R, I>(
+/// Write each broken segment of a record as its own record, `{record_name}:{start}-{end}`, and to the truth bed file.
+pub fn write_breaks<O, B, R, I>(
     record_name: &str,
-    seq_region_pairs: (Vec<&str>, I),
-    writer_fa: &mut Writer<O>,
-    output_bed: &mut Option<bed::Writer<File>>,
+    description: Option<&[u8]>,
+    seq_region_pairs: (Vec<&[u8]>, I),
+    writer_fa: &mut FastaWriter<O>,
+    output_bed: &mut Option<TruthWriter<B>>,
 ) -> eyre::Result<()>
 where
     O: Write,
+    B: Write,
     R: TryInto<Builder<3>> + Clone,
     I: IntoIterator<Item = R>,
 {
@@ -92,23 +73,29 @@ where
         .zip(seq_region_pairs.1)
         .enumerate()
     {
+        let description = description.map(|desc| desc.to_vec());
         let new_definition = TryInto::<Builder<3>>::try_into(region.clone())
             .map(|b| b.set_reference_sequence_name(record_name).build())
             .map(|r| {
                 if let Ok(r) = r {
                     Definition::new(
                         format!("{record_name}:{}-{}", r.start_position(), r.end_position()),
-                        None,
+                        description.clone(),
                     )
                 } else {
-                    Definition::new(format!("{record_name}_ctg_{i}"), None)
+                    Definition::new(format!("{record_name}_ctg_{i}"), description.clone())
                 }
             })
-            .unwrap_or(Definition::new(format!("{record_name}_ctg_{i}"), None));
+            .unwrap_or(Definition::new(
+                format!("{record_name}_ctg_{i}"),
+                description.clone(),
+            ));
 
         write_misassembly(
-            seq.bytes().collect_vec(),
+            SegmentedSequence::from(seq),
+            SequenceType::Break,
             std::iter::once(region),
+            &[],
             new_definition,
             writer_fa,
             output_bed.as_mut(),
@@ -121,31 +108,53 @@ where
 #[cfg(test)]
 mod test {
     use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::utils::{place_misassemblies, Regions};
 
     #[test]
     fn test_generate_breaks() {
-        let seq = "AAAGGCCCGGCCCGGGGATTTTATTTTGGGCCGCCCAATTTAATTT";
-        let regions = IntervalSet::from_iter(std::iter::once(
+        let seq = b"AAAGGCCCGGCCCGGGGATTTTATTTTGGGCCGCCCAATTTAATTT";
+        let regions = Regions::from_iter(std::iter::once((
             Position::new(1).unwrap()..Position::new(seq.len()).unwrap(),
-        ));
+            1.0.into(),
+        )));
 
-        let (seqs, breaks) = generate_breaks(seq, &regions, 3, Some(42)).unwrap();
+        let positions = place_misassemblies(
+            seq.len(),
+            &regions,
+            1,
+            3,
+            &mut StdRng::seed_from_u64(42),
+            true,
+            1,
+            None,
+        )
+        .unwrap()
+        .into_iter()
+        .map(|range| range.start);
+        let (seqs, breaks) = split_at_breaks(seq, positions);
         assert_eq!(
             seqs,
-            ["AAAGGCCCGGCCCGGG", "GATTTTAT", "TTTGGGCCGCCCAATTTAAT", "TT"]
+            [
+                b"AAAGGCCCGGCCCGGG".as_slice(),
+                b"GATTTTAT",
+                b"TTTGGGCCGCCCAATTTAAT",
+                b"TT"
+            ]
         );
         assert_eq!(
             breaks,
             [
-                BrokenSequence { start: 1, end: 17 },
-                BrokenSequence { start: 17, end: 25 },
-                BrokenSequence { start: 25, end: 45 },
+                BrokenSequence { start: 1, end: 16 },
+                BrokenSequence { start: 17, end: 24 },
+                BrokenSequence { start: 25, end: 44 },
                 BrokenSequence {
                     start: 45,
-                    end: seq.len() + 1
+                    end: seq.len()
                 }
             ]
         );
-        assert_eq!(seqs.join(""), seq)
+        assert_eq!(seqs.concat(), seq)
     }
 }
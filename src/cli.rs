@@ -1,85 +1,554 @@
 use std::path::PathBuf;
 
-use clap::{Parser, Subcommand};
+use clap::{
+    parser::ValueSource, ArgAction, ArgMatches, CommandFactory, FromArgMatches, Parser, ValueEnum,
+};
+use log::LevelFilter;
 
-#[derive(Parser)]
+use crate::rng::RngKind;
+pub use crate::{
+    commands::{Commands, MisassemblyCommands},
+    config::{CoverageBias, OutputFormat, ShortRecordPolicy, TruthFormat, UngroupedRecords},
+};
+
+#[derive(Clone, Parser)]
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
 
-    /// Input sequence file. Uncompressed or bgzipped.
+    /// Input sequence file. Uncompressed, bgzipped, or gzipped.
+    /// Read from stdin if "-" or not provided.
     #[arg(short, long, global = true)]
     pub infile: Option<PathBuf>,
 
+    /// Memory-map the input sequence file if uncompressed instead of reading it through a buffer.
+    /// Records are sliced from the file by their faidx offsets. The file must not change while running.
+    #[arg(long, action, default_value_t = false, global = true)]
+    pub mmap: bool,
+
+    /// Faidx of the input sequence file. Defaults to {infile}.fai, generated if missing.
+    #[arg(long, global = true)]
+    pub fai: Option<PathBuf>,
+
+    /// Gzi of a bgzipped input sequence file. Defaults to {infile}.gzi.
+    #[arg(long, global = true)]
+    pub gzi: Option<PathBuf>,
+
+    /// Read the entire input sequence file into memory without reading or generating any index files.
+    /// Only for small inputs. Stdin is always read this way.
+    #[arg(
+        long,
+        action,
+        default_value_t = false,
+        global = true,
+        conflicts_with_all = ["mmap", "fai", "gzi"]
+    )]
+    pub no_index: bool,
+
     /// Input bed file. Each region should map to a sequence from infile.
+    /// Names in the 4th column are added to the output bed file as a "region=" field.
     #[arg(short = 'r', long, global = true)]
     pub inbedfile: Option<PathBuf>,
 
-    /// Output sequence file.
+    /// Sample regions of the input bed file by the weight in the 5th column or a numeric 4th column.
+    /// Otherwise, regions are sampled uniformly.
+    #[arg(
+        long,
+        action,
+        default_value_t = false,
+        global = true,
+        requires = "inbedfile"
+    )]
+    pub weighted_regions: bool,
+
+    /// Don't merge overlapping and bookended regions of the input bed file.
+    #[arg(long, action, default_value_t = false, global = true)]
+    pub no_merge_regions: bool,
+
+    /// Bed file of regions to exclude. No misassemblies are introduced within these regions.
+    #[arg(long, global = true)]
+    pub exclude_bedfile: Option<PathBuf>,
+
+    /// Bed file of regions to bias misassemblies towards. ex. RepeatMasker or SEDEF annotations.
+    #[arg(long, global = true, requires = "bias_fraction")]
+    pub bias_bedfile: Option<PathBuf>,
+
+    /// Fraction of misassemblies placed within the regions of the bias bed file.
+    /// The remainder are placed elsewhere.
+    #[arg(long, global = true, requires = "bias_bedfile")]
+    pub bias_fraction: Option<f64>,
+
+    /// Fraction of misassemblies placed within telomeric (TTAGGG)n and alpha-satellite-like repeats
+    /// detected in each record. The remainder are placed elsewhere.
+    /// Repeats are where misassemblies occur in T2T-style assemblies.
+    #[arg(long, global = true, conflicts_with = "bias_bedfile")]
+    pub repeat_bias_fraction: Option<f64>,
+
+    /// Cluster misassemblies around hotspots with this many misassemblies per hotspot.
+    /// Hotspots are randomly placed within the regions of each record.
+    #[arg(long, global = true)]
+    pub cluster_size: Option<usize>,
+
+    /// Number of bases on each side of a hotspot in which its misassemblies are placed.
+    #[arg(
+        long,
+        default_value_t = 10_000,
+        global = true,
+        requires = "cluster_size"
+    )]
+    pub cluster_spread: usize,
+
+    /// BedGraph file of coverage to bias misassemblies towards low or high coverage.
+    /// Bases not in the bedGraph have no coverage.
+    #[arg(long, global = true)]
+    pub coverage_bedgraph: Option<PathBuf>,
+
+    /// Coverage to bias misassemblies towards.
+    #[arg(long, value_enum, default_value_t = CoverageBias::Low, global = true)]
+    pub coverage_bias: CoverageBias,
+
+    /// Number of bases at each end of a record in which no misassembly starts or ends.
+    #[arg(long, default_value_t = 0, global = true)]
+    pub edge_buffer: usize,
+
+    /// Allow misassemblies within existing assembly gaps (runs of Ns).
+    #[arg(long, action, default_value_t = false, global = true)]
+    pub ignore_existing_gaps: bool,
+
+    /// Number of bases around existing assembly gaps in which no misassembly starts or ends.
+    #[arg(
+        long,
+        default_value_t = 0,
+        global = true,
+        conflicts_with = "ignore_existing_gaps"
+    )]
+    pub gap_buffer: usize,
+
+    /// Output sequence file. Bgzipped if it ends in ".gz".
     #[arg(short, long, global = true)]
     pub outfile: Option<PathBuf>,
 
+    /// Number of threads compressing a bgzipped output sequence file.
+    #[arg(long, default_value_t = 1, global = true)]
+    pub compression_threads: usize,
+
+    /// Compression level of a bgzipped output sequence file from 0 (none) to 9 (smallest).
+    /// Lower levels are faster.
+    #[arg(long, default_value_t = 6, global = true)]
+    pub compression_level: u8,
+
+    /// Output directory to write each output record to its own file, {name}.fa.
+    /// Replaces outfile.
+    #[arg(long, global = true, conflicts_with = "outfile")]
+    pub split_output_dir: Option<PathBuf>,
+
+    /// Only write misassembled records to the output sequence file.
+    #[arg(long, action, default_value_t = false, global = true)]
+    pub only_misassembled: bool,
+
+    /// Also write the original of each misassembled record with the suffix, "_original".
+    #[arg(long, action, default_value_t = false, global = true)]
+    pub keep_original: bool,
+
     /// Output BED file with misassemblies.
     #[arg(short = 'b', long, global = true)]
     pub outbedfile: Option<PathBuf>,
 
-    /// Seed to use for the random number generator.
+    /// Output bedGraph of the expected copy number of each base of the input.
+    /// 0 over deleted and gapped bases and the number of copies over duplicated bases.
+    #[arg(long, global = true)]
+    pub copy_number_bedgraph: Option<PathBuf>,
+
+    /// Output JSON file of the number of misassemblies requested and placed, bases affected, and length quantiles by record and type.
+    /// The same statistics are logged at the end of each run.
+    #[arg(long, global = true)]
+    pub stats_json: Option<PathBuf>,
+
+    /// Suffix appended to the names of misassembled records. Other records keep their name. ex. "_misasim"
+    #[arg(long, global = true)]
+    pub suffix: Option<String>,
+
+    /// TSV of input and output record names to rename output records and their misassemblies.
+    /// Records not in the map keep their name. Broken and original records keep their suffix.
+    #[arg(long, global = true)]
+    pub rename_map: Option<PathBuf>,
+
+    /// Overwrite existing output files.
+    #[arg(long, action, default_value_t = false, global = true)]
+    pub force: bool,
+
+    /// Output sequence file format.
+    /// With fastq, misassembled bases are given a low quality (Q5) and all other bases a high quality (Q40).
+    #[arg(long, value_enum, default_value_t = OutputFormat::Fasta, global = true)]
+    pub output_format: OutputFormat,
+
+    /// Number of bases per line in the output sequence file. 0 disables wrapping.
+    #[arg(long, default_value_t = 80, global = true)]
+    pub line_width: usize,
+
+    /// Write a faidx of the output sequence file to {outfile}.fai.
+    /// Bgzipped output sequence files also get a gzi, {outfile}.gzi.
+    #[arg(long, action, default_value_t = false, global = true)]
+    pub index_outfile: bool,
+
+    /// Don't write any sequences. Only write the output bed file.
+    #[arg(
+        long,
+        action,
+        default_value_t = false,
+        global = true,
+        conflicts_with_all = ["index_outfile", "split_output_dir"]
+    )]
+    pub dry_run: bool,
+
+    /// Soft-mask (lowercase) misassembled segments in the output sequence file.
+    #[arg(long, action, default_value_t = false, global = true)]
+    pub soft_mask: bool,
+
+    /// Append a note of the misassemblies introduced to the description of each misassembled record.
+    /// ex. "misasim:misjoin=2"
+    #[arg(long, action, default_value_t = false, global = true)]
+    pub annotate_description: bool,
+
+    /// Output BED file format.
+    /// * misasim - Misassembled regions in original coordinates and record names.
+    /// * nucflag - Misassembled regions in output coordinates with NucFlag categories.
+    /// * flagger - All regions in output coordinates with Flagger labels.
+    #[arg(long, value_enum, default_value_t = TruthFormat::Misasim, global = true)]
+    pub truth_format: TruthFormat,
+
+    /// Give each misassembly or diploid variant a unique ID numbered across all records in output order. ex. misasim.3
+    /// IDs are added to the output bed file as an "id=" field with "--truth-format misasim", and to the plan, statistics, and truth VCF.
+    #[arg(long, action, default_value_t = false, global = true)]
+    pub event_ids: bool,
+
+    /// Write the output bed file with a track line and records colored by type for IGV.
+    #[arg(long, action, default_value_t = false, global = true)]
+    pub igv: bool,
+
+    /// Output IGV session XML loading the output sequence file as the genome and the output bed and copy number files as tracks.
+    /// Requires an output sequence file.
+    #[arg(long, global = true)]
+    pub igv_session: Option<PathBuf>,
+
+    /// Seed to use for the random number generator. Generated and logged if not provided.
+    /// Each record is seeded by its name so results don't depend on record order.
     #[arg(short, long, global = true)]
     pub seed: Option<u64>,
 
+    /// Random number generator algorithm. Use chacha12 or xoshiro256pp to reproduce seeds across releases.
+    #[arg(long, value_enum, default_value_t = RngKind::Std, global = true)]
+    pub rng: RngKind,
+
+    /// Preset number and length of misassemblies of common benchmarking profiles.
+    /// Only fills in the number, length, and maximum duplications not given.
+    #[arg(long, value_enum, global = true)]
+    pub preset: Option<Preset>,
+
+    /// Number of independent replicates to generate from one read of the input sequence file.
+    /// Replicate i is seeded with seed + i - 1 and its output files have the suffix ".rep{i}".
+    /// ex. out.fa.gz -> out.rep1.fa.gz
+    #[arg(long, default_value_t = 1, global = true)]
+    pub replicates: usize,
+
+    /// Skip runs with complete outputs. ex. Replicates or samples of an interrupted batch run.
+    /// Completed runs write {output}.misasim.json listing their outputs, seed, and settings. Runs with other settings or seeds are rerun. Incomplete outputs are overwritten.
+    #[arg(long, action, default_value_t = false, global = true)]
+    pub resume: bool,
+
+    /// Introduce misassemblies at the exact regions of the input bed file instead of sampling them.
+    /// The type is read from the 4th column or is the command's type if absent.
+    #[arg(long, action, default_value_t = false, global = true)]
+    pub replay: bool,
+
+    /// Introduce a misassembly of the command's type at an exact 1-based region instead of sampling it. Can be repeated.
+    /// Regions without an end are a single base. ex. chr1:1000-2000 or chr1:1000
+    #[arg(long, global = true, conflicts_with = "replay")]
+    pub at: Vec<String>,
+
+    /// Misassemble every record instead of one record per group.
+    /// Only records with regions are misassembled if an input bed file is provided.
+    #[arg(long, action, default_value_t = false, global = true)]
+    pub all_records: bool,
+
+    /// Number of records per group to misassemble.
+    #[arg(
+        long,
+        default_value_t = 1,
+        global = true,
+        conflicts_with = "all_records"
+    )]
+    pub per_group: usize,
+
+    /// Proportion of records per group to misassemble, rounded to the nearest record.
+    /// ex. 0.3 for ~30% of records.
+    #[arg(long, global = true, conflicts_with_all = ["all_records", "per_group"])]
+    pub proportion: Option<f64>,
+
+    /// Only misassemble records with names matching a regex pattern. Can be repeated.
+    /// All other records are written as is. ex. "^chrX_.*$"
+    #[arg(long, global = true)]
+    pub target_records: Option<Vec<String>>,
+
+    /// Weight the choice of records to misassemble by their length.
+    #[arg(
+        long,
+        action,
+        default_value_t = false,
+        global = true,
+        conflicts_with = "all_records"
+    )]
+    pub weight_by_length: bool,
+
     /// Randomize length.
     #[arg(long, action, default_value_t = false, global = true)]
     pub randomize_length: bool,
 
+    /// Length of misassemblies as a fraction of the bases of each record's regions in the input bed file or of the record.
+    /// Replaces the length. ex. 0.1 for 5 kbp misassemblies in a 50 kbp record.
+    #[arg(long, global = true)]
+    pub length_frac: Option<f64>,
+
+    /// How records whose regions are all shorter than the misassembly length are handled.
+    #[arg(long, value_enum, default_value_t = ShortRecordPolicy::Error, global = true)]
+    pub short_record_policy: ShortRecordPolicy,
+
+    /// Minimum length of randomized misassemblies.
+    #[arg(
+        long,
+        default_value_t = 1,
+        global = true,
+        requires = "randomize_length"
+    )]
+    pub min_length: usize,
+
+    /// Maximum total bases of misassemblies over all records.
+    /// Misassemblies are shrunk if randomizing length or no more are placed once reached.
+    #[arg(long, global = true)]
+    pub max_total_bases: Option<usize>,
+
+    /// Exit with an error if a record in the input bed file isn't in the input sequence file
+    /// or a record chosen to misassemble has no regions. Otherwise, the entire record is used.
+    #[arg(
+        long,
+        action,
+        default_value_t = false,
+        global = true,
+        requires = "inbedfile"
+    )]
+    pub strict_regions: bool,
+
+    /// Exit with an error if fewer misassemblies than the number requested can be placed in a record.
+    #[arg(long, action, default_value_t = false, global = true)]
+    pub strict_count: bool,
+
+    /// Number of threads to fetch, misassemble, and write records with.
+    /// Up to this many misassembled records are held in memory at once.
+    #[arg(long, default_value_t = 1, global = true)]
+    pub threads: usize,
+
+    /// Log the number of records and bases processed every this many seconds.
+    #[arg(long, global = true)]
+    pub progress_interval: Option<u64>,
+
     /// Group by regex pattern.
     /// ex. "^.*?_(?<hap>.*?)$" with group by haplotype.
     #[arg(short, long, global = true)]
     pub group_by: Option<String>,
+
+    /// How records not matching the group by pattern are handled. Their names are logged.
+    #[arg(long, value_enum, default_value_t = UngroupedRecords::Together, global = true)]
+    pub ungrouped_records: UngroupedRecords,
+
+    /// Log level. One of off, error, warn, info, debug, or trace.
+    /// Per-record details are logged at debug.
+    #[arg(
+        long,
+        default_value_t = LevelFilter::Info,
+        global = true,
+        conflicts_with_all = ["verbose", "quiet"]
+    )]
+    pub log_level: LevelFilter,
+
+    /// Log format.
+    #[arg(long, value_enum, default_value_t = LogFormat::Text, global = true)]
+    pub log_format: LogFormat,
+
+    /// Increase the log level. Can be repeated. ex. -vv for trace
+    #[arg(short, long, action = ArgAction::Count, global = true, conflicts_with = "quiet")]
+    pub verbose: u8,
+
+    /// Decrease the log level. Can be repeated. ex. -qqq for no logging
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    pub quiet: u8,
+}
+
+impl Cli {
+    /// Parse arguments like [`Parser::try_parse_from`] and apply the preset.
+    pub fn try_parse_with_preset<I, T>(args: I) -> Result<Self, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<std::ffi::OsString> + Clone,
+    {
+        let matches = Cli::command().try_get_matches_from(args)?;
+        let mut cli = Cli::from_arg_matches(&matches)?;
+        let Some(preset) = cli.preset else {
+            return Ok(cli);
+        };
+        // Misassembly commands are nested under plan.
+        let mut sub_matches = &matches;
+        while let Some((_, matches)) = sub_matches.subcommand() {
+            sub_matches = matches;
+        }
+        let command = match &mut cli.command {
+            Commands::Misassembly(command) | Commands::Plan { command } => command,
+            _ => return Ok(cli),
+        };
+        command.apply_preset(preset, sub_matches);
+        Ok(cli)
+    }
+
+    /// Parse arguments from the command line and apply the preset. Exits on error.
+    pub fn parse_with_preset() -> Self {
+        Cli::try_parse_with_preset(std::env::args_os()).unwrap_or_else(|err| err.exit())
+    }
+
+    /// Log level adjusted by the number of verbose and quiet flags.
+    pub fn level_filter(&self) -> LevelFilter {
+        let level = (self.log_level as usize + usize::from(self.verbose))
+            .saturating_sub(usize::from(self.quiet));
+        LevelFilter::iter().nth(level).unwrap_or(LevelFilter::max())
+    }
+}
+
+/// Severity of the misassemblies of a benchmarking profile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Preset {
+    /// A few short misassemblies. ex. A polished T2T assembly.
+    Mild,
+    /// Tens of misassemblies up to tens of kbp. ex. A typical HiFi assembly.
+    Moderate,
+    /// Many misassemblies up to hundreds of kbp. ex. A low-coverage or unpolished assembly.
+    Severe,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Human-readable lines.
+    Text,
+    /// One JSON object per line with the timestamp, level, target, and message.
+    Json,
 }
 
-#[derive(Debug, PartialEq, Eq, Subcommand)]
-pub enum Commands {
-    /// Simulate a misjoin in a sequence.
-    Misjoin {
-        /// Number of misjoins to simulate.
-        #[arg(short, long, default_value_t = 1)]
-        number: usize,
-
-        /// Max length of misjoin.
-        #[arg(short, long, default_value_t = 5_000)]
-        length: usize,
-    },
-
-    /// Simulate a falsely duplicated sequence.
-    FalseDuplication {
-        /// Number of false duplications to simulate.
-        #[arg(short, long, default_value_t = 1)]
-        number: usize,
-
-        /// Max length of sequence to duplicate.
-        #[arg(short, long, default_value_t = 5_000)]
-        length: usize,
-
-        /// Maximum number of duplications for any single segment.
-        #[arg(short, long, default_value_t = 3)]
-        max_duplications: usize,
-    },
-
-    /// Simulate a gap in a sequence.
-    Gap {
-        /// Number of gaps to simulate.
-        #[arg(short, long, default_value_t = 1)]
-        number: usize,
-
-        /// Max length of gap simulate.
-        #[arg(short, long, default_value_t = 5_000)]
-        length: usize,
-    },
-
-    /// Simulate a break in a sequence.
-    Break {
-        /// Number of breaks to simulate.
-        #[arg(short, long, default_value_t = 1)]
-        number: usize,
-    },
+impl MisassemblyCommands {
+    /// Set the number, length, and maximum duplications of a preset that weren't given in `matches`.
+    fn apply_preset(&mut self, preset: Preset, matches: &ArgMatches) {
+        let is_default = |id: &str| matches.value_source(id) != Some(ValueSource::CommandLine);
+        // Number, length, and maximum duplications by preset.
+        let (preset_number, preset_length, preset_max_duplications) = match (&self, preset) {
+            (MisassemblyCommands::Misjoin { .. }, Preset::Mild) => (2, 5_000, None),
+            (MisassemblyCommands::Misjoin { .. }, Preset::Moderate) => (10, 20_000, None),
+            (MisassemblyCommands::Misjoin { .. }, Preset::Severe) => (50, 100_000, None),
+            (MisassemblyCommands::Gap { .. }, Preset::Mild) => (2, 1_000, None),
+            (MisassemblyCommands::Gap { .. }, Preset::Moderate) => (10, 10_000, None),
+            (MisassemblyCommands::Gap { .. }, Preset::Severe) => (50, 50_000, None),
+            (MisassemblyCommands::FalseDuplication { .. }, Preset::Mild) => (1, 5_000, Some(3)),
+            (MisassemblyCommands::FalseDuplication { .. }, Preset::Moderate) => {
+                (5, 20_000, Some(4))
+            }
+            (MisassemblyCommands::FalseDuplication { .. }, Preset::Severe) => {
+                (20, 100_000, Some(6))
+            }
+            (MisassemblyCommands::Break { .. }, Preset::Mild) => (2, 0, None),
+            (MisassemblyCommands::Break { .. }, Preset::Moderate) => (10, 0, None),
+            (MisassemblyCommands::Break { .. }, Preset::Severe) => (50, 0, None),
+        };
+        match self {
+            MisassemblyCommands::Misjoin { number, length }
+            | MisassemblyCommands::Gap { number, length } => {
+                if is_default("number") {
+                    *number = preset_number;
+                }
+                if is_default("length") {
+                    *length = preset_length;
+                }
+            }
+            MisassemblyCommands::FalseDuplication {
+                number,
+                length,
+                max_duplications,
+            } => {
+                if is_default("number") {
+                    *number = preset_number;
+                }
+                if is_default("length") {
+                    *length = preset_length;
+                }
+                if let (true, Some(preset_max_duplications)) =
+                    (is_default("max_duplications"), preset_max_duplications)
+                {
+                    *max_duplications = preset_max_duplications;
+                }
+            }
+            MisassemblyCommands::Break { number } => {
+                if is_default("number") {
+                    *number = preset_number;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cli() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_replay_after_command() {
+        let cli = Cli::try_parse_from([
+            "misasim", "-i", "g.fa", "-r", "x.bed", "misjoin", "--replay",
+        ])
+        .unwrap();
+        assert!(cli.replay);
+        assert_eq!(cli.inbedfile, Some(PathBuf::from("x.bed")));
+    }
+
+    #[test]
+    fn test_preset() {
+        let cli =
+            Cli::try_parse_with_preset(["misasim", "misjoin", "--preset", "moderate"]).unwrap();
+        assert_eq!(
+            cli.command,
+            Commands::Misassembly(MisassemblyCommands::Misjoin {
+                number: 10,
+                length: 20_000
+            })
+        );
+        // Given values are kept.
+        let cli = Cli::try_parse_with_preset([
+            "misasim",
+            "--preset",
+            "severe",
+            "plan",
+            "false-duplication",
+            "-n",
+            "3",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.command,
+            Commands::Plan {
+                command: MisassemblyCommands::FalseDuplication {
+                    number: 3,
+                    length: 100_000,
+                    max_duplications: 6
+                }
+            }
+        );
+    }
 }
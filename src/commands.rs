@@ -0,0 +1,305 @@
+//! Commands of a run and their misassemblies.
+
+use std::path::PathBuf;
+
+use clap::Subcommand;
+
+use crate::reads::ReadPlatform;
+
+#[derive(Debug, Clone, PartialEq, Subcommand)]
+pub enum Commands {
+    #[command(flatten)]
+    Misassembly(MisassemblyCommands),
+
+    /// Apply the DEL, INS, DUP, and INV records of a VCF file to their sequences.
+    /// Records with variants are misassembled instead of one random record per group.
+    Apply {
+        /// Input VCF file. Uncompressed or gzipped.
+        #[arg(long)]
+        vcf: PathBuf,
+    },
+
+    /// Sample misassemblies with a command and write them to stdout as a JSON plan.
+    /// No sequences are written.
+    Plan {
+        #[command(subcommand)]
+        command: MisassemblyCommands,
+    },
+
+    /// Apply a JSON plan from "misasim plan".
+    /// The original sequences and junctions of its variants must match the input sequence file.
+    Execute {
+        /// Input JSON plan.
+        #[arg(short, long)]
+        plan: PathBuf,
+    },
+
+    /// Revert misassembled records to their original sequence with the input sequence file as
+    /// the misassembled sequence file. Broken records are fused.
+    /// Misassembled records are found by their name with "--suffix" and "--rename-map".
+    Revert {
+        /// Truth BED file written with "--truth-format misasim".
+        #[arg(short, long)]
+        truth: PathBuf,
+
+        /// Deletions in the truth BED file are gaps.
+        #[arg(long, action, default_value_t = false)]
+        gaps: bool,
+    },
+
+    /// Compare misassembly calls to a truth BED file and output per-type precision and recall.
+    /// Types are read from the 4th column. ex. A truth BED file from "--truth-format nucflag".
+    Compare {
+        /// Truth BED file.
+        #[arg(short, long)]
+        truth: PathBuf,
+
+        /// Misassembly calls BED file.
+        #[arg(short, long)]
+        calls: PathBuf,
+
+        /// Bases to extend truth intervals by on each side.
+        #[arg(long, default_value_t = 0)]
+        slop: usize,
+
+        /// Minimum number of overlapping bases for a call to match a truth interval.
+        #[arg(long, default_value_t = 1)]
+        min_overlap_bp: usize,
+
+        /// Minimum fraction of both the call and truth interval overlapped.
+        #[arg(long, default_value_t = 0.0)]
+        reciprocal_overlap: f64,
+
+        /// Require calls and truth intervals to have the same type.
+        #[arg(long, action, default_value_t = false)]
+        match_type: bool,
+    },
+
+    /// Lift BED or GFF annotations from the original records onto the misassembled records.
+    /// Features within deletions are dropped and features spanning deletions or breaks are truncated.
+    /// Features are written on the record names from "--suffix" and "--rename-map".
+    Liftover {
+        /// Truth BED file written with "--truth-format misasim".
+        #[arg(short, long)]
+        truth: PathBuf,
+
+        /// Annotations BED or GFF file. GFF files are detected by their extension.
+        #[arg(short, long)]
+        annotations: PathBuf,
+
+        /// Deletions in the truth BED file are gaps.
+        #[arg(long, action, default_value_t = false)]
+        gaps: bool,
+    },
+
+    /// Simulate a diploid genome from a haploid input sequence file with phased heterozygous SNVs and small indels.
+    /// Each record is written to both haplotype files with the suffix "_hap1" or "_hap2".
+    Diploid {
+        /// Output sequence file of haplotype 1. Bgzipped if it ends in ".gz".
+        #[arg(long)]
+        hap1: PathBuf,
+
+        /// Output sequence file of haplotype 2. Bgzipped if it ends in ".gz".
+        #[arg(long)]
+        hap2: PathBuf,
+
+        /// Output truth VCF file of the phased variants in original coordinates.
+        #[arg(long)]
+        truth_vcf: Option<PathBuf>,
+
+        /// Fraction of bases with a heterozygous variant.
+        #[arg(long, default_value_t = 0.001)]
+        heterozygosity: f64,
+
+        /// Fraction of variants that are indels. The rest are SNVs.
+        #[arg(long, default_value_t = 0.1)]
+        indel_fraction: f64,
+
+        /// Maximum length of indels.
+        #[arg(long, default_value_t = 10)]
+        max_indel_length: usize,
+    },
+
+    /// Generate a random genome with embedded tandem repeat arrays and segmental duplications.
+    /// Contigs are written to the output sequence file and their repeats to the output bed file.
+    /// No input sequence file is read.
+    Generate {
+        /// Number of contigs. Named "contig_{i}".
+        #[arg(long, default_value_t = 1)]
+        contigs: usize,
+
+        /// Length of each contig.
+        #[arg(long, default_value_t = 1_000_000)]
+        contig_length: usize,
+
+        /// Minimum length of each contig. Contig lengths are uniform between this and the length if given.
+        #[arg(long)]
+        min_contig_length: Option<usize>,
+
+        /// Fraction of bases that are G or C.
+        #[arg(long, default_value_t = 0.41)]
+        gc: f64,
+
+        /// Number of tandem repeat arrays per contig.
+        #[arg(long, default_value_t = 0)]
+        tandem_repeats: usize,
+
+        /// Length of each tandem repeat array.
+        #[arg(long, default_value_t = 20_000)]
+        tandem_repeat_length: usize,
+
+        /// Length of the repeat unit of tandem repeat arrays. ex. 171 for alpha-satellite-like arrays.
+        #[arg(long, default_value_t = 171)]
+        tandem_repeat_unit_length: usize,
+
+        /// Fraction of bases changed in each copy of a tandem repeat unit.
+        #[arg(long, default_value_t = 0.02)]
+        tandem_repeat_divergence: f64,
+
+        /// Number of segmental duplications per contig.
+        #[arg(long, default_value_t = 0)]
+        segmental_duplications: usize,
+
+        /// Length of each segmental duplication.
+        #[arg(long, default_value_t = 10_000)]
+        segmental_duplication_length: usize,
+
+        /// Fraction of bases changed in the copy of a segmental duplication.
+        #[arg(long, default_value_t = 0.01)]
+        segmental_duplication_divergence: f64,
+    },
+
+    /// Misassemble the paths of a GFA assembly graph. Breaks remove links, misjoins bypass segments,
+    /// and false duplications duplicate segments. Writes the misassembled graph, its linearized paths
+    /// to the output sequence file, and the misassemblies in original path coordinates to the output bed file.
+    Gfa {
+        /// Input GFA file with paths.
+        #[arg(long)]
+        gfa: PathBuf,
+
+        /// Output misassembled GFA file.
+        #[arg(long)]
+        out_gfa: PathBuf,
+
+        /// Number of breaks.
+        #[arg(long, default_value_t = 0)]
+        breaks: usize,
+
+        /// Number of misjoins.
+        #[arg(long, default_value_t = 1)]
+        misjoins: usize,
+
+        /// Number of false duplications.
+        #[arg(long, default_value_t = 0)]
+        false_duplications: usize,
+    },
+
+    /// Simulate long reads from the input sequence file with a simple length and error model.
+    /// ex. Reads of a misassembled sequence file. Reads are written as fastq to the output sequence file.
+    Reads {
+        /// Platform of the default read length and error rate.
+        #[arg(long, value_enum, default_value_t = ReadPlatform::Hifi)]
+        platform: ReadPlatform,
+
+        /// Mean depth of coverage of each record.
+        #[arg(long, default_value_t = 30.0)]
+        coverage: f64,
+
+        /// Mean read length. Read lengths are log-normally distributed.
+        /// Defaults to 15 kbp for hifi and 20 kbp for ont.
+        #[arg(long)]
+        mean_read_length: Option<usize>,
+
+        /// Standard deviation of read lengths. Defaults to 3 kbp for hifi and 15 kbp for ont.
+        #[arg(long)]
+        read_length_sd: Option<usize>,
+
+        /// Fraction of bases with an error, split equally between substitutions, insertions, and deletions.
+        /// Defaults to 0.001 for hifi and 0.05 for ont.
+        #[arg(long)]
+        error_rate: Option<f64>,
+    },
+
+    /// Misassemble each sample of a TSV samplesheet with the columns: fasta, config, seed, and output prefix.
+    /// Configs are files of misasim arguments. ex. "misjoin -n 5 -l 5000 --soft-mask"
+    /// Outputs are written to {prefix}.fa and {prefix}.bed. Empty or "." seeds are generated.
+    Batch {
+        /// Input samplesheet.
+        #[arg(long)]
+        samplesheet: PathBuf,
+
+        /// Misassemble samples in parallel.
+        #[arg(long, action, default_value_t = false)]
+        parallel: bool,
+    },
+}
+
+// Commands simulating random misassemblies.
+#[derive(Debug, Clone, PartialEq, Subcommand)]
+pub enum MisassemblyCommands {
+    /// Simulate a misjoin in a sequence.
+    Misjoin {
+        /// Number of misjoins to simulate.
+        #[arg(short, long, default_value_t = 1)]
+        number: usize,
+
+        /// Max length of misjoin.
+        #[arg(short, long, default_value_t = 5_000)]
+        length: usize,
+    },
+
+    /// Simulate a falsely duplicated sequence.
+    FalseDuplication {
+        /// Number of false duplications to simulate.
+        #[arg(short, long, default_value_t = 1)]
+        number: usize,
+
+        /// Max length of sequence to duplicate.
+        #[arg(short, long, default_value_t = 5_000)]
+        length: usize,
+
+        /// Maximum number of duplications for any single segment. Must be at least 3.
+        #[arg(short, long, default_value_t = 3)]
+        max_duplications: usize,
+    },
+
+    /// Simulate a gap in a sequence.
+    Gap {
+        /// Number of gaps to simulate.
+        #[arg(short, long, default_value_t = 1)]
+        number: usize,
+
+        /// Max length of gap simulate.
+        #[arg(short, long, default_value_t = 5_000)]
+        length: usize,
+    },
+
+    /// Simulate a break in a sequence.
+    Break {
+        /// Number of breaks to simulate.
+        #[arg(short, long, default_value_t = 1)]
+        number: usize,
+    },
+}
+
+impl MisassemblyCommands {
+    /// Number of misassemblies to simulate.
+    pub fn number(&self) -> usize {
+        match self {
+            MisassemblyCommands::Misjoin { number, .. }
+            | MisassemblyCommands::FalseDuplication { number, .. }
+            | MisassemblyCommands::Gap { number, .. }
+            | MisassemblyCommands::Break { number } => *number,
+        }
+    }
+
+    /// Maximum length of misassemblies. Breaks have no length.
+    pub fn length(&self) -> Option<usize> {
+        match self {
+            MisassemblyCommands::Misjoin { length, .. }
+            | MisassemblyCommands::FalseDuplication { length, .. }
+            | MisassemblyCommands::Gap { length, .. } => Some(*length),
+            MisassemblyCommands::Break { .. } => None,
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::Write,
+    path::Path,
+};
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::io::read_bed_records;
+
+/// Label for statistics over all types.
+const ALL_TYPES: &str = "all";
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Annotation {
+    pub chrom: String,
+    /// 0-based start.
+    pub start: usize,
+    pub end: usize,
+    /// Type of misassembly. ex. misjoin
+    pub mtype: String,
+}
+
+/// Criteria for a call to match a truth interval.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct OverlapCriteria {
+    /// Bases to extend truth intervals by on each side.
+    pub slop: usize,
+    /// Minimum number of overlapping bases.
+    pub min_overlap_bp: usize,
+    /// Minimum fraction of both intervals overlapped.
+    pub reciprocal_overlap: f64,
+    /// Require the same misassembly type.
+    pub match_type: bool,
+}
+
+impl OverlapCriteria {
+    fn is_match(&self, truth: &Annotation, call: &Annotation) -> bool {
+        if truth.chrom != call.chrom || (self.match_type && truth.mtype != call.mtype) {
+            return false;
+        }
+        let (truth_start, truth_end) =
+            (truth.start.saturating_sub(self.slop), truth.end + self.slop);
+        let overlap = truth_end
+            .min(call.end)
+            .saturating_sub(truth_start.max(call.start));
+        if overlap == 0 || overlap < self.min_overlap_bp {
+            return false;
+        }
+        let truth_frac = overlap as f64 / (truth_end - truth_start).max(1) as f64;
+        let call_frac = overlap as f64 / (call.end - call.start).max(1) as f64;
+        truth_frac.min(call_frac) >= self.reciprocal_overlap
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct TypeStats {
+    pub mtype: String,
+    /// Number of truth intervals.
+    pub truth: usize,
+    /// Number of calls.
+    pub calls: usize,
+    /// Number of truth intervals matched by a call.
+    pub truth_matched: usize,
+    /// Number of calls matching a truth interval.
+    pub calls_matched: usize,
+}
+
+impl TypeStats {
+    pub fn recall(&self) -> Option<f64> {
+        (self.truth != 0).then(|| self.truth_matched as f64 / self.truth as f64)
+    }
+
+    pub fn precision(&self) -> Option<f64> {
+        (self.calls != 0).then(|| self.calls_matched as f64 / self.calls as f64)
+    }
+}
+
+/// Read intervals from a BED file with the misassembly type in the 4th column.
+pub fn read_annotations(bed: impl AsRef<Path>) -> eyre::Result<Vec<Annotation>> {
+    let mut annotations = vec![];
+    for rec in read_bed_records(bed)? {
+        let rec = rec?;
+        annotations.push(Annotation {
+            chrom: rec.reference_sequence_name().to_owned(),
+            start: usize::from(rec.start_position()) - 1,
+            end: rec.end_position().into(),
+            mtype: rec.optional_fields().first().cloned().unwrap_or_default(),
+        });
+    }
+    Ok(annotations)
+}
+
+/// Compute per-type precision and recall of calls against a truth set.
+pub fn compare(
+    truth: &[Annotation],
+    calls: &[Annotation],
+    criteria: OverlapCriteria,
+) -> Vec<TypeStats> {
+    let calls_by_chrom: HashMap<&str, Vec<&Annotation>> =
+        calls.iter().into_group_map_by(|call| call.chrom.as_str());
+    let truth_by_chrom: HashMap<&str, Vec<&Annotation>> =
+        truth.iter().into_group_map_by(|truth| truth.chrom.as_str());
+
+    let mut stats: HashMap<&str, TypeStats> = HashMap::new();
+    let types: BTreeSet<&str> = truth
+        .iter()
+        .chain(calls.iter())
+        .map(|a| a.mtype.as_str())
+        .chain(std::iter::once(ALL_TYPES))
+        .collect();
+    for mtype in types {
+        stats.insert(
+            mtype,
+            TypeStats {
+                mtype: mtype.to_owned(),
+                truth: 0,
+                calls: 0,
+                truth_matched: 0,
+                calls_matched: 0,
+            },
+        );
+    }
+
+    for t in truth {
+        let is_matched = calls_by_chrom
+            .get(t.chrom.as_str())
+            .is_some_and(|calls| calls.iter().any(|c| criteria.is_match(t, c)));
+        for mtype in [t.mtype.as_str(), ALL_TYPES] {
+            let type_stats = stats.get_mut(mtype).unwrap();
+            type_stats.truth += 1;
+            type_stats.truth_matched += usize::from(is_matched);
+        }
+    }
+    for c in calls {
+        let is_matched = truth_by_chrom
+            .get(c.chrom.as_str())
+            .is_some_and(|truth| truth.iter().any(|t| criteria.is_match(t, c)));
+        for mtype in [c.mtype.as_str(), ALL_TYPES] {
+            let type_stats = stats.get_mut(mtype).unwrap();
+            type_stats.calls += 1;
+            type_stats.calls_matched += usize::from(is_matched);
+        }
+    }
+
+    stats
+        .into_values()
+        .sorted_by(|a, b| {
+            (a.mtype == ALL_TYPES)
+                .cmp(&(b.mtype == ALL_TYPES))
+                .then(a.mtype.cmp(&b.mtype))
+        })
+        .collect()
+}
+
+/// Write statistics as a TSV. Undefined precision or recall is written as NA.
+pub fn write_stats(stats: &[TypeStats], mut writer: impl Write) -> eyre::Result<()> {
+    let fmt_ratio = |ratio: Option<f64>| ratio.map_or("NA".to_owned(), |r| format!("{r:.4}"));
+    writeln!(
+        writer,
+        "type\ttruth\tcalls\ttruth_matched\tcalls_matched\trecall\tprecision"
+    )?;
+    for s in stats {
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            s.mtype,
+            s.truth,
+            s.calls,
+            s.truth_matched,
+            s.calls_matched,
+            fmt_ratio(s.recall()),
+            fmt_ratio(s.precision())
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn annot(chrom: &str, start: usize, end: usize, mtype: &str) -> Annotation {
+        Annotation {
+            chrom: chrom.to_owned(),
+            start,
+            end,
+            mtype: mtype.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_compare() {
+        let truth = [
+            annot("chr1", 100, 102, "misjoin"),
+            annot("chr1", 500, 1000, "false_dupe"),
+            annot("chr2", 10, 20, "misjoin"),
+        ];
+        let calls = [
+            annot("chr1", 90, 95, "misjoin"),
+            annot("chr1", 600, 700, "collapse"),
+            annot("chr2", 5000, 6000, "misjoin"),
+        ];
+        let criteria = OverlapCriteria {
+            slop: 10,
+            min_overlap_bp: 1,
+            reciprocal_overlap: 0.0,
+            match_type: false,
+        };
+        let stats = compare(&truth, &calls, criteria);
+        let as_tuple = |s: &TypeStats| {
+            (
+                s.mtype.clone(),
+                s.truth,
+                s.calls,
+                s.truth_matched,
+                s.calls_matched,
+            )
+        };
+        assert_eq!(
+            stats.iter().map(as_tuple).collect_vec(),
+            [
+                ("collapse".to_owned(), 0, 1, 0, 1),
+                ("false_dupe".to_owned(), 1, 0, 1, 0),
+                ("misjoin".to_owned(), 2, 2, 1, 1),
+                ("all".to_owned(), 3, 3, 2, 2),
+            ]
+        );
+
+        let stats = compare(
+            &truth,
+            &calls,
+            OverlapCriteria {
+                match_type: true,
+                ..criteria
+            },
+        );
+        assert_eq!(
+            stats.iter().map(as_tuple).collect_vec(),
+            [
+                ("collapse".to_owned(), 0, 1, 0, 0),
+                ("false_dupe".to_owned(), 1, 0, 0, 0),
+                ("misjoin".to_owned(), 2, 2, 1, 1),
+                ("all".to_owned(), 3, 3, 1, 1),
+            ]
+        );
+    }
+}
@@ -0,0 +1,248 @@
+//! Plain configuration of a run, independent of the command-line parser.
+//!
+//! The command-line tool builds a [`Config`] from its arguments. Library users build one directly.
+
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+
+use crate::{commands::Commands, rng::RngKind};
+
+/// Configuration of a run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub command: Commands,
+    pub input: InputConfig,
+    pub selection: SelectionConfig,
+    pub placement: PlacementConfig,
+    pub output: OutputConfig,
+    /// Random seed. Generated if `None`.
+    pub seed: Option<u64>,
+    pub rng: RngKind,
+    /// Number of replicates, each with the next seed and numbered outputs.
+    pub replicates: usize,
+    /// Skip completed replicates and rerun incomplete ones.
+    pub resume: bool,
+    /// Number of records fetched, misassembled, and written in parallel.
+    pub threads: usize,
+    /// Seconds between progress logs.
+    pub progress_interval: Option<u64>,
+}
+
+impl Config {
+    /// Configuration of a command with the defaults of the command-line tool.
+    pub fn new(command: Commands) -> Self {
+        Self {
+            command,
+            input: InputConfig::default(),
+            selection: SelectionConfig::default(),
+            placement: PlacementConfig::default(),
+            output: OutputConfig::default(),
+            seed: None,
+            rng: RngKind::Std,
+            replicates: 1,
+            resume: false,
+            threads: 1,
+            progress_interval: None,
+        }
+    }
+}
+
+/// Input sequence and region files.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InputConfig {
+    /// Input sequence file. Read from stdin if `None` or "-".
+    pub infile: Option<PathBuf>,
+    pub mmap: bool,
+    pub fai: Option<PathBuf>,
+    pub gzi: Option<PathBuf>,
+    /// Read the input sequence file into memory instead of indexing it.
+    pub no_index: bool,
+    /// Regions to place misassemblies in or, if replaying, exact misassemblies.
+    pub inbedfile: Option<PathBuf>,
+    /// Weight regions by their name or score.
+    pub weighted_regions: bool,
+    pub no_merge_regions: bool,
+    pub exclude_bedfile: Option<PathBuf>,
+    pub bias_bedfile: Option<PathBuf>,
+    pub coverage_bedgraph: Option<PathBuf>,
+    /// Replay the misassemblies of the input bed file.
+    pub replay: bool,
+    /// Exact regions to misassemble. ex. "chr1:100-200"
+    pub at: Vec<String>,
+}
+
+/// How records are grouped and chosen to misassemble.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelectionConfig {
+    pub all_records: bool,
+    /// Number of records to misassemble per group.
+    pub per_group: usize,
+    /// Proportion of records to misassemble per group. Replaces `per_group`.
+    pub proportion: Option<f64>,
+    /// Patterns of the only records that can be misassembled.
+    pub target_records: Option<Vec<String>>,
+    pub weight_by_length: bool,
+    /// Pattern whose captures group records.
+    pub group_by: Option<String>,
+    pub ungrouped_records: UngroupedRecords,
+}
+
+impl Default for SelectionConfig {
+    fn default() -> Self {
+        Self {
+            all_records: false,
+            per_group: 1,
+            proportion: None,
+            target_records: None,
+            weight_by_length: false,
+            group_by: None,
+            ungrouped_records: UngroupedRecords::Together,
+        }
+    }
+}
+
+/// Where and how long misassemblies are placed within records.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlacementConfig {
+    pub randomize_length: bool,
+    pub min_length: usize,
+    /// Fraction of the bases of a record's regions to use as the length.
+    pub length_frac: Option<f64>,
+    pub short_record_policy: ShortRecordPolicy,
+    /// Bases to misassemble over all records.
+    pub max_total_bases: Option<usize>,
+    pub strict_regions: bool,
+    pub strict_count: bool,
+    pub bias_fraction: Option<f64>,
+    pub repeat_bias_fraction: Option<f64>,
+    pub cluster_size: Option<usize>,
+    pub cluster_spread: usize,
+    pub coverage_bias: CoverageBias,
+    pub edge_buffer: usize,
+    pub ignore_existing_gaps: bool,
+    pub gap_buffer: usize,
+}
+
+impl Default for PlacementConfig {
+    fn default() -> Self {
+        Self {
+            randomize_length: false,
+            min_length: 1,
+            length_frac: None,
+            short_record_policy: ShortRecordPolicy::Error,
+            max_total_bases: None,
+            strict_regions: false,
+            strict_count: false,
+            bias_fraction: None,
+            repeat_bias_fraction: None,
+            cluster_size: None,
+            cluster_spread: 10_000,
+            coverage_bias: CoverageBias::Low,
+            edge_buffer: 0,
+            ignore_existing_gaps: false,
+            gap_buffer: 0,
+        }
+    }
+}
+
+/// Output files and how records are written to them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputConfig {
+    /// Output sequence file. Written to stdout if `None`.
+    pub outfile: Option<PathBuf>,
+    pub compression_threads: usize,
+    pub compression_level: u8,
+    pub split_output_dir: Option<PathBuf>,
+    pub only_misassembled: bool,
+    pub keep_original: bool,
+    pub outbedfile: Option<PathBuf>,
+    pub copy_number_bedgraph: Option<PathBuf>,
+    pub stats_json: Option<PathBuf>,
+    /// Suffix added to the names of misassembled records.
+    pub suffix: Option<String>,
+    pub rename_map: Option<PathBuf>,
+    /// Overwrite existing output files.
+    pub force: bool,
+    pub output_format: OutputFormat,
+    pub line_width: usize,
+    pub index_outfile: bool,
+    /// Only write the output bed file.
+    pub dry_run: bool,
+    pub soft_mask: bool,
+    pub annotate_description: bool,
+    pub truth_format: TruthFormat,
+    pub event_ids: bool,
+    pub igv: bool,
+    pub igv_session: Option<PathBuf>,
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        Self {
+            outfile: None,
+            compression_threads: 1,
+            compression_level: 6,
+            split_output_dir: None,
+            only_misassembled: false,
+            keep_original: false,
+            outbedfile: None,
+            copy_number_bedgraph: None,
+            stats_json: None,
+            suffix: None,
+            rename_map: None,
+            force: false,
+            output_format: OutputFormat::Fasta,
+            line_width: 80,
+            index_outfile: false,
+            dry_run: false,
+            soft_mask: false,
+            annotate_description: false,
+            truth_format: TruthFormat::Misasim,
+            event_ids: false,
+            igv: false,
+            igv_session: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Fasta,
+    Fastq,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TruthFormat {
+    Misasim,
+    Nucflag,
+    Flagger,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ShortRecordPolicy {
+    /// Write the record as is.
+    Skip,
+    /// Shrink misassemblies to fit the longest region.
+    Shrink,
+    /// Exit with an error.
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum UngroupedRecords {
+    /// Group adjacent, by name, records together.
+    Together,
+    /// Write records as is.
+    Passthrough,
+    /// Put each record in its own group.
+    Separate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CoverageBias {
+    /// Favor low coverage.
+    Low,
+    /// Favor high coverage.
+    High,
+}
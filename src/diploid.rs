@@ -0,0 +1,286 @@
+use std::io::Write;
+
+use noodles::fasta::{
+    self,
+    record::{Definition, Sequence},
+};
+use rand::{seq::SliceRandom, Rng};
+
+use crate::{
+    apply::EventIds,
+    io::{Fasta, FastaWriter},
+    rng::{RngKind, SimRng},
+    utils::record_seed,
+};
+
+/// Suffixes of the records of each haplotype.
+pub const HAPLOTYPE_SUFFIXES: [&str; 2] = ["_hap1", "_hap2"];
+
+const BASES: &[u8; 4] = b"ACGT";
+
+/// Options of [`generate_het_variants`].
+#[derive(Debug, Clone, Copy)]
+pub struct HeterozygosityConfig {
+    /// Fraction of bases with a heterozygous variant.
+    pub heterozygosity: f64,
+    /// Fraction of variants that are indels.
+    pub indel_fraction: f64,
+    pub max_indel_length: usize,
+}
+
+/// Heterozygous variant on one haplotype.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HetVariant {
+    /// 0-based position of the reference allele.
+    pub pos: usize,
+    pub ref_allele: Vec<u8>,
+    /// Alternate allele. Indels share the first base of the reference allele.
+    pub alt_allele: Vec<u8>,
+    /// Haplotype with the alternate allele. 0 for hap1 and 1 for hap2.
+    pub haplotype: usize,
+    /// Unique ID of the variant written to the VCF ID field, if any. ex. misasim.3
+    pub id: Option<String>,
+}
+
+/// Generate sorted, non-overlapping heterozygous SNVs and indels.
+/// Variants are spaced geometrically so each base has a `heterozygosity` chance of starting one.
+/// Variants are never placed at or over ambiguous bases like N.
+pub fn generate_het_variants(
+    seq: &[u8],
+    config: HeterozygosityConfig,
+    rng: &mut impl Rng,
+) -> Vec<HetVariant> {
+    let mut variants = vec![];
+    if config.heterozygosity <= 0.0 {
+        return variants;
+    }
+    let is_base = |base: &u8| BASES.contains(&base.to_ascii_uppercase());
+    let mut pos: usize = 0;
+    loop {
+        // Number of bases skipped before the next variant.
+        let skipped = if config.heterozygosity >= 1.0 {
+            0.0
+        } else {
+            (1.0 - rng.gen::<f64>()).ln() / (1.0 - config.heterozygosity).ln()
+        };
+        pos = pos.saturating_add(skipped as usize);
+        let Some(base) = seq.get(pos).copied() else {
+            break;
+        };
+        if !is_base(&base) {
+            pos += 1;
+            continue;
+        }
+        let haplotype = usize::from(rng.gen_bool(0.5));
+        let is_indel = config.max_indel_length > 0 && rng.gen_bool(config.indel_fraction);
+        let (ref_allele, alt_allele) = if !is_indel {
+            let alt = *BASES
+                .iter()
+                .filter(|alt| **alt != base.to_ascii_uppercase())
+                .collect::<Vec<_>>()
+                .choose(rng)
+                .unwrap();
+            let alt = if base.is_ascii_lowercase() {
+                alt.to_ascii_lowercase()
+            } else {
+                *alt
+            };
+            (vec![base], vec![alt])
+        } else {
+            let length = rng.gen_range(1..=config.max_indel_length);
+            if rng.gen_bool(0.5) {
+                let inserted = (0..length).map(|_| *BASES.choose(rng).unwrap());
+                (vec![base], std::iter::once(base).chain(inserted).collect())
+            } else {
+                match seq.get(pos..=pos + length) {
+                    Some(deleted) if deleted.iter().all(is_base) => (deleted.to_vec(), vec![base]),
+                    // Skip deletions over ambiguous bases or the end of the record.
+                    _ => {
+                        pos += 1;
+                        continue;
+                    }
+                }
+            }
+        };
+        let ref_len = ref_allele.len();
+        variants.push(HetVariant {
+            pos,
+            ref_allele,
+            alt_allele,
+            haplotype,
+            id: None,
+        });
+        // Leave a base between variants so they never share a padding base.
+        pos += ref_len + 1;
+    }
+    variants
+}
+
+/// Apply the variants of a haplotype to a sequence.
+pub fn apply_het_variants(seq: &[u8], variants: &[HetVariant], haplotype: usize) -> Vec<u8> {
+    let mut new_seq = Vec::with_capacity(seq.len());
+    let mut pos: usize = 0;
+    for variant in variants.iter().filter(|v| v.haplotype == haplotype) {
+        new_seq.extend(&seq[pos..variant.pos]);
+        new_seq.extend(&variant.alt_allele);
+        pos = variant.pos + variant.ref_allele.len();
+    }
+    new_seq.extend(&seq[pos..]);
+    new_seq
+}
+
+/// Write the header of a truth VCF with a single sample.
+pub fn write_vcf_header(mut writer: impl Write, lengths: &[(String, u64)]) -> eyre::Result<()> {
+    writeln!(writer, "##fileformat=VCFv4.2")?;
+    writeln!(writer, "##source=misasim")?;
+    for (name, length) in lengths {
+        writeln!(writer, "##contig=<ID={name},length={length}>")?;
+    }
+    writeln!(
+        writer,
+        "##FORMAT=<ID=GT,Number=1,Type=String,Description=\"Phased genotype. Haplotype 1|haplotype 2\">"
+    )?;
+    writeln!(
+        writer,
+        "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\tFORMAT\tSAMPLE"
+    )?;
+    Ok(())
+}
+
+/// Write variants of a record as phased VCF records in original coordinates.
+pub fn write_vcf_records(
+    mut writer: impl Write,
+    name: &str,
+    variants: &[HetVariant],
+) -> eyre::Result<()> {
+    for variant in variants {
+        let genotype = if variant.haplotype == 0 { "1|0" } else { "0|1" };
+        writeln!(
+            writer,
+            "{name}\t{}\t{}\t{}\t{}\t.\tPASS\t.\tGT\t{genotype}",
+            variant.pos + 1,
+            variant.id.as_deref().unwrap_or("."),
+            String::from_utf8_lossy(&variant.ref_allele).to_ascii_uppercase(),
+            String::from_utf8_lossy(&variant.alt_allele).to_ascii_uppercase(),
+        )?;
+    }
+    Ok(())
+}
+
+/// Write both haplotypes of each record of `reader_fa` and optionally a truth VCF.
+/// Each record is seeded by its name from `seed`.
+/// Variants are given unique IDs numbered across all records if `event_ids`.
+pub fn simulate_diploid<W: Write>(
+    reader_fa: &mut Fasta,
+    config: HeterozygosityConfig,
+    seed: u64,
+    rng_kind: RngKind,
+    writers_fa: &mut [FastaWriter<W>; 2],
+    mut writer_vcf: Option<&mut dyn Write>,
+    event_ids: bool,
+) -> eyre::Result<()> {
+    let mut ids = event_ids.then(EventIds::default);
+    let lengths = reader_fa.lengths();
+    if let Some(writer_vcf) = writer_vcf.as_mut() {
+        write_vcf_header(writer_vcf, &lengths)?;
+    }
+    for (name, length) in lengths {
+        let record = reader_fa.fetch(&name, 1, length.try_into()?)?;
+        let seq = record.sequence().as_ref();
+        let mut rng = SimRng::new(rng_kind, record_seed(seed, &name));
+        let mut variants = generate_het_variants(seq, config, &mut rng);
+        if let Some(ids) = ids.as_mut() {
+            for variant in variants.iter_mut() {
+                variant.id = Some(ids.next_id());
+            }
+        }
+        log::debug!(
+            "Added {} heterozygous variant(s) to {name:?}.",
+            variants.len()
+        );
+
+        for (haplotype, writer_fa) in writers_fa.iter_mut().enumerate() {
+            let hap_record = fasta::Record::new(
+                Definition::new(
+                    format!("{name}{}", HAPLOTYPE_SUFFIXES[haplotype]),
+                    record.description().map(<[u8]>::to_vec),
+                ),
+                Sequence::from(apply_het_variants(seq, &variants, haplotype)),
+            );
+            writer_fa.write_record(&hap_record)?;
+        }
+        if let Some(writer_vcf) = writer_vcf.as_mut() {
+            write_vcf_records(writer_vcf, &name, &variants)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn test_het_variants() {
+        let seq = b"ACGTACGTACgtacgtNNNNACGTACGTACGTACGTACGTACGT".repeat(50);
+        let config = HeterozygosityConfig {
+            heterozygosity: 0.1,
+            indel_fraction: 0.5,
+            max_indel_length: 3,
+        };
+        let variants = generate_het_variants(&seq, config, &mut StdRng::seed_from_u64(42));
+        assert!(!variants.is_empty());
+        for (variant, next) in variants.iter().zip(variants.iter().skip(1)) {
+            assert!(variant.pos + variant.ref_allele.len() < next.pos);
+        }
+        for variant in variants.iter() {
+            assert_eq!(
+                &seq[variant.pos..variant.pos + variant.ref_allele.len()],
+                variant.ref_allele
+            );
+            assert!(!variant.ref_allele.contains(&b'N'));
+            assert_ne!(variant.ref_allele, variant.alt_allele);
+        }
+
+        // Each haplotype differs from the original by only its own variants.
+        for haplotype in [0, 1] {
+            let hap_seq = apply_het_variants(&seq, &variants, haplotype);
+            let length_change: isize = variants
+                .iter()
+                .filter(|v| v.haplotype == haplotype)
+                .map(|v| v.alt_allele.len() as isize - v.ref_allele.len() as isize)
+                .sum();
+            assert_eq!(hap_seq.len() as isize, seq.len() as isize + length_change);
+        }
+    }
+
+    #[test]
+    fn test_write_vcf_records() {
+        let variants = [
+            HetVariant {
+                pos: 0,
+                ref_allele: b"a".to_vec(),
+                alt_allele: b"g".to_vec(),
+                haplotype: 0,
+                id: None,
+            },
+            HetVariant {
+                pos: 4,
+                ref_allele: b"ACG".to_vec(),
+                alt_allele: b"A".to_vec(),
+                haplotype: 1,
+                id: Some("misasim.1".to_owned()),
+            },
+        ];
+        assert_eq!(apply_het_variants(b"aCGTACGT", &variants, 0), b"gCGTACGT");
+        assert_eq!(apply_het_variants(b"aCGTACGT", &variants, 1), b"aCGTAT");
+        let mut vcf = vec![];
+        write_vcf_records(&mut vcf, "chr1", &variants).unwrap();
+        assert_eq!(
+            String::from_utf8(vcf).unwrap(),
+            "chr1\t1\t.\tA\tG\t.\tPASS\t.\tGT\t1|0\nchr1\t5\tmisasim.1\tACG\tA\t.\tPASS\t.\tGT\t0|1\n"
+        );
+    }
+}
@@ -1,6 +1,6 @@
-use eyre::ContextCompat;
-use iset::IntervalSet;
-use itertools::Itertools;
+use std::{borrow::Cow, ops::Range};
+
+use eyre::bail;
 use noodles::{
     bed::{
         self,
@@ -8,21 +8,23 @@ use noodles::{
     },
     core::Position,
 };
-use rand::{rngs::StdRng, seq::IteratorRandom, SeedableRng};
+use rand::{seq::IteratorRandom, Rng};
 
-use crate::utils::generate_random_seq_ranges;
+use crate::utils::SegmentedSequence;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
-pub struct DuplicateSequence {
+pub struct DuplicateSequence<'a> {
     /// The duplicated sequence.
-    pub seq: String,
+    pub seq: SegmentedSequence<'a>,
     /// The duplicated segments.
     pub duplicated_seqs: Vec<Repeat>,
+    /// Ranges of misassembled bases in the new sequence.
+    pub misassembled_ranges: Vec<Range<usize>>,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Hash)]
 pub struct Repeat {
-    pub seq: String,
+    pub seq: Vec<u8>,
     pub start: usize,
     pub count: usize,
 }
@@ -30,89 +32,227 @@ pub struct Repeat {
 impl From<Repeat> for Builder<3> {
     fn from(rp: Repeat) -> Self {
         bed::Record::<3>::builder()
-            .set_start_position(Position::new(rp.start.clamp(1, usize::MAX)).unwrap())
+            .set_start_position(Position::new(rp.start + 1).unwrap())
             .set_end_position(Position::new(rp.start + (rp.seq.len() * rp.count)).unwrap())
-            .set_optional_fields(OptionalFields::from(vec![rp.count.to_string(), rp.seq]))
+            .set_optional_fields(OptionalFields::from(vec![
+                rp.count.to_string(),
+                String::from_utf8_lossy(&rp.seq).into_owned(),
+            ]))
     }
 }
 
-pub fn generate_false_duplication(
-    seq: &str,
-    regions: &IntervalSet<Position>,
-    length: usize,
-    number: usize,
+/// Check that segments can be duplicated from 2 to less than `max_duplications` times.
+pub fn check_max_duplications(max_duplications: usize) -> eyre::Result<()> {
+    if max_duplications < 3 {
+        bail!("Maximum number of duplications must be at least 3.")
+    }
+    Ok(())
+}
+
+/// Number of times to duplicate a segment, from 2 to less than `max_duplications`.
+pub fn duplication_count<R: Rng + ?Sized>(
     max_duplications: usize,
-    seed: Option<u64>,
-    randomize_length: bool,
-) -> eyre::Result<DuplicateSequence> {
-    let seq_segments =
-        generate_random_seq_ranges(seq.len(), regions, length, number, seed, randomize_length)?
-            .context("No sequence segments")?
-            .collect_vec();
-    let mut seq_iter = seq_segments.into_iter().peekable();
-    let mut new_seq = String::new();
+    rng: &mut R,
+) -> eyre::Result<usize> {
+    check_max_duplications(max_duplications)?;
+    Ok((2..max_duplications).choose(rng).unwrap())
+}
+
+/// Duplicate each sorted, non-overlapping range of `seq` from 2 to less than `max_duplications` times.
+pub fn generate_false_duplication<'a>(
+    seq: &'a [u8],
+    seq_segments: &[Range<usize>],
+    max_duplications: usize,
+    rng: &mut impl Rng,
+    soft_mask: bool,
+) -> eyre::Result<DuplicateSequence<'a>> {
+    // TODO: Look into characteristics of false duplications. Probably not completely random.
+    let duplications = seq_segments
+        .iter()
+        .map(|rrange| Ok((rrange.clone(), duplication_count(max_duplications, rng)?)))
+        .collect::<eyre::Result<Vec<_>>>()?;
+    Ok(duplicate_segments(seq, &duplications, soft_mask))
+}
+
+/// Duplicate each sorted, non-overlapping range of `seq` its number of times.
+pub fn duplicate_segments<'a>(
+    seq: &'a [u8],
+    duplications: &[(Range<usize>, usize)],
+    soft_mask: bool,
+) -> DuplicateSequence<'a> {
+    let mut seq_iter = duplications.iter().peekable();
+    let mut new_seq = SegmentedSequence::default();
     let mut duplicated_seqs = vec![];
+    let mut misassembled_ranges = vec![];
 
-    // Add starting sequence before first position.
-    if let Some((_, _, rrange)) = seq_iter.peek() {
-        new_seq.push_str(&seq[..rrange.start]);
-    };
+    // Add starting sequence before first position or the whole sequence if there are none.
+    new_seq.push(
+        &seq[..seq_iter
+            .peek()
+            .map_or(seq.len(), |(rrange, _)| rrange.start)],
+    );
 
-    // TODO: Look into characteristics of false duplications. Probably not completely random.
-    let mut rng = seed.map_or(StdRng::from_entropy(), StdRng::seed_from_u64);
-    while let Some((_, _, rrange)) = seq_iter.next() {
-        let num_dupes = (2..max_duplications.clamp(1, usize::MAX))
-            .choose(&mut rng)
-            .unwrap();
+    while let Some((rrange, num_dupes)) = seq_iter.next() {
         let dup_seq = &seq[rrange.clone()];
         let repeat = Repeat {
-            seq: dup_seq.to_string(),
+            seq: dup_seq.to_vec(),
             start: rrange.start,
-            count: num_dupes,
+            count: *num_dupes,
         };
 
-        for _ in 0..num_dupes {
-            new_seq.push_str(dup_seq);
+        let new_start = new_seq.len();
+        let dup_seq: Cow<[u8]> = if soft_mask {
+            dup_seq.to_ascii_lowercase().into()
+        } else {
+            dup_seq.into()
+        };
+        for _ in 0..*num_dupes {
+            new_seq.push(dup_seq.clone());
         }
 
-        let remaining_seq = if let Some((_, _, next_rrange)) = seq_iter.peek() {
+        misassembled_ranges.push(new_start..new_seq.len());
+
+        let remaining_seq = if let Some((next_rrange, _)) = seq_iter.peek() {
             &seq[rrange.end..next_rrange.start]
         } else {
             &seq[rrange.end..seq.len()]
         };
-        new_seq.push_str(remaining_seq);
+        new_seq.push(remaining_seq);
         duplicated_seqs.push(repeat);
     }
 
-    Ok(DuplicateSequence {
+    DuplicateSequence {
         seq: new_seq,
         duplicated_seqs,
-    })
+        misassembled_ranges,
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::utils::{place_misassemblies, Regions};
 
     #[test]
     fn test_generate_false_duplication() {
-        let seq = "AAAGGCCCTTTTCCGGGGGAACTTCGGAC";
-        let regions = IntervalSet::from_iter(std::iter::once(
+        let seq = b"AAAGGCCCTTTTCCGGGGGAACTTCGGAC";
+        let regions = Regions::from_iter(std::iter::once((
             Position::new(1).unwrap()..Position::new(seq.len()).unwrap(),
-        ));
+            1.0.into(),
+        )));
 
-        let new_seq = generate_false_duplication(seq, &regions, 10, 1, 3, Some(432), true).unwrap();
+        let new_seq = generate_false_duplication(
+            seq,
+            &place_misassemblies(
+                seq.len(),
+                &regions,
+                10,
+                1,
+                &mut StdRng::seed_from_u64(432),
+                true,
+                1,
+                None,
+            )
+            .unwrap(),
+            3,
+            &mut StdRng::seed_from_u64(432),
+            false,
+        )
+        .unwrap();
         assert_eq!(
             new_seq,
             DuplicateSequence {
-                seq: "AAAGGCCCTTTTCCGGGGGAACTTCGGATTCGGAC".to_string(),
+                seq: b"AAAGGCCCTTTTCCGGGGGAACTTCGGATTCGGAC".as_slice().into(),
                 duplicated_seqs: [Repeat {
-                    seq: "TTCGGA".to_string(),
+                    seq: b"TTCGGA".to_vec(),
                     start: 22,
                     count: 2
                 }]
-                .to_vec()
+                .to_vec(),
+                misassembled_ranges: std::iter::once(22..34).collect(),
             }
         );
     }
+
+    #[test]
+    fn test_generate_false_duplication_few_duplications() {
+        let seq = b"AAAGGCCCTTTTCCGGGGGAACTTCGGAC";
+        for max_duplications in 0..3 {
+            assert!(generate_false_duplication(
+                seq,
+                &[3..8, 12..20],
+                max_duplications,
+                &mut StdRng::seed_from_u64(432),
+                false,
+            )
+            .is_err());
+        }
+    }
+
+    #[test]
+    fn test_generate_false_duplication_soft_mask() {
+        let seq = b"AAAGGCCCTTTTCCGGGGGAACTTCGGAC";
+        let regions = Regions::from_iter(std::iter::once((
+            Position::new(1).unwrap()..Position::new(seq.len()).unwrap(),
+            1.0.into(),
+        )));
+
+        let new_seq = generate_false_duplication(
+            seq,
+            &place_misassemblies(
+                seq.len(),
+                &regions,
+                10,
+                1,
+                &mut StdRng::seed_from_u64(432),
+                true,
+                1,
+                None,
+            )
+            .unwrap(),
+            3,
+            &mut StdRng::seed_from_u64(432),
+            true,
+        )
+        .unwrap();
+        assert_eq!(
+            new_seq.seq,
+            SegmentedSequence::from(b"AAAGGCCCTTTTCCGGGGGAACttcggattcggaC".as_slice())
+        );
+    }
+
+    #[test]
+    fn test_generate_false_duplication_preserve_case() {
+        let seq = b"AAAGGCCCTTTTCCGGGGGAacttcggAC";
+        let regions = Regions::from_iter(std::iter::once((
+            Position::new(1).unwrap()..Position::new(seq.len()).unwrap(),
+            1.0.into(),
+        )));
+
+        let new_seq = generate_false_duplication(
+            seq,
+            &place_misassemblies(
+                seq.len(),
+                &regions,
+                10,
+                1,
+                &mut StdRng::seed_from_u64(432),
+                true,
+                1,
+                None,
+            )
+            .unwrap(),
+            3,
+            &mut StdRng::seed_from_u64(432),
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            new_seq.seq,
+            SegmentedSequence::from(b"AAAGGCCCTTTTCCGGGGGAacttcggAttcggAC".as_slice())
+        );
+        assert_eq!(new_seq.duplicated_seqs[0].seq, b"ttcggA");
+    }
 }
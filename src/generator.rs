@@ -0,0 +1,192 @@
+use std::collections::BTreeMap;
+
+use rand::RngCore;
+
+use crate::{
+    apply::Variant,
+    commands::MisassemblyCommands,
+    false_dupe::duplication_count,
+    utils::{place_misassemblies, Regions},
+};
+
+/// Generator of misassemblies in a record.
+/// Generated variants are applied with [`crate::apply::apply_variants`] or planned with [`crate::plan::Plan`].
+pub trait MisassemblyGenerator: Send + Sync {
+    /// Sample misassemblies within `regions` of a record of `seq_len` bases.
+    /// Variants must be sorted and not overlap.
+    fn generate(
+        &self,
+        seq_len: usize,
+        regions: &Regions,
+        rng: &mut dyn RngCore,
+    ) -> eyre::Result<Vec<Variant>>;
+}
+
+/// Built-in misassembly types of the misassembly commands.
+#[derive(Debug)]
+pub struct BuiltinGenerator {
+    pub command: MisassemblyCommands,
+    /// Randomize the length of each misassembly between `min_length` and its length.
+    pub randomize_length: bool,
+    pub min_length: usize,
+    /// Maximum total bases misassembled in a record.
+    pub max_total_bases: Option<usize>,
+}
+
+impl MisassemblyGenerator for BuiltinGenerator {
+    fn generate(
+        &self,
+        seq_len: usize,
+        regions: &Regions,
+        mut rng: &mut dyn RngCore,
+    ) -> eyre::Result<Vec<Variant>> {
+        // Breaks have no length.
+        let (length, randomize_length, min_length, max_total_bases) = match self.command {
+            MisassemblyCommands::Misjoin { length, .. }
+            | MisassemblyCommands::Gap { length, .. }
+            | MisassemblyCommands::FalseDuplication { length, .. } => (
+                length,
+                self.randomize_length,
+                self.min_length,
+                self.max_total_bases,
+            ),
+            MisassemblyCommands::Break { .. } => (1, true, 1, None),
+        };
+        let ranges = place_misassemblies(
+            seq_len,
+            regions,
+            length,
+            self.command.number(),
+            &mut rng,
+            randomize_length,
+            min_length,
+            max_total_bases,
+        )?;
+        ranges
+            .into_iter()
+            .map(|range| {
+                Ok(match self.command {
+                    MisassemblyCommands::Misjoin { .. } => Variant::Deletion(range),
+                    MisassemblyCommands::Gap { .. } => Variant::Gap(range),
+                    MisassemblyCommands::FalseDuplication {
+                        max_duplications, ..
+                    } => Variant::Duplication {
+                        range,
+                        count: duplication_count(max_duplications, &mut rng)?,
+                    },
+                    MisassemblyCommands::Break { .. } => Variant::Break { pos: range.start },
+                })
+            })
+            .collect()
+    }
+}
+
+/// Misassembly generators by name.
+#[derive(Default)]
+pub struct GeneratorRegistry {
+    generators: BTreeMap<String, Box<dyn MisassemblyGenerator>>,
+}
+
+impl GeneratorRegistry {
+    /// Add a generator, replacing any generator with the same name.
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        generator: impl MisassemblyGenerator + 'static,
+    ) {
+        self.generators.insert(name.into(), Box::new(generator));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn MisassemblyGenerator> {
+        self.generators
+            .get(name)
+            .map(|generator| generator.as_ref())
+    }
+
+    /// Names of all generators in sorted order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.generators.keys().map(String::as_str)
+    }
+
+    /// Sample misassemblies with the generator `name`.
+    pub fn generate(
+        &self,
+        name: &str,
+        seq_len: usize,
+        regions: &Regions,
+        rng: &mut dyn RngCore,
+    ) -> eyre::Result<Vec<Variant>> {
+        let Some(generator) = self.get(name) else {
+            eyre::bail!("No misassembly generator named {name:?}.")
+        };
+        generator.generate(seq_len, regions, rng)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use noodles::core::Position;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    /// Inverts the middle of a record.
+    struct MiddleInversion;
+
+    impl MisassemblyGenerator for MiddleInversion {
+        fn generate(
+            &self,
+            seq_len: usize,
+            _: &Regions,
+            _: &mut dyn RngCore,
+        ) -> eyre::Result<Vec<Variant>> {
+            Ok(vec![Variant::Inversion(seq_len / 4..seq_len * 3 / 4)])
+        }
+    }
+
+    #[test]
+    fn test_generator_registry() {
+        let regions = Regions::from_iter(std::iter::once((
+            Position::MIN..Position::new(40).unwrap(),
+            1.0.into(),
+        )));
+        let mut registry = GeneratorRegistry::default();
+        registry.register("inversion", MiddleInversion);
+        registry.register(
+            "false_duplication",
+            BuiltinGenerator {
+                command: MisassemblyCommands::FalseDuplication {
+                    number: 2,
+                    length: 5,
+                    max_duplications: 3,
+                },
+                randomize_length: false,
+                min_length: 1,
+                max_total_bases: None,
+            },
+        );
+        assert_eq!(
+            registry.names().collect::<Vec<_>>(),
+            ["false_duplication", "inversion"]
+        );
+
+        let mut rng = StdRng::seed_from_u64(42);
+        assert_eq!(
+            registry
+                .generate("inversion", 40, &regions, &mut rng)
+                .unwrap(),
+            [Variant::Inversion(10..30)]
+        );
+        let variants = registry
+            .generate("false_duplication", 40, &regions, &mut rng)
+            .unwrap();
+        assert_eq!(variants.len(), 2);
+        assert!(variants.iter().all(|variant| matches!(
+            variant,
+            Variant::Duplication { range, count: 2 } if range.len() == 5
+        )));
+        assert!(registry
+            .generate("collapse", 40, &regions, &mut rng)
+            .is_err());
+    }
+}
@@ -0,0 +1,269 @@
+use std::{io::Write, ops::Range};
+
+use iset::IntervalSet;
+use noodles::{
+    core::Position,
+    fasta::{
+        self,
+        record::{Definition, Sequence},
+    },
+};
+use rand::{seq::SliceRandom, Rng};
+
+use crate::{
+    io::FastaWriter,
+    rng::{RngKind, SimRng},
+    utils::{place_misassemblies, record_seed, reverse_complement, subtract_intervals, Regions},
+};
+
+/// Prefix of generated contig names. ex. contig_1
+pub const CONTIG_PREFIX: &str = "contig_";
+
+/// Options of [`generate_contig`].
+#[derive(Debug, Clone, Copy)]
+pub struct GenomeConfig {
+    /// Fraction of bases that are G or C.
+    pub gc: f64,
+    pub tandem_repeats: usize,
+    pub tandem_repeat_length: usize,
+    pub tandem_repeat_unit_length: usize,
+    /// Fraction of bases changed in each copy of a tandem repeat unit.
+    pub tandem_repeat_divergence: f64,
+    pub segmental_duplications: usize,
+    pub segmental_duplication_length: usize,
+    /// Fraction of bases changed in the copy of a segmental duplication.
+    pub segmental_duplication_divergence: f64,
+}
+
+/// Repeat embedded in a generated contig.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Feature {
+    TandemRepeat {
+        /// 0-based range of the array.
+        range: Range<usize>,
+        unit_length: usize,
+    },
+    SegmentalDuplication {
+        /// 0-based range of the copy.
+        range: Range<usize>,
+        /// 0-based range of the copied sequence.
+        source: Range<usize>,
+        reverse: bool,
+    },
+}
+
+/// Generate a random sequence of `length` bases with a fraction of `gc` bases being G or C.
+pub fn random_sequence(length: usize, gc: f64, rng: &mut impl Rng) -> Vec<u8> {
+    (0..length)
+        .map(|_| {
+            let bases = if rng.gen_bool(gc) { b"GC" } else { b"AT" };
+            *bases.choose(rng).unwrap()
+        })
+        .collect()
+}
+
+/// Change each base of a sequence to another base with a probability of `divergence`.
+pub fn diverge(seq: &mut [u8], divergence: f64, rng: &mut impl Rng) {
+    for base in seq.iter_mut() {
+        if !rng.gen_bool(divergence) {
+            continue;
+        }
+        let original = *base;
+        *base = **b"ACGT"
+            .iter()
+            .filter(|b| **b != original)
+            .collect::<Vec<_>>()
+            .choose(rng)
+            .unwrap();
+    }
+}
+
+/// Generate a random contig with non-overlapping tandem repeat arrays and segmental duplications.
+/// Fewer repeats are embedded if no more can be placed.
+pub fn generate_contig(
+    length: usize,
+    config: GenomeConfig,
+    rng: &mut impl Rng,
+) -> eyre::Result<(Vec<u8>, Vec<Feature>)> {
+    let mut seq = random_sequence(length, config.gc, rng);
+    let mut features = vec![];
+    let contig_interval = Position::MIN..Position::try_from(length)?;
+    let mut regions = Regions::from_iter(std::iter::once((contig_interval, 1.0.into())));
+
+    if config.tandem_repeats > 0 {
+        let unit = random_sequence(config.tandem_repeat_unit_length.max(1), config.gc, rng);
+        let ranges = place_misassemblies(
+            length,
+            &regions,
+            config.tandem_repeat_length,
+            config.tandem_repeats,
+            rng,
+            false,
+            1,
+            None,
+        )?;
+        let mut placed = IntervalSet::new();
+        for range in ranges {
+            for (base, unit_base) in seq[range.clone()].iter_mut().zip(unit.iter().cycle()) {
+                *base = *unit_base;
+            }
+            diverge(
+                &mut seq[range.clone()],
+                config.tandem_repeat_divergence,
+                rng,
+            );
+            placed.insert(Position::try_from(range.start.max(1))?..Position::try_from(range.end)?);
+            features.push(Feature::TandemRepeat {
+                range,
+                unit_length: unit.len(),
+            });
+        }
+        // Keep segmental duplications out of tandem repeat arrays.
+        regions = subtract_intervals(&regions, &placed);
+    }
+
+    if config.segmental_duplications > 0 {
+        let mut ranges = place_misassemblies(
+            length,
+            &regions,
+            config.segmental_duplication_length,
+            config.segmental_duplications * 2,
+            rng,
+            false,
+            1,
+            None,
+        )?;
+        ranges.shuffle(rng);
+        for pair in ranges.chunks_exact(2) {
+            let [source, range] = [pair[0].clone(), pair[1].clone()];
+            let reverse = rng.gen_bool(0.5);
+            let mut copy = seq[source.clone()].to_vec();
+            if reverse {
+                copy = reverse_complement(&copy);
+            }
+            diverge(&mut copy, config.segmental_duplication_divergence, rng);
+            seq[range.clone()].copy_from_slice(&copy);
+            features.push(Feature::SegmentalDuplication {
+                range,
+                source,
+                reverse,
+            });
+        }
+    }
+    features.sort_by_key(|feature| match feature {
+        Feature::TandemRepeat { range, .. } | Feature::SegmentalDuplication { range, .. } => {
+            range.start
+        }
+    });
+    Ok((seq, features))
+}
+
+/// Write a BED record of each feature of a contig.
+/// The 4th column is the type and the 5th column is the unit length or source of the duplication.
+pub fn write_features(
+    mut writer: impl Write,
+    name: &str,
+    features: &[Feature],
+) -> eyre::Result<()> {
+    for feature in features {
+        match feature {
+            Feature::TandemRepeat { range, unit_length } => writeln!(
+                writer,
+                "{name}\t{}\t{}\tTandemRepeat\tunit_length={unit_length}",
+                range.start, range.end
+            )?,
+            Feature::SegmentalDuplication {
+                range,
+                source,
+                reverse,
+            } => writeln!(
+                writer,
+                "{name}\t{}\t{}\tSegmentalDuplication\tsource={name}:{}-{},strand={}",
+                range.start,
+                range.end,
+                source.start,
+                source.end,
+                if *reverse { '-' } else { '+' }
+            )?,
+        }
+    }
+    Ok(())
+}
+
+/// Generate a contig of each length and write it and its features.
+/// Each contig is seeded by its name from `seed`.
+pub fn generate_genome<W: Write>(
+    lengths: &[usize],
+    config: GenomeConfig,
+    seed: u64,
+    rng_kind: RngKind,
+    writer_fa: &mut FastaWriter<W>,
+    mut writer_bed: Option<&mut dyn Write>,
+) -> eyre::Result<()> {
+    for (i, length) in lengths.iter().enumerate() {
+        let name = format!("{CONTIG_PREFIX}{}", i + 1);
+        let mut rng = SimRng::new(rng_kind, record_seed(seed, &name));
+        let (seq, features) = generate_contig(*length, config, &mut rng)?;
+        log::debug!(
+            "Generated {name:?} of {length} bp with {} repeat(s).",
+            features.len()
+        );
+        writer_fa.write_record(&fasta::Record::new(
+            Definition::new(name.clone(), None),
+            Sequence::from(seq),
+        ))?;
+        if let Some(writer_bed) = writer_bed.as_mut() {
+            write_features(writer_bed, &name, &features)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn test_generate_contig() {
+        let config = GenomeConfig {
+            gc: 0.4,
+            tandem_repeats: 2,
+            tandem_repeat_length: 1000,
+            tandem_repeat_unit_length: 171,
+            tandem_repeat_divergence: 0.0,
+            segmental_duplications: 2,
+            segmental_duplication_length: 500,
+            segmental_duplication_divergence: 0.0,
+        };
+        let mut rng = StdRng::seed_from_u64(42);
+        let (seq, features) = generate_contig(20_000, config, &mut rng).unwrap();
+        assert_eq!(seq.len(), 20_000);
+        let gc = seq.iter().filter(|b| b"GC".contains(b)).count() as f64 / seq.len() as f64;
+        assert!((0.35..0.45).contains(&gc), "{gc}");
+        assert_eq!(features.len(), 4);
+        for feature in features {
+            match feature {
+                Feature::TandemRepeat { range, unit_length } => {
+                    assert_eq!(
+                        seq[range.start..range.end - unit_length],
+                        seq[range.start + unit_length..range.end]
+                    );
+                }
+                Feature::SegmentalDuplication {
+                    range,
+                    source,
+                    reverse,
+                } => {
+                    let copy = if reverse {
+                        reverse_complement(&seq[source])
+                    } else {
+                        seq[source].to_vec()
+                    };
+                    assert_eq!(seq[range], copy);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,533 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+};
+
+use eyre::{bail, Context, ContextCompat};
+use itertools::Itertools;
+use noodles::fasta::{
+    self,
+    record::{Definition, Sequence},
+};
+use rand::{seq::SliceRandom, Rng};
+
+use crate::{io::FastaWriter, utils::reverse_complement, utils::SequenceType};
+
+/// Suffix of duplicated segments. ex. s1_dup1
+const DUPLICATE_SUFFIX: &str = "_dup";
+
+/// Oriented segment. `true` if reverse complemented.
+pub type Step = (String, bool);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GfaSegment {
+    pub name: String,
+    pub seq: Vec<u8>,
+    /// Optional fields written as is.
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GfaLink {
+    pub from: Step,
+    pub to: Step,
+    /// Overlap CIGAR. ex. 0M
+    pub overlap: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GfaPath {
+    pub name: String,
+    pub steps: Vec<Step>,
+}
+
+/// GFA 1 assembly graph of segments, links, and paths.
+/// Other lines, like the header, are kept as is. Containments and path overlaps are dropped.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Gfa {
+    pub other_lines: Vec<String>,
+    pub segments: Vec<GfaSegment>,
+    pub links: Vec<GfaLink>,
+    pub paths: Vec<GfaPath>,
+}
+
+/// Misassembly introduced in a GFA path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GfaMisassembly {
+    pub path: String,
+    /// 0-based range in the original linearized path.
+    pub start: usize,
+    pub end: usize,
+    pub typ: SequenceType,
+    pub segment: String,
+}
+
+fn parse_orient(orient: &str) -> eyre::Result<bool> {
+    match orient {
+        "+" => Ok(false),
+        "-" => Ok(true),
+        _ => bail!("Invalid orientation: {orient:?}"),
+    }
+}
+
+fn orient_char(reverse: bool) -> char {
+    if reverse {
+        '-'
+    } else {
+        '+'
+    }
+}
+
+/// Flip a step to the other strand.
+fn flip((name, reverse): &Step) -> Step {
+    (name.clone(), !reverse)
+}
+
+/// Same key for a link in either direction.
+fn link_key(from: &Step, to: &Step) -> (Step, Step) {
+    (from.clone(), to.clone()).min((flip(to), flip(from)))
+}
+
+/// Length of the overlap of a CIGAR like "10M". Unknown overlaps, "*", are 0.
+fn overlap_length(overlap: &str) -> eyre::Result<usize> {
+    if overlap == "*" {
+        return Ok(0);
+    }
+    let mut length = 0;
+    let mut num = String::new();
+    for c in overlap.chars() {
+        if c.is_ascii_digit() {
+            num.push(c);
+            continue;
+        }
+        let n: usize = num
+            .parse()
+            .with_context(|| format!("Invalid overlap: {overlap}"))?;
+        // Overlapping bases of the next segment.
+        if matches!(c, 'M' | 'I' | '=' | 'X') {
+            length += n;
+        }
+        num.clear();
+    }
+    Ok(length)
+}
+
+impl Gfa {
+    pub fn read(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("Cannot open GFA: {path:?}"))?;
+        Gfa::from_reader(BufReader::new(file))
+    }
+
+    pub fn from_reader(reader: impl BufRead) -> eyre::Result<Self> {
+        let mut gfa = Gfa::default();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let fields = line.split('\t').collect_vec();
+            let invalid = || format!("Invalid GFA line {}: {line}", i + 1);
+            match fields[..] {
+                ["S", name, seq, ref tags @ ..] => gfa.segments.push(GfaSegment {
+                    name: name.to_owned(),
+                    seq: seq.as_bytes().to_vec(),
+                    tags: tags.iter().map(|tag| tag.to_string()).collect(),
+                }),
+                ["L", from, from_orient, to, to_orient, overlap, ..] => gfa.links.push(GfaLink {
+                    from: (
+                        from.to_owned(),
+                        parse_orient(from_orient).with_context(invalid)?,
+                    ),
+                    to: (
+                        to.to_owned(),
+                        parse_orient(to_orient).with_context(invalid)?,
+                    ),
+                    overlap: overlap.to_owned(),
+                }),
+                ["P", name, steps, ..] => gfa.paths.push(GfaPath {
+                    name: name.to_owned(),
+                    steps: steps
+                        .split(',')
+                        .map(|step| {
+                            let (name, orient) = step.split_at(step.len().saturating_sub(1));
+                            Ok((name.to_owned(), parse_orient(orient)?))
+                        })
+                        .collect::<eyre::Result<_>>()
+                        .with_context(invalid)?,
+                }),
+                ["S" | "L" | "P", ..] => bail!(invalid()),
+                [""] => {}
+                _ => gfa.other_lines.push(line),
+            }
+        }
+        Ok(gfa)
+    }
+
+    pub fn write(&self, mut writer: impl Write) -> eyre::Result<()> {
+        for line in self.other_lines.iter() {
+            writeln!(writer, "{line}")?;
+        }
+        for segment in self.segments.iter() {
+            write!(
+                writer,
+                "S\t{}\t{}",
+                segment.name,
+                String::from_utf8_lossy(&segment.seq)
+            )?;
+            for tag in segment.tags.iter() {
+                write!(writer, "\t{tag}")?;
+            }
+            writeln!(writer)?;
+        }
+        for link in self.links.iter() {
+            writeln!(
+                writer,
+                "L\t{}\t{}\t{}\t{}\t{}",
+                link.from.0,
+                orient_char(link.from.1),
+                link.to.0,
+                orient_char(link.to.1),
+                link.overlap
+            )?;
+        }
+        for path in self.paths.iter() {
+            let steps = path
+                .steps
+                .iter()
+                .map(|(name, reverse)| format!("{name}{}", orient_char(*reverse)))
+                .join(",");
+            writeln!(writer, "P\t{}\t{steps}\t*", path.name)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Overlap of the link between two steps in either direction. `None` if not linked.
+    fn link_overlap(&self, from: &Step, to: &Step) -> eyre::Result<Option<usize>> {
+        let key = link_key(from, to);
+        self.links
+            .iter()
+            .find(|link| link_key(&link.from, &link.to) == key)
+            .map(|link| overlap_length(&link.overlap))
+            .transpose()
+    }
+
+    /// Start of each step in the linearized path and the length of the path.
+    /// Overlapping bases are only counted in the first step.
+    fn step_offsets(&self, path: &GfaPath) -> eyre::Result<(Vec<usize>, usize)> {
+        let lengths: HashMap<&str, usize> = self
+            .segments
+            .iter()
+            .map(|segment| (segment.name.as_str(), segment.seq.len()))
+            .collect();
+        let mut offsets = vec![];
+        let mut pos = 0;
+        for (i, step) in path.steps.iter().enumerate() {
+            let length = *lengths.get(step.0.as_str()).with_context(|| {
+                format!("Segment {:?} of path {:?} not found.", step.0, path.name)
+            })?;
+            let overlap = match i.checked_sub(1) {
+                Some(prev) => self.link_overlap(&path.steps[prev], step)?.unwrap_or(0),
+                None => 0,
+            };
+            offsets.push(pos);
+            pos += length.saturating_sub(overlap);
+        }
+        Ok((offsets, pos))
+    }
+
+    /// Spell out the sequence of a path.
+    pub fn linearize(&self, path: &GfaPath) -> eyre::Result<Vec<u8>> {
+        let segments: HashMap<&str, &[u8]> = self
+            .segments
+            .iter()
+            .map(|segment| (segment.name.as_str(), segment.seq.as_slice()))
+            .collect();
+        let mut seq = vec![];
+        for (i, step) in path.steps.iter().enumerate() {
+            let segment_seq = segments.get(step.0.as_str()).with_context(|| {
+                format!("Segment {:?} of path {:?} not found.", step.0, path.name)
+            })?;
+            let segment_seq = if step.1 {
+                reverse_complement(segment_seq)
+            } else {
+                segment_seq.to_vec()
+            };
+            let overlap = match i.checked_sub(1) {
+                Some(prev) => self.link_overlap(&path.steps[prev], step)?.unwrap_or(0),
+                None => 0,
+            };
+            seq.extend(segment_seq.get(overlap..).unwrap_or_default());
+        }
+        Ok(seq)
+    }
+
+    fn remove_link(&mut self, from: &Step, to: &Step) {
+        let key = link_key(from, to);
+        self.links
+            .retain(|link| link_key(&link.from, &link.to) != key);
+    }
+
+    /// Misassemble the paths of the graph.
+    /// * Breaks remove the link between two steps and split the path into paths named `{name}:{start}-{end}`.
+    /// * Misjoins bypass a step with a new link between its neighbors and remove its links.
+    /// * False duplications add a copy of a step's segment named `{segment}_dup{i}` after it.
+    ///
+    /// Steps are chosen at random with at most one misassembly each.
+    /// Returns the misassemblies in original path coordinates.
+    pub fn misassemble(
+        &mut self,
+        breaks: usize,
+        misjoins: usize,
+        false_duplications: usize,
+        rng: &mut impl Rng,
+    ) -> eyre::Result<Vec<GfaMisassembly>> {
+        if self.paths.is_empty() {
+            bail!("GFA has no paths to misassemble.")
+        }
+        // Choose steps to misassemble. Misjoins need neighbors to join and breaks a previous step.
+        let mut steps = self
+            .paths
+            .iter()
+            .enumerate()
+            .flat_map(|(i, path)| (0..path.steps.len()).map(move |j| (i, j)))
+            .collect_vec();
+        steps.shuffle(rng);
+        let mut chosen: HashMap<(usize, usize), SequenceType> = HashMap::new();
+        for (typ, number) in [
+            (SequenceType::Misjoin, misjoins),
+            (SequenceType::Break, breaks),
+            (SequenceType::FalseDuplication, false_duplications),
+        ] {
+            let candidates = steps
+                .iter()
+                .filter(|(i, j)| {
+                    let num_steps = self.paths[*i].steps.len();
+                    match typ {
+                        SequenceType::Misjoin => (1..num_steps.saturating_sub(1)).contains(j),
+                        SequenceType::Break => *j > 0,
+                        _ => true,
+                    }
+                })
+                .filter(|step| !chosen.contains_key(step))
+                .take(number)
+                .copied()
+                .collect_vec();
+            if candidates.len() < number {
+                log::warn!(
+                    "Could only place {} of {number} {typ:?} misassemblies.",
+                    candidates.len()
+                );
+            }
+            chosen.extend(candidates.into_iter().map(|step| (step, typ)));
+        }
+
+        let mut misassemblies = vec![];
+        let mut num_duplicates: HashMap<String, usize> = HashMap::new();
+        let mut new_paths = vec![];
+        let mut new_segments = vec![];
+        for (i, path) in self.paths.iter().enumerate() {
+            let (offsets, path_length) = self.step_offsets(path)?;
+            let step_end = |j: usize| offsets.get(j + 1).copied().unwrap_or(path_length);
+            // Pieces of the path between breaks with their original start.
+            let mut pieces: Vec<(usize, Vec<Step>)> = vec![(0, vec![])];
+            for (j, step) in path.steps.iter().enumerate() {
+                let Some(typ) = chosen.get(&(i, j)).copied() else {
+                    pieces.last_mut().unwrap().1.push(step.clone());
+                    continue;
+                };
+                let (start, end) = match typ {
+                    SequenceType::Break => (offsets[j], offsets[j] + 1),
+                    _ => (offsets[j], step_end(j)),
+                };
+                misassemblies.push(GfaMisassembly {
+                    path: path.name.clone(),
+                    start,
+                    end,
+                    typ,
+                    segment: step.0.clone(),
+                });
+                match typ {
+                    SequenceType::Break => pieces.push((offsets[j], vec![step.clone()])),
+                    SequenceType::Misjoin => {}
+                    _ => {
+                        let count = num_duplicates.entry(step.0.clone()).or_default();
+                        *count += 1;
+                        let dup_name = format!("{}{DUPLICATE_SUFFIX}{count}", step.0);
+                        let segment = self
+                            .segments
+                            .iter()
+                            .find(|segment| segment.name == step.0)
+                            .with_context(|| format!("Segment {:?} not found.", step.0))?;
+                        new_segments.push(GfaSegment {
+                            name: dup_name.clone(),
+                            ..segment.clone()
+                        });
+                        let piece = &mut pieces.last_mut().unwrap().1;
+                        piece.push(step.clone());
+                        piece.push((dup_name, step.1));
+                    }
+                }
+            }
+            let num_pieces = pieces.len();
+            for (k, (start, steps)) in pieces.iter().enumerate() {
+                let name = if num_pieces == 1 {
+                    path.name.clone()
+                } else {
+                    let end = pieces.get(k + 1).map_or(path_length, |(end, _)| *end);
+                    format!("{}:{}-{end}", path.name, start + 1)
+                };
+                new_paths.push(GfaPath {
+                    name,
+                    steps: steps.clone(),
+                });
+            }
+        }
+
+        // Remove the links of breaks and misjoins.
+        let removed_links =
+            self.paths
+                .iter()
+                .enumerate()
+                .flat_map(|(i, path)| {
+                    let chosen = &chosen;
+                    path.steps.iter().enumerate().flat_map(move |(j, step)| {
+                        match chosen.get(&(i, j)) {
+                            Some(SequenceType::Break) => {
+                                vec![(path.steps[j - 1].clone(), step.clone())]
+                            }
+                            Some(SequenceType::Misjoin) => vec![
+                                (path.steps[j - 1].clone(), step.clone()),
+                                (step.clone(), path.steps[j + 1].clone()),
+                            ],
+                            _ => vec![],
+                        }
+                    })
+                })
+                .collect_vec();
+        // Keep links still used by other paths.
+        let used_links: HashSet<(Step, Step)> = new_paths
+            .iter()
+            .flat_map(|path| path.steps.iter().tuple_windows())
+            .map(|(from, to)| link_key(from, to))
+            .collect();
+        for (from, to) in removed_links.iter() {
+            if !used_links.contains(&link_key(from, to)) {
+                self.remove_link(from, to);
+            }
+        }
+        self.segments.extend(new_segments);
+        // Link every pair of steps of the new paths. ex. Bypassed steps and duplicates
+        let mut linked: HashSet<(Step, Step)> = HashSet::new();
+        for path in new_paths.iter() {
+            for (from, to) in path.steps.iter().tuple_windows() {
+                if self.link_overlap(from, to)?.is_none()
+                    && linked.insert((from.clone(), to.clone()))
+                {
+                    self.links.push(GfaLink {
+                        from: from.clone(),
+                        to: to.clone(),
+                        overlap: "0M".to_owned(),
+                    });
+                }
+            }
+        }
+        self.paths = new_paths;
+        misassemblies.sort_by(|a, b| (&a.path, a.start).cmp(&(&b.path, b.start)));
+        Ok(misassemblies)
+    }
+
+    /// Write the linearized sequence of each path.
+    pub fn write_paths<W: Write>(&self, writer_fa: &mut FastaWriter<W>) -> eyre::Result<()> {
+        for path in self.paths.iter() {
+            writer_fa.write_record(&fasta::Record::new(
+                Definition::new(path.name.clone(), None),
+                Sequence::from(self.linearize(path)?),
+            ))?;
+        }
+        Ok(())
+    }
+}
+
+/// Write misassemblies as BED records with their type and segment.
+pub fn write_gfa_misassemblies(
+    mut writer: impl Write,
+    misassemblies: &[GfaMisassembly],
+) -> eyre::Result<()> {
+    for misassembly in misassemblies {
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\tsegment={}",
+            misassembly.path,
+            misassembly.start,
+            misassembly.end,
+            misassembly.typ.name(),
+            misassembly.segment
+        )?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    const GFA: &str = "H\tVN:Z:1.0
+S\ts1\tAAAACC
+S\ts2\tCCGGGG
+S\ts3\tTTTT
+L\ts1\t+\ts2\t+\t2M
+L\ts2\t+\ts3\t-\t0M
+P\tp1\ts1+,s2+,s3-\t*
+";
+
+    fn read_gfa() -> Gfa {
+        Gfa::from_reader(GFA.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_gfa_read_write() {
+        let gfa = read_gfa();
+        assert_eq!(gfa.segments.len(), 3);
+        assert_eq!(gfa.linearize(&gfa.paths[0]).unwrap(), b"AAAACCGGGGAAAA");
+        let mut out = vec![];
+        gfa.write(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), GFA);
+    }
+
+    #[test]
+    fn test_gfa_misassemble() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut gfa = read_gfa();
+        let misassemblies = gfa.misassemble(0, 1, 0, &mut rng).unwrap();
+        // Only s2 has neighbors to join.
+        assert_eq!(
+            misassemblies,
+            [GfaMisassembly {
+                path: "p1".to_owned(),
+                start: 6,
+                end: 10,
+                typ: SequenceType::Misjoin,
+                segment: "s2".to_owned()
+            }]
+        );
+        assert_eq!(gfa.linearize(&gfa.paths[0]).unwrap(), b"AAAACCAAAA");
+        assert_eq!(gfa.links.len(), 1);
+
+        let mut gfa = read_gfa();
+        gfa.misassemble(2, 0, 1, &mut rng).unwrap();
+        let names = gfa
+            .paths
+            .iter()
+            .map(|path| path.name.as_str())
+            .collect_vec();
+        assert_eq!(names, ["p1:1-6", "p1:7-10", "p1:11-14"]);
+        assert_eq!(gfa.segments.len(), 4);
+        // Broken paths keep overlapping bases and s1 is duplicated.
+        assert_eq!(gfa.linearize(&gfa.paths[0]).unwrap(), b"AAAACCAAAACC");
+        assert_eq!(gfa.linearize(&gfa.paths[1]).unwrap(), b"CCGGGG");
+    }
+}
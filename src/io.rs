@@ -1,53 +1,1160 @@
-use eyre::Context;
-use iset::IntervalSet;
+use eyre::{bail, Context, ContextCompat};
+use flate2::read::MultiGzDecoder;
+use iset::{IntervalMap, IntervalSet};
+use itertools::Itertools;
+use memmap2::Mmap;
 use noodles::{
-    bed,
-    bgzf::{self, IndexedReader},
+    bed::{self, record::OptionalFields},
+    bgzf::{self, writer::CompressionLevel, IndexedReader},
     core::Position,
-    fasta,
+    fasta::{
+        self,
+        record::{Definition, Sequence},
+    },
 };
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fs::File,
-    io::{stdout, BufReader, Write},
+    io::{stdin, stdout, BufRead, BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write},
+    num::NonZeroUsize,
+    ops::Range,
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
-type Outfiles = (Box<dyn Write>, Option<bed::Writer<File>>);
+use crate::{
+    apply::{Event, Variant},
+    config::{OutputFormat, TruthFormat},
+    utils::{misassembled_bases, Region, Regions, SequenceType},
+};
+
+const GZIP_MAGIC: [u8; 3] = [0x1f, 0x8b, 0x08];
+
+/// Number of bases fetched at a time when streaming a record.
+const FETCH_CHUNK_SIZE: usize = 1 << 20;
+
+type Outfiles = (OutputStream, Option<TruthWriter>);
+
+const FLAGGER_HAPLOID: &str = "Hap";
+
+/// Prefix of the field with the names of the input regions a misassembly is in.
+pub const REGION_FIELD_PREFIX: &str = "region=";
+
+/// Prefix of the field with the unique ID of a misassembly. ex. id=misasim.3
+pub const ID_FIELD_PREFIX: &str = "id=";
+
+/// Suffix of the original of a misassembled record written with "--keep-original".
+pub const ORIGINAL_RECORD_SUFFIX: &str = "_original";
+
+/// Input record names and their output record names.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RenameMap {
+    names: HashMap<String, String>,
+    /// Suffix added to the names of misassembled records.
+    suffix: String,
+}
+
+impl RenameMap {
+    /// Read a TSV of input and output record names.
+    /// Empty lines and lines starting with "#" are skipped.
+    pub fn read(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("Cannot open rename map: {path:?}"))?;
+        let mut names = HashMap::new();
+        let mut new_names = HashSet::new();
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((old, new)) = line.split_once('\t') else {
+                bail!("Expected 2 columns at {path:?}:{}: {line:?}", i + 1)
+            };
+            if !new_names.insert(new.to_owned()) {
+                bail!("Duplicate output record name in rename map: {new:?}")
+            }
+            if names.insert(old.to_owned(), new.to_owned()).is_some() {
+                bail!("Duplicate input record name in rename map: {old:?}")
+            }
+        }
+        Ok(Self {
+            names,
+            suffix: String::new(),
+        })
+    }
+
+    /// Keep the suffix added to misassembled records when renaming them. ex. "--suffix"
+    pub fn with_suffix(mut self, suffix: &str) -> Self {
+        self.suffix = suffix.to_owned();
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Output name of a record. Names not in the map are unchanged.
+    /// Names derived from a record keep the suffix misasim added.
+    /// ex. "{name}:{start}-{end}", "{name}_original", or "{name}{suffix}"
+    pub fn rename(&self, name: &str) -> String {
+        if let Some(new) = self.names.get(name) {
+            return new.clone();
+        }
+        match self.split_derived(name) {
+            Some((base, suffix)) => format!("{}{suffix}", self.rename(base)),
+            None => name.to_owned(),
+        }
+    }
+
+    /// Output name of a misassembled input record with the suffix.
+    pub fn output_name(&self, name: &str) -> String {
+        self.rename(&format!("{name}{}", self.suffix))
+    }
+
+    /// Name of a misassembled record without the suffix. ex. "{name}{suffix}:{start}-{end}" is "{name}:{start}-{end}"
+    pub fn without_suffix(&self, name: &str) -> String {
+        match self.split_derived(name) {
+            Some((base, coords)) if coords.starts_with(':') => {
+                format!("{}{coords}", self.without_suffix(base))
+            }
+            Some((base, suffix)) if suffix == self.suffix => base.to_owned(),
+            _ => name.to_owned(),
+        }
+    }
+
+    /// Split a name derived from a record into the record name and the suffix misasim added.
+    fn split_derived<'a>(&self, name: &'a str) -> Option<(&'a str, &'a str)> {
+        let is_coords = |coords: &str| {
+            coords.split_once('-').is_some_and(|(start, end)| {
+                [start, end]
+                    .iter()
+                    .all(|pos| !pos.is_empty() && pos.bytes().all(|b| b.is_ascii_digit()))
+            })
+        };
+        let base = name
+            .rsplit_once(':')
+            .filter(|(_, coords)| is_coords(coords))
+            .map(|(base, _)| base)
+            .or_else(|| {
+                [ORIGINAL_RECORD_SUFFIX, self.suffix.as_str()]
+                    .into_iter()
+                    .filter(|suffix| !suffix.is_empty())
+                    .find_map(|suffix| name.strip_suffix(suffix))
+            })?;
+        Some(name.split_at(base.len()))
+    }
+}
+
+impl FromIterator<(String, String)> for RenameMap {
+    fn from_iter<T: IntoIterator<Item = (String, String)>>(iter: T) -> Self {
+        Self {
+            names: iter.into_iter().collect(),
+            suffix: String::new(),
+        }
+    }
+}
+
+/// Output BED writer of introduced misassemblies.
+pub struct TruthWriter<W = File> {
+    writer: bed::Writer<W>,
+    format: TruthFormat,
+    region_names: IntervalMap<Position, String>,
+    renames: RenameMap,
+    /// IDs of the misassemblies of the next record by their type and 0-based start.
+    event_ids: HashMap<(SequenceType, usize), String>,
+    /// Write colored BED9 records for IGV.
+    igv: bool,
+}
+
+impl<W: Write> TruthWriter<W> {
+    /// Write misassemblies to `writer` in the given truth `format`.
+    pub fn new(writer: bed::Writer<W>, format: TruthFormat) -> Self {
+        Self {
+            writer,
+            format,
+            region_names: IntervalMap::new(),
+            event_ids: HashMap::new(),
+            renames: RenameMap::default(),
+            igv: false,
+        }
+    }
+
+    /// Write a track line and colored BED9 records with the type or label as the name for IGV.
+    /// Only the region and ID fields of misasim records are kept and are appended to the name. ex. misjoin;id=misasim.1
+    pub fn with_igv_track(mut self, name: &str, description: &str) -> eyre::Result<Self> {
+        writeln!(
+            self.writer.get_mut(),
+            "track name=\"{name}\" description=\"{description}\" itemRgb=On"
+        )?;
+        self.igv = true;
+        Ok(self)
+    }
+
+    /// Rename records before writing them.
+    pub fn with_rename_map(mut self, renames: RenameMap) -> Self {
+        self.renames = renames;
+        self
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer.into_inner()
+    }
+
+    /// Writer with the same settings writing to memory. Its records are added with [`TruthWriter::append`].
+    pub fn buffer(&self) -> TruthWriter<Vec<u8>> {
+        TruthWriter {
+            writer: bed::Writer::new(Vec::new()),
+            format: self.format,
+            region_names: IntervalMap::new(),
+            renames: self.renames.clone(),
+            event_ids: HashMap::new(),
+            igv: self.igv,
+        }
+    }
+
+    /// Write the records of a buffer from [`TruthWriter::buffer`].
+    pub fn append(&mut self, buffer: TruthWriter<Vec<u8>>) -> eyre::Result<()> {
+        self.writer.get_mut().write_all(&buffer.into_inner())?;
+        Ok(())
+    }
+
+    /// Set the named input regions of the next record written.
+    /// With "--truth-format misasim", the names of regions a misassembly starts in are added as a "region=" field.
+    pub fn set_region_names(&mut self, regions: &Regions) {
+        self.region_names = regions
+            .unsorted_iter()
+            .filter_map(|(interval, region)| Some((interval, region.name.clone()?)))
+            .collect();
+    }
+
+    /// Set the events of the next record written.
+    /// With "--truth-format misasim", the IDs of events are added as an "id=" field. ex. id=misasim.3
+    pub fn set_event_ids(&mut self, events: &[Event]) {
+        self.event_ids = events
+            .iter()
+            .filter_map(|event| {
+                let variant = &event.variant;
+                // Insertions are written at the base before the inserted sequence.
+                let start = match variant {
+                    Variant::Insertion { pos, .. } => pos.saturating_sub(1),
+                    _ => variant.range().start,
+                };
+                Some(((variant.sequence_type(), start), event.id.clone()?))
+            })
+            .collect();
+    }
+
+    /// Write misassemblies of one type in a record.
+    ///
+    /// # Arguments
+    /// * `record_name` - Name of the output record.
+    /// * `mtype` - Type of misassembly.
+    /// * `regions` - Misassembled regions in original coordinates.
+    /// * `misassembled_ranges` - Misassembled ranges in the output record.
+    /// * `seq_len` - Length of the output record.
+    pub fn write_misassembly<R, I>(
+        &mut self,
+        record_name: &str,
+        mtype: SequenceType,
+        regions: I,
+        misassembled_ranges: &[Range<usize>],
+        seq_len: usize,
+    ) -> eyre::Result<()>
+    where
+        R: TryInto<bed::record::Builder<3>>,
+        I: IntoIterator<Item = R>,
+    {
+        let misassembled_ranges = misassembled_ranges
+            .iter()
+            .map(|range| (mtype, range.clone()))
+            .collect_vec();
+        let regions = regions.into_iter().map(|region| (mtype, region));
+        self.write_typed_misassemblies(record_name, regions, &misassembled_ranges, seq_len)
+    }
+
+    /// Write misassemblies of any type in a record.
+    /// Same as [`TruthWriter::write_misassembly`] but each misassembled range has its own type.
+    /// Each region has the type of the misassembled range at the same index.
+    pub fn write_misassemblies<R, I>(
+        &mut self,
+        record_name: &str,
+        regions: I,
+        misassembled_ranges: &[(SequenceType, Range<usize>)],
+        seq_len: usize,
+    ) -> eyre::Result<()>
+    where
+        R: TryInto<bed::record::Builder<3>>,
+        I: IntoIterator<Item = R>,
+    {
+        let regions = misassembled_ranges
+            .iter()
+            .map(|(mtype, _)| *mtype)
+            .zip(regions);
+        self.write_typed_misassemblies(record_name, regions, misassembled_ranges, seq_len)
+    }
+
+    fn write_typed_misassemblies<R, I>(
+        &mut self,
+        record_name: &str,
+        regions: I,
+        misassembled_ranges: &[(SequenceType, Range<usize>)],
+        seq_len: usize,
+    ) -> eyre::Result<()>
+    where
+        R: TryInto<bed::record::Builder<3>>,
+        I: IntoIterator<Item = (SequenceType, R)>,
+    {
+        let record_name = &match self.format {
+            // Misassemblies are in original coordinates so keep the input record name.
+            TruthFormat::Misasim => self.renames.without_suffix(record_name),
+            TruthFormat::Nucflag | TruthFormat::Flagger => self.renames.rename(record_name),
+        };
+        match self.format {
+            TruthFormat::Misasim => {
+                for (mtype, builder) in regions.into_iter().flat_map(|(mtype, r)| {
+                    TryInto::<bed::record::Builder<3>>::try_into(r).map(|builder| (mtype, builder))
+                }) {
+                    let record = builder.set_reference_sequence_name(record_name).build()?;
+                    let record = self.add_fields(record, mtype)?;
+                    self.write_record(&record, mtype.name())?;
+                }
+            }
+            // Output record coordinates with NucFlag's misassembly categories.
+            TruthFormat::Nucflag => {
+                for (mtype, range) in misassembled_ranges {
+                    let Some(category) = mtype.nucflag_category() else {
+                        continue;
+                    };
+                    self.write_label(record_name, misassembled_bases(range, seq_len), category)?;
+                }
+            }
+            // Label every base of the output record. Bases not misassembled are haploid.
+            TruthFormat::Flagger => {
+                let ranges = misassembled_ranges
+                    .iter()
+                    .flat_map(|(mtype, range)| {
+                        mtype
+                            .flagger_label()
+                            .map(|label| (label, misassembled_bases(range, seq_len)))
+                    })
+                    .sorted_by_key(|(_, range)| range.start);
+                let mut pos = 0;
+                for (label, range) in ranges {
+                    let start = range.start.max(pos);
+                    if start >= range.end {
+                        continue;
+                    }
+                    self.write_label(record_name, pos..start, FLAGGER_HAPLOID)?;
+                    self.write_label(record_name, start..range.end, label)?;
+                    pos = range.end;
+                }
+                self.write_label(record_name, pos..seq_len, FLAGGER_HAPLOID)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write a record without misassemblies.
+    pub fn write_unchanged(&mut self, record_name: &str, seq_len: usize) -> eyre::Result<()> {
+        if self.format == TruthFormat::Flagger {
+            let record_name = &self.renames.rename(record_name);
+            self.write_label(record_name, 0..seq_len, FLAGGER_HAPLOID)?;
+        }
+        Ok(())
+    }
+
+    /// Add the names of input regions and the event ID to a misasim truth record.
+    fn add_fields(
+        &self,
+        record: bed::Record<3>,
+        mtype: SequenceType,
+    ) -> eyre::Result<bed::Record<3>> {
+        // Positions of regions are the 0-based start of misassemblies.
+        let start = usize::from(record.start_position()) - 1;
+        let names = Position::new(start)
+            .map(|start| {
+                self.region_names
+                    .overlap(start)
+                    .map(|(_, name)| name)
+                    .unique()
+                    .join(",")
+            })
+            .unwrap_or_default();
+        let event_id = self
+            .event_ids
+            .get(&(mtype, start))
+            .map(|id| format!("{ID_FIELD_PREFIX}{id}"));
+        if names.is_empty() && event_id.is_none() {
+            return Ok(record);
+        }
+        let fields = record
+            .optional_fields()
+            .iter()
+            .cloned()
+            .chain((!names.is_empty()).then(|| format!("{REGION_FIELD_PREFIX}{names}")))
+            .chain(event_id)
+            .collect_vec();
+        Ok(bed::Record::<3>::builder()
+            .set_reference_sequence_name(record.reference_sequence_name())
+            .set_start_position(record.start_position())
+            .set_end_position(record.end_position())
+            .set_optional_fields(OptionalFields::from(fields))
+            .build()?)
+    }
+
+    fn write_label(
+        &mut self,
+        record_name: &str,
+        range: Range<usize>,
+        label: &str,
+    ) -> eyre::Result<()> {
+        if range.is_empty() {
+            return Ok(());
+        }
+        let record = bed::Record::<3>::builder()
+            .set_reference_sequence_name(record_name)
+            .set_start_position(Position::new(range.start + 1).unwrap())
+            .set_end_position(Position::new(range.end).unwrap())
+            .set_optional_fields(OptionalFields::from(vec![label.to_string()]))
+            .build()?;
+        self.write_record(&record, label)
+    }
+
+    /// Write a record as is or as a colored BED9 record named by its type or label for IGV.
+    fn write_record(&mut self, record: &bed::Record<3>, label: &str) -> eyre::Result<()> {
+        if !self.igv {
+            self.writer.write_record(record)?;
+            return Ok(());
+        }
+        let name = std::iter::once(label)
+            .chain(
+                record
+                    .optional_fields()
+                    .iter()
+                    .map(String::as_str)
+                    .filter(|field| {
+                        field.starts_with(REGION_FIELD_PREFIX) || field.starts_with(ID_FIELD_PREFIX)
+                    }),
+            )
+            .join(";");
+        let start = usize::from(record.start_position()) - 1;
+        let end = usize::from(record.end_position());
+        let fields = [
+            name,
+            "0".to_owned(),
+            ".".to_owned(),
+            start.to_string(),
+            end.to_string(),
+            igv_color(label).to_owned(),
+        ];
+        let record = bed::Record::<3>::builder()
+            .set_reference_sequence_name(record.reference_sequence_name())
+            .set_start_position(record.start_position())
+            .set_end_position(record.end_position())
+            .set_optional_fields(OptionalFields::from(fields.to_vec()))
+            .build()?;
+        self.writer.write_record(&record)?;
+        Ok(())
+    }
+}
+
+/// RGB color of a misassembly type, NucFlag category, or Flagger label in IGV.
+fn igv_color(label: &str) -> &'static str {
+    match label {
+        "misjoin" | "Err" => "228,26,28",
+        "gap" => "0,0,0",
+        "false_duplication" | "false_dupe" | "Dup" => "55,126,184",
+        "inversion" => "77,175,74",
+        "insertion" | "indel" => "152,78,163",
+        "break" => "255,127,0",
+        _ => "190,190,190",
+    }
+}
+
+/// Write an IGV session loading a sequence file as the genome and the other files as tracks.
+/// Paths are made absolute so the session can be opened from anywhere.
+pub fn write_igv_session(
+    mut writer: impl Write,
+    genome: &Path,
+    tracks: &[&Path],
+) -> eyre::Result<()> {
+    let absolute = |path: &Path| -> eyre::Result<String> {
+        let path = std::path::absolute(path)?;
+        Ok(path
+            .to_string_lossy()
+            .replace('&', "&amp;")
+            .replace('"', "&quot;"))
+    };
+    writeln!(
+        writer,
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>"#
+    )?;
+    writeln!(
+        writer,
+        r#"<Session genome="{}" version="8">"#,
+        absolute(genome)?
+    )?;
+    writeln!(writer, "    <Resources>")?;
+    for track in tracks {
+        writeln!(writer, r#"        <Resource path="{}"/>"#, absolute(track)?)?;
+    }
+    writeln!(writer, "    </Resources>")?;
+    writeln!(writer, "</Session>")?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Fastq quality of correctly assembled bases. Q40
+const HIGH_QUALITY: u8 = b'I';
+/// Fastq quality of misassembled bases. Q5
+const LOW_QUALITY: u8 = b'&';
+
+/// Write the fastq qualities of `length` bases with low quality misassembled bases.
+fn write_qualities(
+    writer: &mut impl Write,
+    length: usize,
+    misassembled_ranges: &[Range<usize>],
+) -> eyre::Result<()> {
+    const BUF_SIZE: usize = 1 << 16;
+    let mut write_run = |quality: u8, mut count: usize| -> eyre::Result<()> {
+        let buf = [quality; BUF_SIZE];
+        while count != 0 {
+            let n = count.min(BUF_SIZE);
+            writer.write_all(&buf[..n])?;
+            count -= n;
+        }
+        Ok(())
+    };
+    let mut pos = 0;
+    for range in misassembled_ranges
+        .iter()
+        .map(|range| misassembled_bases(range, length))
+        .sorted_by_key(|range| range.start)
+    {
+        if range.end <= pos {
+            continue;
+        }
+        write_run(HIGH_QUALITY, range.start.saturating_sub(pos))?;
+        write_run(LOW_QUALITY, range.end - range.start.max(pos))?;
+        pos = range.end;
+    }
+    write_run(HIGH_QUALITY, length - pos)
+}
+
+/// Write the expected copy number of a record of `length` bases as bedGraph records in original coordinates.
+/// Deletions and gaps have a copy number of 0 and duplications their number of copies. All other bases have 1.
+pub fn write_copy_number(
+    writer: &mut impl Write,
+    name: &str,
+    length: usize,
+    variants: &[Variant],
+) -> eyre::Result<()> {
+    let changes = variants
+        .iter()
+        .filter_map(|variant| match variant {
+            Variant::Deletion(range) | Variant::Gap(range) => Some((range.clone(), 0)),
+            Variant::Duplication { range, count } => Some((range.clone(), *count)),
+            _ => None,
+        })
+        .sorted_by_key(|(range, _)| range.start);
+    // Merge adjacent intervals with the same copy number.
+    let mut intervals: Vec<(Range<usize>, usize)> = vec![];
+    let mut add_interval = |range: Range<usize>, copy_number: usize| {
+        if range.is_empty() {
+            return;
+        }
+        match intervals.last_mut() {
+            Some((last, last_copy_number)) if *last_copy_number == copy_number => {
+                last.end = range.end
+            }
+            _ => intervals.push((range, copy_number)),
+        }
+    };
+    let mut pos = 0;
+    for (range, copy_number) in changes {
+        let (start, end) = (range.start.max(pos), range.end.min(length));
+        if start >= end {
+            continue;
+        }
+        add_interval(pos..start, 1);
+        add_interval(start..end, copy_number);
+        pos = end;
+    }
+    add_interval(pos..length, 1);
+    for (range, copy_number) in intervals {
+        writeln!(
+            writer,
+            "{name}\t{}\t{}\t{copy_number}",
+            range.start, range.end
+        )?;
+    }
+    Ok(())
+}
+
+enum SeqWriter<W: Write> {
+    Fasta(W),
+    Fastq(W),
+}
+
+/// Output sequence writer. Writes fasta or fastq.
+///
+/// Optionally builds a faidx of every record written so the output can be indexed without rereading it.
+pub struct FastaWriter<W: Write> {
+    writer: SeqWriter<W>,
+    line_bases: usize,
+    offset: u64,
+    index: Option<fasta::fai::Index>,
+    split_dir: Option<PathBuf>,
+    /// Overwrite existing files in `split_dir`.
+    force: bool,
+    renames: RenameMap,
+}
+
+impl<W: Write> FastaWriter<W> {
+    /// Create a writer wrapping sequence lines at `line_bases`. A `line_bases` of 0 disables wrapping.
+    /// Fastq output is never wrapped or indexed.
+    pub fn new(inner: W, format: OutputFormat, line_bases: usize, build_index: bool) -> Self {
+        let line_bases = if line_bases == 0 {
+            usize::MAX
+        } else {
+            line_bases
+        };
+        let writer = match format {
+            OutputFormat::Fasta => SeqWriter::Fasta(inner),
+            OutputFormat::Fastq => SeqWriter::Fastq(inner),
+        };
+        Self {
+            writer,
+            line_bases,
+            offset: 0,
+            index: build_index.then(Vec::new),
+            split_dir: None,
+            force: false,
+            renames: RenameMap::default(),
+        }
+    }
+
+    fn format(&self) -> OutputFormat {
+        match self.writer {
+            SeqWriter::Fasta(_) => OutputFormat::Fasta,
+            SeqWriter::Fastq(_) => OutputFormat::Fastq,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        let (SeqWriter::Fasta(inner) | SeqWriter::Fastq(inner)) = self.writer;
+        inner
+    }
+
+    /// Write each record to its own file, `{split_dir}/{name}.{fa,fq}`, instead of the inner writer.
+    /// Existing files are only overwritten if `force`.
+    pub fn with_split_dir(mut self, split_dir: impl Into<PathBuf>, force: bool) -> Self {
+        self.split_dir = Some(split_dir.into());
+        self.force = force;
+        self
+    }
+
+    /// Rename records before writing them.
+    pub fn with_rename_map(mut self, renames: RenameMap) -> Self {
+        self.renames = renames;
+        self
+    }
+
+    /// Writer with the same settings writing to memory. Its records are added with [`FastaWriter::append`].
+    pub fn buffer(&self) -> FastaWriter<Vec<u8>> {
+        let writer = match self.writer {
+            SeqWriter::Fasta(_) => SeqWriter::Fasta(Vec::new()),
+            SeqWriter::Fastq(_) => SeqWriter::Fastq(Vec::new()),
+        };
+        FastaWriter {
+            writer,
+            line_bases: self.line_bases,
+            offset: 0,
+            index: self.index.as_ref().map(|_| Vec::new()),
+            split_dir: self.split_dir.clone(),
+            force: self.force,
+            renames: self.renames.clone(),
+        }
+    }
+
+    /// Write the records of a buffer from [`FastaWriter::buffer`] and index them after the records written so far.
+    pub fn append(&mut self, buffer: FastaWriter<Vec<u8>>) -> eyre::Result<()> {
+        if let (Some(index), Some(buffer_index)) = (self.index.as_mut(), buffer.index) {
+            index.extend(buffer_index.into_iter().map(|record| {
+                fasta::fai::Record::new(
+                    record.name(),
+                    record.length(),
+                    self.offset + record.offset(),
+                    record.line_bases(),
+                    record.line_width(),
+                )
+            }));
+        }
+        self.offset += buffer.offset;
+        let (SeqWriter::Fasta(buf) | SeqWriter::Fastq(buf)) = buffer.writer;
+        let (SeqWriter::Fasta(writer) | SeqWriter::Fastq(writer)) = &mut self.writer;
+        writer.write_all(&buf)?;
+        Ok(())
+    }
+
+    /// Write a record without misassemblies.
+    pub fn write_record(&mut self, record: &fasta::Record) -> eyre::Result<()> {
+        self.write_misassembled_record(record, &[])
+    }
+
+    /// Write a record with the given ranges of misassembled bases.
+    /// * With fastq output, these bases are given a low quality. Empty ranges, like misjoins, lower the quality of their flanking bases.
+    pub fn write_misassembled_record(
+        &mut self,
+        record: &fasta::Record,
+        misassembled_ranges: &[Range<usize>],
+    ) -> eyre::Result<()> {
+        let seq = record.sequence().as_ref();
+        self.write_record_chunks(
+            record.definition(),
+            seq.len(),
+            std::iter::once(eyre::Ok(seq)),
+            misassembled_ranges,
+        )
+    }
+
+    /// Write a record of `length` bases with its sequence streamed in chunks.
+    /// The whole sequence is never held in memory.
+    pub fn write_record_chunks<S: AsRef<[u8]>>(
+        &mut self,
+        definition: &Definition,
+        length: usize,
+        chunks: impl IntoIterator<Item = eyre::Result<S>>,
+        misassembled_ranges: &[Range<usize>],
+    ) -> eyre::Result<()> {
+        let renamed;
+        let definition = if self.renames.is_empty() {
+            definition
+        } else {
+            renamed = Definition::new(
+                self.renames.rename(std::str::from_utf8(definition.name())?),
+                definition.description().map(<[u8]>::to_vec),
+            );
+            &renamed
+        };
+        if let Some(split_dir) = self.split_dir.as_ref() {
+            // Avoid path separators in record names creating subdirectories.
+            let name = std::str::from_utf8(definition.name())?.replace('/', "_");
+            let ext = match self.format() {
+                OutputFormat::Fasta => "fa",
+                OutputFormat::Fastq => "fq",
+            };
+            let outfile = split_dir.join(format!("{name}.{ext}"));
+            let mut writer = FastaWriter::new(
+                create_outfile(&outfile, self.force)?,
+                self.format(),
+                self.line_bases,
+                self.index.is_some(),
+            );
+            writer.write_record_chunks(definition, length, chunks, misassembled_ranges)?;
+            if writer.index.is_some() {
+                writer.write_index(&outfile)?;
+            }
+            return Ok(());
+        }
+        // >{name}[ {description}]
+        let definition_line = definition.to_string();
+        let is_fastq = matches!(self.format(), OutputFormat::Fastq);
+        let (writer, line_bases) = match &mut self.writer {
+            SeqWriter::Fasta(writer) => (writer, self.line_bases),
+            SeqWriter::Fastq(writer) => (writer, usize::MAX),
+        };
+        if is_fastq {
+            writer.write_all(b"@")?;
+            writer.write_all(&definition_line.as_bytes()[1..])?;
+        } else {
+            writer.write_all(definition_line.as_bytes())?;
+        }
+        writer.write_all(b"\n")?;
+
+        // Wrap lines across chunks.
+        let (mut written, mut line_pos) = (0, 0);
+        for chunk in chunks {
+            let chunk = chunk?;
+            let mut bases = chunk.as_ref();
+            written += bases.len();
+            while !bases.is_empty() {
+                let (line, rest) = bases.split_at((line_bases - line_pos).min(bases.len()));
+                writer.write_all(line)?;
+                line_pos += line.len();
+                if line_pos == line_bases {
+                    writer.write_all(b"\n")?;
+                    line_pos = 0;
+                }
+                bases = rest;
+            }
+        }
+        if line_pos != 0 {
+            writer.write_all(b"\n")?;
+        }
+        if written != length {
+            bail!(
+                "Expected {length} bases for {definition_line:?} but wrote {written}.",
+                definition_line = &definition_line[1..]
+            )
+        }
+
+        if is_fastq {
+            if length == 0 {
+                writer.write_all(b"\n")?;
+            }
+            writer.write_all(b"+\n")?;
+            write_qualities(writer, length, misassembled_ranges)?;
+            writer.write_all(b"\n")?;
+            return Ok(());
+        }
+
+        // Each line of sequence is followed by a newline.
+        let seq_lines = length.div_ceil(self.line_bases);
+        let seq_offset = self.offset + definition_line.len() as u64 + 1;
+        if let Some(index) = self.index.as_mut() {
+            // Single line sequences are indexed by their length. Same as samtools faidx.
+            let line_bases = self.line_bases.min(length) as u64;
+            index.push(fasta::fai::Record::new(
+                definition.name(),
+                length as u64,
+                seq_offset,
+                line_bases,
+                line_bases + 1,
+            ));
+        }
+        self.offset = seq_offset + (length + seq_lines) as u64;
+        Ok(())
+    }
+
+    /// Write the faidx of all records written so far to `{outfile}.fai`.
+    pub fn write_index(&self, outfile: impl AsRef<Path>) -> eyre::Result<()> {
+        let Some(index) = self.index.as_ref() else {
+            bail!("Output fasta index not built.")
+        };
+        let mut fai_fname = outfile.as_ref().as_os_str().to_owned();
+        fai_fname.push(".fai");
+        let mut writer = File::create(&fai_fname).map(fasta::fai::Writer::new)?;
+        writer.write_index(index)?;
+        log::info!("Wrote output fasta index to {fai_fname:?}");
+        Ok(())
+    }
+}
+
+impl FastaWriter<OutputStream> {
+    /// Finish the output sequence file and, if given `outfile`, write its faidx to `{outfile}.fai`.
+    /// Bgzipped files also get a gzi of their blocks, `{outfile}.gzi`.
+    pub fn finish(self, index_outfile: Option<&Path>) -> eyre::Result<()> {
+        if let Some(outfile) = index_outfile {
+            self.write_index(outfile)?;
+        }
+        let output_fa = self.into_inner();
+        let is_bgzipped = matches!(output_fa, OutputStream::Bgzip(_));
+        output_fa.finish()?;
+        // Blocks are only known once the file is finished.
+        if let Some(outfile) = index_outfile.filter(|_| is_bgzipped) {
+            let mut gzi_fname = outfile.as_os_str().to_owned();
+            gzi_fname.push(".gzi");
+            let index = index_bgzf_blocks(BufReader::new(File::open(outfile)?))?;
+            write_gzi(BufWriter::new(File::create(&gzi_fname)?), &index)?;
+            log::info!("Wrote output gzi to {gzi_fname:?}");
+        }
+        Ok(())
+    }
+}
+
+/// Compressed and uncompressed offsets of the start of every bgzf block. Same as "bgzip --reindex".
+/// * https://samtools.github.io/hts-specs/SAMv1.pdf (4.1 The BGZF compression format)
+pub fn index_bgzf_blocks(mut reader: impl BufRead) -> eyre::Result<bgzf::gzi::Index> {
+    let mut index = vec![(0, 0)];
+    let (mut compressed, mut uncompressed) = (0, 0);
+    while !reader.fill_buf()?.is_empty() {
+        // ID1, ID2, CM, FLG, MTIME, XFL, OS, and XLEN.
+        let mut header = [0; 12];
+        reader.read_exact(&mut header)?;
+        if header[..4] != [GZIP_MAGIC[0], GZIP_MAGIC[1], GZIP_MAGIC[2], 0x04] {
+            bail!("No bgzf block at {compressed}.")
+        }
+        let xlen = u16::from_le_bytes([header[10], header[11]]);
+        let mut extra = vec![0; usize::from(xlen)];
+        reader.read_exact(&mut extra)?;
+        // The block size less 1 is in the "BC" subfield.
+        let mut subfields = extra.as_slice();
+        let mut block_size = None;
+        while let [si1, si2, slen1, slen2, rest @ ..] = subfields {
+            let slen = usize::from(u16::from_le_bytes([*slen1, *slen2]));
+            if let (b'B', b'C', [bsize1, bsize2, ..]) = (si1, si2, rest) {
+                block_size = Some(u64::from(u16::from_le_bytes([*bsize1, *bsize2])) + 1);
+            }
+            subfields = rest.get(slen..).unwrap_or_default();
+        }
+        let block_size =
+            block_size.with_context(|| format!("No bgzf block size at {compressed}."))?;
+        // Skip the compressed data and CRC32 to the uncompressed size.
+        let data_size = block_size
+            .checked_sub(header.len() as u64 + u64::from(xlen) + 4)
+            .with_context(|| format!("Invalid bgzf block size at {compressed}."))?;
+        std::io::copy(&mut (&mut reader).take(data_size), &mut std::io::sink())?;
+        let mut isize = [0; 4];
+        reader.read_exact(&mut isize)?;
+        compressed += block_size;
+        uncompressed += u64::from(u32::from_le_bytes(isize));
+        // The EOF block is empty.
+        if isize != [0; 4] {
+            index.push((compressed, uncompressed));
+        }
+    }
+    Ok(index)
+}
+
+/// Write a gzi. The number of entries followed by each entry's compressed and uncompressed offsets as little-endian integers.
+/// The first block, at (0, 0), isn't written.
+pub fn write_gzi(mut writer: impl Write, index: &bgzf::gzi::Index) -> eyre::Result<()> {
+    let entries = index.get(1..).unwrap_or_default();
+    writer.write_all(&(entries.len() as u64).to_le_bytes())?;
+    for (compressed, uncompressed) in entries {
+        writer.write_all(&compressed.to_le_bytes())?;
+        writer.write_all(&uncompressed.to_le_bytes())?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Output sequence stream.
+pub enum OutputStream {
+    Plain(Box<dyn Write>),
+    Bgzip(bgzf::MultithreadedWriter<File>),
+}
+
+impl OutputStream {
+    /// Flush the stream. Bgzipped streams are also terminated with an EOF block.
+    pub fn finish(self) -> eyre::Result<()> {
+        match self {
+            OutputStream::Plain(mut writer) => writer.flush()?,
+            OutputStream::Bgzip(mut writer) => {
+                writer.finish()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Write for OutputStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            OutputStream::Plain(writer) => writer.write(buf),
+            OutputStream::Bgzip(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            OutputStream::Plain(writer) => writer.flush(),
+            OutputStream::Bgzip(writer) => writer.flush(),
+        }
+    }
+}
 
+/// Add the suffix ".rep{replicate}" to an output path before its extension, ignoring ".gz".
+/// ex. out.fa.gz -> out.rep1.fa.gz
+pub fn replicate_path(path: &Path, replicate: usize) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let (base, gz) = match file_name.strip_suffix(".gz") {
+        Some(base) => (base, ".gz"),
+        None => (file_name.as_str(), ""),
+    };
+    let new_name = match base.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => format!("{stem}.rep{replicate}.{ext}{gz}"),
+        _ => format!("{base}.rep{replicate}{gz}"),
+    };
+    path.with_file_name(new_name)
+}
+
+/// Create an output file. Existing files are only overwritten if `force`.
+/// Other existing paths like "/dev/stdout" are always written to.
+pub fn create_outfile(path: impl AsRef<Path>, force: bool) -> eyre::Result<File> {
+    let path = path.as_ref();
+    if !force && path.is_file() {
+        bail!("Output file {path:?} already exists. Use --force to overwrite it.")
+    }
+    File::create(path).with_context(|| format!("Cannot create output file: {path:?}"))
+}
+
+/// Get output sequence and truth bed writers. Sequences are discarded if `dry_run`.
+/// Output sequence files ending in ".gz" are bgzipped at `compression_level` with `compression_threads` threads.
+/// Existing output files are only overwritten if `force`.
+#[allow(clippy::too_many_arguments)]
 pub fn get_outfile_writers(
     outfile: Option<PathBuf>,
     outbedfile: Option<PathBuf>,
+    truth_format: TruthFormat,
+    dry_run: bool,
+    compression_threads: usize,
+    compression_level: u8,
+    force: bool,
 ) -> eyre::Result<Outfiles> {
-    let output_fa: Box<dyn Write> = if let Some(outfile) = outfile {
-        Box::new(File::create(outfile)?)
+    let output_fa = if dry_run {
+        OutputStream::Plain(Box::new(std::io::sink()))
+    } else if let Some(outfile) = outfile {
+        let file = create_outfile(&outfile, force)?;
+        if outfile.extension().is_some_and(|ext| ext == "gz") {
+            let worker_count = NonZeroUsize::new(compression_threads)
+                .context("Number of compression threads must be at least 1.")?;
+            let compression_level = CompressionLevel::new(compression_level)
+                .context("Compression level must be between 0 and 9.")?;
+            OutputStream::Bgzip(
+                bgzf::multithreaded_writer::Builder::default()
+                    .set_compression_level(compression_level)
+                    .set_worker_count(worker_count)
+                    .build_from_writer(file),
+            )
+        } else {
+            OutputStream::Plain(Box::new(file))
+        }
     } else {
-        Box::new(stdout().lock())
+        OutputStream::Plain(Box::new(stdout().lock()))
     };
     let output_bed = outbedfile
-        .and_then(|f| File::create(f).ok())
-        .map(bed::Writer::new);
+        .map(|f| create_outfile(f, force))
+        .transpose()?
+        .map(bed::Writer::new)
+        .map(|writer| TruthWriter::new(writer, truth_format));
 
     Ok((output_fa, output_bed))
 }
 
+/// Reader of an input sequence file.
 pub enum FastaReader {
     Bgzip(fasta::io::Reader<IndexedReader<File>>),
     Standard(fasta::io::Reader<BufReader<File>>),
+    /// Entire decompressed file held in memory.
+    Buffered(fasta::io::Reader<Cursor<Arc<[u8]>>>),
+    /// Memory-mapped uncompressed file.
+    Mmap(Arc<Mmap>),
 }
 
+/// Indexed input sequence file.
 pub struct Fasta {
     pub(crate) reader: FastaReader,
-    pub(crate) index: fasta::fai::Index,
+    pub(crate) index: Arc<fasta::fai::Index>,
+    /// Position of each record's definition line. Found when its description is first read.
+    definition_offsets: HashMap<Vec<u8>, u64>,
+    /// Input file and its bgzip index to reopen. `None` if held in memory.
+    source: Option<(PathBuf, Option<bgzf::gzi::Index>)>,
 }
 
 impl Fasta {
-    pub fn new(infile: impl AsRef<Path>) -> eyre::Result<Self> {
-        let (index, gzi) = Self::get_faidx(&infile)?;
-        let fh = Self::read_fa(&infile, gzi.as_ref())?;
-        Ok(Self { reader: fh, index })
+    /// Read an indexed fasta file. Uncompressed files are memory-mapped if `mmap`.
+    /// Indexes are read from `fai` and `gzi` if given, otherwise next to `infile`.
+    pub fn new(
+        infile: impl AsRef<Path>,
+        mmap: bool,
+        fai: Option<&Path>,
+        gzi: Option<&Path>,
+    ) -> eyre::Result<Self> {
+        // Regular gzip has no random access so decompress the whole file.
+        if Self::is_gzip_not_bgzf(&infile)? {
+            log::info!(
+                "{:?} is gzipped but not bgzipped. Decompressing into memory.",
+                infile.as_ref()
+            );
+            if fai.is_some() || gzi.is_some() {
+                log::warn!("Ignoring index files of gzipped input. It's indexed in memory.");
+            }
+            let mut buf = Vec::new();
+            MultiGzDecoder::new(File::open(&infile)?).read_to_end(&mut buf)?;
+            return Self::from_bytes(buf);
+        }
+        let (index, gzi) = Self::get_faidx(&infile, fai, gzi)?;
+        let fh = Self::read_fa(&infile, gzi.as_ref(), mmap)?;
+        Ok(Self {
+            source: Some((infile.as_ref().to_path_buf(), gzi)),
+            ..Self::from_reader(fh, index)
+        })
     }
 
+    fn from_reader(reader: FastaReader, index: fasta::fai::Index) -> Self {
+        Self {
+            reader,
+            index: Arc::new(index),
+            definition_offsets: HashMap::new(),
+            source: None,
+        }
+    }
+
+    /// Open another reader of the same input to read from another thread.
+    /// Files are reopened and sequences held in memory or memory-mapped are shared.
+    pub fn try_clone(&self) -> eyre::Result<Self> {
+        let reader = match (&self.reader, &self.source) {
+            (FastaReader::Buffered(reader), _) => FastaReader::Buffered(fasta::io::Reader::new(
+                Cursor::new(reader.get_ref().get_ref().clone()),
+            )),
+            (FastaReader::Mmap(mmap), _) => FastaReader::Mmap(mmap.clone()),
+            (_, Some((infile, gzi))) => Self::read_fa(infile, gzi.as_ref(), false)?,
+            (_, None) => bail!("Cannot reopen the input sequence file."),
+        };
+        Ok(Self {
+            reader,
+            index: self.index.clone(),
+            definition_offsets: self.definition_offsets.clone(),
+            source: self.source.clone(),
+        })
+    }
+
+    /// Read an entire fasta from stdin into memory. Gzipped or bgzipped input is decompressed.
+    pub fn from_stdin() -> eyre::Result<Self> {
+        Self::read_to_memory(stdin().lock())
+    }
+
+    /// Read an entire fasta file into memory without any index files. Gzipped or bgzipped input is decompressed.
+    pub fn from_file_in_memory(infile: impl AsRef<Path>) -> eyre::Result<Self> {
+        let infile = infile.as_ref();
+        Self::read_to_memory(File::open(infile).with_context(|| format!("Cannot open {infile:?}"))?)
+    }
+
+    fn read_to_memory(mut reader: impl Read) -> eyre::Result<Self> {
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf)?;
+        if buf.starts_with(&GZIP_MAGIC) {
+            let mut decompressed = Vec::new();
+            MultiGzDecoder::new(&buf[..]).read_to_end(&mut decompressed)?;
+            buf = decompressed;
+        }
+        Self::from_bytes(buf)
+    }
+
+    /// Index and read an uncompressed fasta held in memory.
+    pub fn from_bytes(buf: Vec<u8>) -> eyre::Result<Self> {
+        let mut records = Vec::new();
+        let mut indexer = fasta::io::Indexer::new(Cursor::new(&buf));
+        while let Some(record) = indexer.index_record()? {
+            records.push(record);
+        }
+        Ok(Self::from_reader(
+            FastaReader::Buffered(fasta::io::Reader::new(Cursor::new(buf.into()))),
+            fasta::fai::Index::from(records),
+        ))
+    }
+
+    /// Check gzip magic bytes and for the BGZF extra subfield, `BC`.
+    /// * https://samtools.github.io/hts-specs/SAMv1.pdf (4.1 The BGZF compression format)
+    fn is_gzip_not_bgzf(fa: &impl AsRef<Path>) -> eyre::Result<bool> {
+        let mut header = [0u8; 14];
+        let mut fh = File::open(fa)?;
+        let n = fh.read(&mut header)?;
+        let is_gzip = header[..n].starts_with(&GZIP_MAGIC);
+        if !is_gzip {
+            return Ok(false);
+        }
+        let has_extra = header[3] & 0x04 != 0;
+        let is_bgzf = n == header.len() && has_extra && header[12..14] == *b"BC";
+        Ok(!is_bgzf)
+    }
+
+    /// Names and lengths of all records in index order.
     pub fn lengths(&self) -> Vec<(String, u64)> {
         self.index
             .iter()
@@ -60,16 +1167,40 @@ impl Fasta {
             .collect()
     }
 
+    /// Read the faidx and, if bgzipped, the gzi of a fasta file. Paths of given indexes must exist.
+    /// Otherwise, they're `{fa}.fai` and `{fa}.gzi`. A missing faidx is generated instead.
     fn get_faidx(
         fa: &impl AsRef<Path>,
+        fai: Option<&Path>,
+        gzi: Option<&Path>,
     ) -> eyre::Result<(fasta::fai::Index, Option<bgzf::gzi::Index>)> {
         // https://www.ginkgobioworks.com/2023/03/17/even-more-rapid-retrieval-from-very-large-files-with-rust/
         let fa_path = fa.as_ref().canonicalize()?;
-        let is_bgzipped = fa_path.extension().and_then(|e| e.to_str()) == Some("gz");
-        let fai_fname = fa_path.with_extension(if is_bgzipped { "gz.fai" } else { "fa.fai" });
-        let fai = fasta::fai::read(fai_fname);
+        let is_bgzipped = matches!(
+            fa_path.extension().and_then(|e| e.to_str()),
+            Some("gz" | "bgz")
+        );
+        let fai = match fai {
+            Some(fai_fname) => Ok(fasta::fai::read(fai_fname)
+                .with_context(|| format!("Failed to read fai: {fai_fname:?}"))?),
+            // Append rather than replace the extension. ex. sample.fasta -> sample.fasta.fai
+            None => {
+                let mut fai_fname = fa_path.as_os_str().to_owned();
+                fai_fname.push(".fai");
+                fasta::fai::read(fai_fname)
+            }
+        };
+        if !is_bgzipped && gzi.is_some() {
+            log::warn!("Ignoring gzi of uncompressed {fa_path:?}.");
+        }
         if is_bgzipped {
-            let index_reader = bgzf::indexed_reader::Builder::default()
+            let mut builder = bgzf::indexed_reader::Builder::default();
+            if let Some(gzi_fname) = gzi {
+                let gzi = bgzf::gzi::read(gzi_fname)
+                    .with_context(|| format!("Failed to read gzi: {gzi_fname:?}"))?;
+                builder = builder.set_index(gzi);
+            }
+            let index_reader = builder
                 .build_from_path(fa)
                 .with_context(|| format!("Failed to read gzi for {fa_path:?}"))?;
             let gzi = index_reader.index().clone();
@@ -95,27 +1226,178 @@ impl Fasta {
         }
     }
 
-    pub fn fetch(&mut self, ctg_name: &str, start: u32, stop: u32) -> eyre::Result<fasta::Record> {
-        let start_pos = noodles::core::Position::new(start.clamp(1, u32::MAX) as usize).unwrap();
-        let stop_pos = noodles::core::Position::new(stop.clamp(1, u32::MAX) as usize).unwrap();
-        let region = noodles::core::Region::new(ctg_name, start_pos..=stop_pos);
+    /// Fetch a region of a record. The original description of the record is kept.
+    /// Whole records also keep their original name.
+    pub fn fetch(
+        &mut self,
+        ctg_name: &str,
+        start: usize,
+        stop: usize,
+    ) -> eyre::Result<fasta::Record> {
+        let start_pos = Position::new(start.max(1)).unwrap();
+        let stop_pos = Position::new(stop.max(1)).unwrap();
+        let record = self.query(&noodles::core::Region::new(ctg_name, start_pos..=stop_pos))?;
+        let description = self.description(ctg_name)?;
+        // Keep the original name if the whole record was fetched.
+        let is_whole_record = start <= 1
+            && self
+                .index
+                .iter()
+                .find(|rec| rec.name() == ctg_name.as_bytes())
+                .is_some_and(|rec| stop as u64 >= rec.length());
+        let name = if is_whole_record {
+            ctg_name.as_bytes()
+        } else {
+            record.name()
+        };
+        Ok(fasta::Record::new(
+            Definition::new(name, description),
+            record.sequence().clone(),
+        ))
+    }
+
+    /// Fetch only the given 0-based `ranges` of a whole record of `length` bases without its description.
+    /// Other bases are zeroed. Their memory is never written so is only allocated by the OS if read.
+    pub fn fetch_ranges(
+        &mut self,
+        ctg_name: &str,
+        length: usize,
+        ranges: &[Range<usize>],
+    ) -> eyre::Result<fasta::Record> {
+        let mut seq = vec![0; length];
+        for range in ranges.iter().filter(|range| !range.is_empty()) {
+            let region = noodles::core::Region::new(
+                ctg_name,
+                Position::try_from(range.start + 1)?..=Position::try_from(range.end)?,
+            );
+            let record = self.query(&region)?;
+            match seq.get_mut(range.clone()) {
+                Some(bases) if bases.len() == record.sequence().len() => {
+                    bases.copy_from_slice(record.sequence().as_ref())
+                }
+                _ => bail!("Range out of bounds of {ctg_name}: {range:?}"),
+            }
+        }
+        // The record isn't written so its description isn't needed.
+        Ok(fasta::Record::new(
+            Definition::new(ctg_name, None),
+            Sequence::from(seq),
+        ))
+    }
+
+    /// Fetch the sequence of a record of `length` bases in chunks of at most `FETCH_CHUNK_SIZE` bases.
+    pub fn fetch_chunks<'a>(
+        &'a mut self,
+        ctg_name: &'a str,
+        length: usize,
+    ) -> impl Iterator<Item = eyre::Result<Sequence>> + 'a {
+        (0..length).step_by(FETCH_CHUNK_SIZE).map(move |start| {
+            let start_pos = Position::try_from(start + 1)?;
+            let stop_pos = Position::try_from((start + FETCH_CHUNK_SIZE).min(length))?;
+            let record = self.query(&noodles::core::Region::new(ctg_name, start_pos..=stop_pos))?;
+            Ok(record.sequence().clone())
+        })
+    }
+
+    /// Write a whole record without holding its sequence in memory.
+    pub fn copy_record<W: Write>(
+        &mut self,
+        ctg_name: &str,
+        length: usize,
+        writer_fa: &mut FastaWriter<W>,
+    ) -> eyre::Result<()> {
+        let definition = Definition::new(ctg_name, self.description(ctg_name)?);
+        // Write lines straight from the mapped file.
+        if let FastaReader::Mmap(mmap) = &self.reader {
+            let index_record = find_index_record(&self.index, ctg_name.as_bytes())?;
+            return writer_fa.write_record_chunks(
+                &definition,
+                length,
+                mmap_lines(mmap, index_record, 0..length),
+                &[],
+            );
+        }
+        writer_fa.write_record_chunks(
+            &definition,
+            length,
+            self.fetch_chunks(ctg_name, length),
+            &[],
+        )
+    }
+
+    fn query(&mut self, region: &noodles::core::Region) -> eyre::Result<fasta::Record> {
+        Ok(match &mut self.reader {
+            FastaReader::Bgzip(reader) => reader.query(&self.index, region)?,
+            FastaReader::Standard(reader) => reader.query(&self.index, region)?,
+            FastaReader::Buffered(reader) => reader.query(&self.index, region)?,
+            FastaReader::Mmap(mmap) => query_mmap(mmap, &self.index, region)?,
+        })
+    }
+
+    /// Get the description of a record from its definition line.
+    pub fn description(&mut self, ctg_name: &str) -> eyre::Result<Option<Vec<u8>>> {
+        let pos = match self.definition_offsets.get(ctg_name.as_bytes()) {
+            Some(pos) => *pos,
+            None => {
+                // The definition line is the line before the record's sequence.
+                let offset = find_index_record(&self.index, ctg_name.as_bytes())?.offset();
+                let pos = match &mut self.reader {
+                    FastaReader::Bgzip(reader) => find_definition_before(reader, offset)?,
+                    FastaReader::Standard(reader) => find_definition_before(reader, offset)?,
+                    FastaReader::Buffered(reader) => find_definition_before(reader, offset)?,
+                    FastaReader::Mmap(mmap) => find_definition_before(
+                        &mut fasta::io::Reader::new(Cursor::new(&mmap[..])),
+                        offset,
+                    )?,
+                }
+                .with_context(|| format!("No definition line found for {ctg_name}."))?;
+                self.definition_offsets
+                    .insert(ctg_name.as_bytes().to_vec(), pos);
+                pos
+            }
+        };
+        let mut buf = String::new();
         match &mut self.reader {
-            FastaReader::Bgzip(reader) => Ok(reader.query(&self.index, &region)?),
-            FastaReader::Standard(reader) => Ok(reader.query(&self.index, &region)?),
+            FastaReader::Bgzip(reader) => read_definition_at(reader, pos, &mut buf)?,
+            FastaReader::Standard(reader) => read_definition_at(reader, pos, &mut buf)?,
+            FastaReader::Buffered(reader) => read_definition_at(reader, pos, &mut buf)?,
+            FastaReader::Mmap(mmap) => read_definition_at(
+                &mut fasta::io::Reader::new(Cursor::new(&mmap[..])),
+                pos,
+                &mut buf,
+            )?,
+        };
+        let definition: Definition = buf
+            .parse()
+            .with_context(|| format!("Invalid definition for {ctg_name}: {buf:?}"))?;
+        if definition.name() != ctg_name.as_bytes() {
+            bail!("Definition {buf:?} doesn't match record {ctg_name}.")
         }
+        Ok(definition.description().map(|desc| desc.to_vec()))
     }
 
     fn read_fa(
         fa: &impl AsRef<Path>,
         fa_gzi: Option<&bgzf::gzi::Index>,
+        mmap: bool,
     ) -> eyre::Result<FastaReader> {
         let fa_file = std::fs::File::open(fa);
         if let Some(fa_gzi) = fa_gzi {
+            if mmap {
+                log::warn!(
+                    "{:?} is compressed and can't be memory-mapped.",
+                    fa.as_ref()
+                );
+            }
             Ok(FastaReader::Bgzip(
                 fa_file
                     .map(|file| bgzf::IndexedReader::new(file, fa_gzi.to_vec()))
                     .map(fasta::io::Reader::new)?,
             ))
+        } else if mmap {
+            // SAFETY: The input file isn't modified while misassembling.
+            let mmap = unsafe { Mmap::map(&fa_file?)? };
+            Ok(FastaReader::Mmap(Arc::new(mmap)))
         } else {
             Ok(FastaReader::Standard(
                 fa_file
@@ -126,24 +1408,821 @@ impl Fasta {
     }
 }
 
-pub fn get_regions(
-    mut reader_bed: Option<bed::Reader<BufReader<File>>>,
-) -> Option<HashMap<String, IntervalSet<Position>>> {
-    reader_bed.as_mut().map(|input_bed| {
-        let mut regions: HashMap<String, IntervalSet<Position>> = HashMap::new();
-        for rec in input_bed.records::<3>().flatten() {
-            let region = rec.start_position()..rec.end_position();
-            regions
-                .entry(rec.reference_sequence_name().to_string())
-                .and_modify(|r| {
-                    r.insert(region.clone());
-                })
-                .or_insert_with(|| {
-                    let mut rs = IntervalSet::new();
-                    rs.insert(region);
-                    rs
+fn find_index_record<'a>(
+    index: &'a fasta::fai::Index,
+    ctg_name: &[u8],
+) -> eyre::Result<&'a fasta::fai::Record> {
+    index
+        .iter()
+        .find(|rec| rec.name() == ctg_name)
+        .with_context(|| format!("Invalid record name: {}", String::from_utf8_lossy(ctg_name)))
+}
+
+/// Same as [`fasta::io::Reader::query`] but reading a memory-mapped fasta.
+fn query_mmap(
+    mmap: &[u8],
+    index: &fasta::fai::Index,
+    region: &noodles::core::Region,
+) -> eyre::Result<fasta::Record> {
+    let index_record = find_index_record(index, region.name())?;
+    // 1-based, inclusive interval to a 0-based range within the record.
+    let length = index_record.length() as usize;
+    let interval = region.interval();
+    let start = interval.start().map_or(0, |pos| usize::from(pos) - 1);
+    let end = interval.end().map_or(length, usize::from).min(length);
+    let mut seq = Vec::with_capacity(end.saturating_sub(start));
+    for line in mmap_lines(mmap, index_record, start..end) {
+        seq.extend_from_slice(line?);
+    }
+    Ok(fasta::Record::new(
+        Definition::new(region.to_string(), None),
+        seq.into(),
+    ))
+}
+
+/// Slices of each line of bases within `range` of a record in a memory-mapped fasta.
+fn mmap_lines<'a>(
+    mmap: &'a [u8],
+    index_record: &fasta::fai::Record,
+    range: Range<usize>,
+) -> impl Iterator<Item = eyre::Result<&'a [u8]>> {
+    let (offset, line_bases, line_width) = (
+        index_record.offset() as usize,
+        index_record.line_bases() as usize,
+        index_record.line_width() as usize,
+    );
+    let mut start = range.start;
+    std::iter::from_fn(move || {
+        if start >= range.end || line_bases == 0 {
+            return None;
+        }
+        let end = ((start / line_bases + 1) * line_bases).min(range.end);
+        let pos = offset + start / line_bases * line_width + start % line_bases;
+        let line = mmap
+            .get(pos..pos + (end - start))
+            .context("Record out of bounds of file. Is the faidx outdated?");
+        start = end;
+        Some(line)
+    })
+}
+
+fn read_definition_at<R: BufRead + Seek>(
+    reader: &mut fasta::io::Reader<R>,
+    pos: u64,
+    buf: &mut String,
+) -> std::io::Result<usize> {
+    reader.get_mut().seek(SeekFrom::Start(pos))?;
+    reader.read_definition(buf)
+}
+
+/// Find the position of the definition line ending just before `offset`, the start of a record's sequence.
+/// Searches back in growing windows as descriptions can be long.
+fn find_definition_before<R: BufRead + Seek>(
+    reader: &mut fasta::io::Reader<R>,
+    offset: u64,
+) -> std::io::Result<Option<u64>> {
+    let reader = reader.get_mut();
+    let mut window = 4096;
+    loop {
+        let start = offset.saturating_sub(window);
+        let mut buf = vec![0; (offset - start) as usize];
+        reader.seek(SeekFrom::Start(start))?;
+        reader.read_exact(&mut buf)?;
+        // Ignore the line terminator of the definition line.
+        let line = buf.strip_suffix(b"\n").unwrap_or(&buf);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let line_start = match line.iter().rposition(|b| *b == b'\n') {
+            Some(i) => i + 1,
+            None if start == 0 => 0,
+            None => {
+                window *= 2;
+                continue;
+            }
+        };
+        return Ok((line.get(line_start) == Some(&b'>')).then_some(start + line_start as u64));
+    }
+}
+
+/// Read the records of a bed file.
+/// Comment, track, browser, and empty lines are skipped.
+/// Invalid records are errors with their line number.
+pub fn read_bed_records(
+    bed: impl AsRef<Path>,
+) -> eyre::Result<impl Iterator<Item = eyre::Result<bed::Record<3>>>> {
+    let path = bed.as_ref().to_owned();
+    let mut reader = File::open(&path)
+        .map(BufReader::new)
+        .map(bed::Reader::new)
+        .wrap_err_with(|| format!("Cannot open bed file {path:?}"))?;
+    let mut buf = String::new();
+    let mut line_num = 0;
+    Ok(std::iter::from_fn(move || loop {
+        buf.clear();
+        line_num += 1;
+        match reader.read_line(&mut buf) {
+            Ok(0) => return None,
+            Ok(_) => {
+                if buf.is_empty()
+                    || buf.starts_with('#')
+                    || buf.starts_with("track")
+                    || buf.starts_with("browser")
+                {
+                    continue;
+                }
+                let rec = buf.parse::<bed::Record<3>>().wrap_err_with(|| {
+                    format!("Invalid bed record at {path:?}:{line_num}: {buf:?}")
                 });
+                return Some(rec.and_then(|rec| {
+                    // Positions are 1-based so the start is one past the 0-based start.
+                    if usize::from(rec.end_position()) < usize::from(rec.start_position()) {
+                        bail!("Bed record is empty or ends before it starts at {path:?}:{line_num}: {buf:?}")
+                    }
+                    Ok(rec)
+                }));
+            }
+            Err(err) => {
+                return Some(Err(err).wrap_err_with(|| format!("Cannot read {path:?}:{line_num}")))
+            }
         }
+    }))
+}
+
+/// Read intervals from a bed file by record name.
+pub fn read_intervals(
+    bed: impl AsRef<Path>,
+) -> eyre::Result<HashMap<String, IntervalSet<Position>>> {
+    let mut intervals: HashMap<String, IntervalSet<Position>> = HashMap::new();
+    for rec in read_bed_records(bed)? {
+        let rec = rec?;
+        // Intervals are half-open so 1 bp records are empty.
+        if rec.start_position() == rec.end_position() {
+            continue;
+        }
+        intervals
+            .entry(rec.reference_sequence_name().to_string())
+            .or_default()
+            .insert(rec.start_position()..rec.end_position());
+    }
+    Ok(intervals)
+}
+
+/// Read regions from a bed file.
+/// If weighted, the weight of a region is read from the 5th column (score) or a numeric 4th column.
+/// Otherwise, or if no weight is given, regions have a weight of 1.
+/// The name of a region is read from the 4th column if not a weight.
+pub fn get_regions(
+    bed: impl AsRef<Path>,
+    weighted: bool,
+) -> eyre::Result<HashMap<String, Regions>> {
+    let mut regions: HashMap<String, Regions> = HashMap::new();
+    for rec in read_bed_records(bed)? {
+        let rec = rec?;
+        // Intervals are half-open so 1 bp records are empty.
+        if rec.start_position() == rec.end_position() {
+            continue;
+        }
+        let interval = rec.start_position()..rec.end_position();
+        let (name, weight) = match &rec.optional_fields()[..] {
+            [] => (None, None),
+            [field] if weighted => match field.parse::<f64>() {
+                Ok(weight) => (None, Some(weight)),
+                Err(_) => (Some(field), None),
+            },
+            [name] => (Some(name), None),
+            [name, score, ..] => (Some(name), score.parse::<f64>().ok().filter(|_| weighted)),
+        };
+        if let Some(weight) = weight.filter(|weight| !(weight.is_finite() && *weight >= 0.0)) {
+            bail!("Invalid weight ({weight}) of bed record: {rec}")
+        }
+        let region = Region {
+            weight: weight.unwrap_or(1.0),
+            name: name.filter(|name| *name != ".").cloned(),
+        };
         regions
-    })
+            .entry(rec.reference_sequence_name().to_string())
+            .or_default()
+            .insert(interval, region);
+    }
+    Ok(regions)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{Cursor, Read, Seek, SeekFrom, Write},
+        path::{Path, PathBuf},
+    };
+
+    use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+    use itertools::Itertools;
+    use noodles::{
+        bgzf,
+        fasta::{
+            self,
+            record::{Definition, Sequence},
+        },
+    };
+
+    use noodles::core::Position;
+
+    use super::{
+        create_outfile, get_outfile_writers, get_regions, index_bgzf_blocks, read_bed_records,
+        replicate_path, write_copy_number, write_gzi, write_igv_session, Fasta, FastaReader,
+        FastaWriter, OutputStream, RenameMap, SeqWriter, TruthWriter,
+    };
+    use crate::{
+        apply::{Event, Variant},
+        breaks::BrokenSequence,
+        config::{OutputFormat, TruthFormat},
+        misjoin::RemovedSequence,
+        test_utils::TestDir,
+        utils::{Region, Regions, SequenceType},
+    };
+
+    /// Truth writer writing to memory.
+    fn truth_writer(format: TruthFormat) -> TruthWriter<Vec<u8>> {
+        TruthWriter::new(noodles::bed::Writer::new(vec![]), format)
+    }
+
+    fn written(writer: TruthWriter<Vec<u8>>) -> String {
+        String::from_utf8(writer.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn test_fasta_plain_gzip() {
+        let dir = TestDir::new("plain_gzip");
+        let fa = dir.join("in.fa.gz");
+        let mut encoder = GzEncoder::new(std::fs::File::create(&fa).unwrap(), Compression::fast());
+        encoder
+            .write_all(b">chr1 hap1 metadata\nAAAACCCC\nGG\n>chr2\nTTTT\n")
+            .unwrap();
+        encoder.finish().unwrap();
+
+        let mut reader = Fasta::new(&fa, false, None, None).unwrap();
+        assert_eq!(
+            reader.lengths(),
+            [("chr1".to_string(), 10), ("chr2".to_string(), 4)]
+        );
+        let record = reader.fetch("chr1", 3, 10).unwrap();
+        assert_eq!(record.sequence().as_ref(), b"AACCCCGG");
+        assert_eq!(record.description(), Some(&b"hap1 metadata"[..]));
+        let record = reader.fetch("chr2", 1, 4).unwrap();
+        assert_eq!(record.description(), None);
+    }
+
+    #[test]
+    fn test_create_outfile() {
+        let dir = TestDir::new("create_outfile");
+        let outfile = dir.join("out.bed");
+        std::fs::write(&outfile, b"truth").unwrap();
+        assert!(create_outfile(&outfile, false).is_err());
+        assert_eq!(std::fs::read(&outfile).unwrap(), b"truth");
+        create_outfile(&outfile, true).unwrap();
+        assert!(std::fs::read(&outfile).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_bgzip_output() {
+        let dir = TestDir::new("bgzip_output");
+        let fa = dir.join("out.fa.gz");
+        let (output_fa, _) = get_outfile_writers(
+            Some(fa.clone()),
+            None,
+            TruthFormat::Misasim,
+            false,
+            2,
+            1,
+            true,
+        )
+        .unwrap();
+        assert!(matches!(output_fa, OutputStream::Bgzip(_)));
+        let mut writer = FastaWriter::new(output_fa, OutputFormat::Fasta, 4, false);
+        writer
+            .write_record(&fasta::Record::new(
+                Definition::new("chr1", None),
+                Sequence::from(b"ACGTACGTAC".to_vec()),
+            ))
+            .unwrap();
+        writer.into_inner().finish().unwrap();
+
+        let mut decompressed = String::new();
+        MultiGzDecoder::new(std::fs::File::open(&fa).unwrap())
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, ">chr1\nACGT\nACGT\nAC\n");
+    }
+
+    #[test]
+    fn test_index_bgzf_blocks() {
+        // Spans several blocks.
+        let data = (0..200_000).map(|i| b"ACGT"[i % 4]).collect_vec();
+        let mut writer = bgzf::Writer::new(Vec::new());
+        writer.write_all(&data).unwrap();
+        let compressed = writer.finish().unwrap();
+
+        let index = index_bgzf_blocks(compressed.as_slice()).unwrap();
+        assert_eq!(index[0], (0, 0));
+        assert!(index.len() > 2);
+        assert_eq!(
+            index.last().unwrap(),
+            &(compressed.len() as u64 - 28, 200_000)
+        );
+        let mut gzi = Vec::new();
+        write_gzi(&mut gzi, &index).unwrap();
+        assert_eq!(
+            bgzf::gzi::Reader::new(gzi.as_slice()).read_index().unwrap(),
+            index
+        );
+
+        let mut reader = bgzf::IndexedReader::new(Cursor::new(compressed), index);
+        reader.seek(SeekFrom::Start(150_001)).unwrap();
+        let mut buf = [0; 10];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, data[150_001..150_011]);
+
+        assert!(index_bgzf_blocks(&b">chr1\nACGT\n"[..]).is_err());
+    }
+
+    #[test]
+    fn test_fasta_explicit_fai() {
+        let dir = TestDir::new("explicit_fai");
+        let fa = dir.join("in.fasta");
+        let fai = dir.join("in.index");
+        std::fs::write(&fa, b">chr1\nACGT\nAC\n").unwrap();
+        std::fs::write(&fai, b"chr1\t6\t6\t4\t5\n").unwrap();
+
+        let mut reader = Fasta::new(&fa, false, Some(&fai), None).unwrap();
+        assert_eq!(reader.lengths(), [("chr1".to_owned(), 6)]);
+        let record = reader.fetch("chr1", 3, 6).unwrap();
+        assert_eq!(record.sequence().as_ref(), b"GTAC");
+        // Given indexes aren't generated.
+        assert!(Fasta::new(&fa, false, Some(&dir.join("missing.fai")), None).is_err());
+    }
+
+    #[test]
+    fn test_fasta_discover_fai() {
+        let dir = TestDir::new("discover_fai");
+        let fa = dir.join("in.fasta");
+        let mut fai = fa.as_os_str().to_owned();
+        fai.push(".fai");
+        std::fs::write(&fa, b">chr1\nACGT\n>chr2\nAC\n").unwrap();
+        // Only index chr1 to check that the existing index is used.
+        std::fs::write(&fai, b"chr1\t4\t6\t4\t5\n").unwrap();
+
+        let reader = Fasta::new(&fa, false, None, None).unwrap();
+        assert_eq!(reader.lengths(), [("chr1".to_owned(), 4)]);
+    }
+
+    #[test]
+    fn test_fasta_in_memory() {
+        let dir = TestDir::new("in_memory");
+        let fa = dir.join("in.fa");
+        std::fs::write(&fa, b">chr1 hap1\nACGT\nAC\n").unwrap();
+        let mut reader = Fasta::from_file_in_memory(&fa).unwrap();
+        assert!(matches!(reader.reader, FastaReader::Buffered(_)));
+        let record = reader.fetch("chr1", 1, 6).unwrap();
+        assert_eq!(record.sequence().as_ref(), b"ACGTAC");
+        assert_eq!(record.description(), Some(&b"hap1"[..]));
+        // No index files are written.
+        assert!(!fa.with_extension("fa.fai").exists());
+    }
+
+    #[test]
+    fn test_fasta_description_blank_lines() {
+        // Blank lines between records aren't part of either record in the index.
+        let fa = b">chr1 hap1\nACGT\n\n\n>chr2 hap2 metadata\r\nAC\r\n".to_vec();
+        let index = fasta::fai::Index::from(vec![
+            fasta::fai::Record::new("chr1", 4, 11, 4, 5),
+            fasta::fai::Record::new("chr2", 2, 39, 2, 4),
+        ]);
+        let mut reader = Fasta::from_reader(
+            FastaReader::Buffered(fasta::io::Reader::new(Cursor::new(fa.into()))),
+            index,
+        );
+        let record = reader.fetch("chr2", 1, 2).unwrap();
+        assert_eq!(record.sequence().as_ref(), b"AC");
+        assert_eq!(record.description(), Some(&b"hap2 metadata"[..]));
+        assert_eq!(reader.description("chr1").unwrap(), Some(b"hap1".to_vec()));
+
+        let mut reader = reader.try_clone().unwrap();
+        let record = reader.fetch("chr1", 1, 4).unwrap();
+        assert_eq!(record.sequence().as_ref(), b"ACGT");
+        assert_eq!(record.description(), Some(&b"hap1"[..]));
+    }
+
+    #[test]
+    fn test_fasta_mmap() {
+        let dir = TestDir::new("mmap");
+        let fa = dir.join("in.fa");
+        std::fs::write(&fa, b">chr1 hap1\nACGTACGTAC\nGTA\n>chr2\nTT\n").unwrap();
+
+        let mut reader = Fasta::new(&fa, true, None, None).unwrap();
+        assert!(matches!(reader.reader, FastaReader::Mmap(_)));
+        let record = reader.fetch("chr1", 8, 20).unwrap();
+        assert_eq!(record.name(), b"chr1:8-20");
+        assert_eq!(record.sequence().as_ref(), b"TACGTA");
+        assert_eq!(record.description(), Some(&b"hap1"[..]));
+        let record = reader.fetch("chr2", 1, 2).unwrap();
+        assert_eq!(record.sequence().as_ref(), b"TT");
+        let record = reader.try_clone().unwrap().fetch("chr2", 1, 2).unwrap();
+        assert_eq!(record.sequence().as_ref(), b"TT");
+
+        let mut writer = FastaWriter::new(Vec::new(), OutputFormat::Fasta, 4, false);
+        reader.copy_record("chr1", 13, &mut writer).unwrap();
+        let SeqWriter::Fasta(inner) = writer.writer else {
+            unreachable!()
+        };
+        assert_eq!(inner, b">chr1 hap1\nACGT\nACGT\nACGT\nA\n");
+    }
+
+    #[test]
+    fn test_fasta_fetch_ranges() {
+        let mut reader = Fasta::from_bytes(b">chr1 hap1\nACGTACGTAC\nGTA\n".to_vec()).unwrap();
+        let record = reader
+            .fetch_ranges("chr1", 13, &[1..3, 8..11, 12..12])
+            .unwrap();
+        assert_eq!(record.name(), b"chr1");
+        assert_eq!(record.description(), None);
+        assert_eq!(record.sequence().as_ref(), b"\0CG\0\0\0\0\0ACG\0\0");
+        assert!(reader.fetch_ranges("chr1", 13, &[0..1, 12..14]).is_err());
+    }
+
+    #[test]
+    fn test_fasta_writer_index() {
+        for line_bases in [80, 60, 0] {
+            let mut writer = FastaWriter::new(Vec::new(), OutputFormat::Fasta, line_bases, true);
+            for (name, desc, len) in [
+                ("chr1", None, 200),
+                ("chr2", Some("hap1"), 80),
+                ("chr3", None, 3),
+            ] {
+                let record = fasta::Record::new(
+                    Definition::new(name, desc.map(|d: &str| d.as_bytes().to_vec())),
+                    Sequence::from(vec![b'A'; len]),
+                );
+                writer.write_record(&record).unwrap();
+            }
+
+            let SeqWriter::Fasta(inner) = &writer.writer else {
+                unreachable!()
+            };
+            let mut indexer = fasta::io::Indexer::new(Cursor::new(inner.clone()));
+            let mut expected = Vec::new();
+            while let Some(record) = indexer.index_record().unwrap() {
+                expected.push(record);
+            }
+            assert_eq!(writer.index.unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_fasta_writer_append() {
+        let records = [("chr1", 200), ("chr2", 80), ("chr3", 3)].map(|(name, len)| {
+            fasta::Record::new(Definition::new(name, None), Sequence::from(vec![b'A'; len]))
+        });
+        let mut expected = FastaWriter::new(Vec::new(), OutputFormat::Fasta, 60, true);
+        for record in &records {
+            expected.write_record(record).unwrap();
+        }
+
+        // Records written to buffers are indexed after the records before them.
+        let mut writer = FastaWriter::new(Vec::new(), OutputFormat::Fasta, 60, true);
+        writer.write_record(&records[0]).unwrap();
+        let mut buffer = writer.buffer();
+        for record in &records[1..] {
+            buffer.write_record(record).unwrap();
+        }
+        writer.append(buffer).unwrap();
+        assert_eq!(writer.index, expected.index);
+        assert_eq!(writer.into_inner(), expected.into_inner());
+    }
+
+    #[test]
+    fn test_fasta_writer_chunks() {
+        let seq = b"ACGTACGTACGTA";
+        let record = fasta::Record::new(
+            Definition::new("chr1", Some(b"hap1".to_vec())),
+            Sequence::from(seq.to_vec()),
+        );
+        for format in [OutputFormat::Fasta, OutputFormat::Fastq] {
+            let mut expected = FastaWriter::new(Vec::new(), format, 4, false);
+            expected
+                .write_misassembled_record(&record, &[2..4, 7..7])
+                .unwrap();
+            let mut writer = FastaWriter::new(Vec::new(), format, 4, false);
+            let chunks = [&seq[..3], &seq[3..8], &seq[8..9], &seq[9..]].map(eyre::Ok);
+            writer
+                .write_record_chunks(record.definition(), seq.len(), chunks, &[2..4, 7..7])
+                .unwrap();
+            let (SeqWriter::Fasta(expected) | SeqWriter::Fastq(expected)) = expected.writer;
+            let (SeqWriter::Fasta(inner) | SeqWriter::Fastq(inner)) = writer.writer;
+            assert_eq!(inner, expected);
+        }
+
+        let mut reader =
+            Fasta::from_bytes(b">chr1 hap1\nACGTACGTAC\nGTA\n>chr2\nTT\n".to_vec()).unwrap();
+        let mut writer = FastaWriter::new(Vec::new(), OutputFormat::Fasta, 4, false);
+        reader.copy_record("chr1", seq.len(), &mut writer).unwrap();
+        let SeqWriter::Fasta(inner) = writer.writer else {
+            unreachable!()
+        };
+        assert_eq!(inner, b">chr1 hap1\nACGT\nACGT\nACGT\nA\n");
+    }
+
+    #[test]
+    fn test_fastq_writer_quality() {
+        let mut writer = FastaWriter::new(Vec::new(), OutputFormat::Fastq, 80, false);
+        let record = fasta::Record::new(
+            Definition::new("chr1", Some(b"hap1".to_vec())),
+            Sequence::from(b"ACGTACGTAC".to_vec()),
+        );
+        writer
+            .write_misassembled_record(&record, &[2..4, 7..7])
+            .unwrap();
+        let SeqWriter::Fastq(inner) = writer.writer else {
+            unreachable!()
+        };
+        assert_eq!(inner, b"@chr1 hap1\nACGTACGTAC\n+\nII&&II&&II\n");
+    }
+
+    #[test]
+    fn test_truth_writer_nucflag() {
+        let mut writer = truth_writer(TruthFormat::Nucflag);
+        writer
+            .write_misassembly::<noodles::bed::record::Builder<3>, _>(
+                "chr1",
+                SequenceType::Misjoin,
+                [],
+                &[5..5, 24..24],
+                40,
+            )
+            .unwrap();
+        writer
+            .write_misassembly::<noodles::bed::record::Builder<3>, _>(
+                "chr1",
+                SequenceType::FalseDuplication,
+                [],
+                &[30..36, 38..40],
+                40,
+            )
+            .unwrap();
+        assert_eq!(
+            written(writer),
+            "chr1\t4\t6\tmisjoin\nchr1\t23\t25\tmisjoin\nchr1\t30\t36\tfalse_dupe\nchr1\t38\t40\tfalse_dupe\n"
+        );
+    }
+
+    #[test]
+    fn test_write_copy_number() {
+        let variants = [
+            Variant::Duplication {
+                range: 10..20,
+                count: 3,
+            },
+            Variant::Deletion(0..5),
+            Variant::Gap(5..8),
+            Variant::Inversion(30..40),
+            Variant::Deletion(95..120),
+        ];
+        let mut bedgraph = vec![];
+        write_copy_number(&mut bedgraph, "chr1", 100, &variants).unwrap();
+        assert_eq!(
+            String::from_utf8(bedgraph).unwrap(),
+            "chr1\t0\t8\t0\nchr1\t8\t10\t1\nchr1\t10\t20\t3\nchr1\t20\t95\t1\nchr1\t95\t100\t0\n"
+        );
+    }
+
+    #[test]
+    fn test_replicate_path() {
+        for (path, expected) in [
+            ("out/sim.fa", "out/sim.rep2.fa"),
+            ("sim.fa.gz", "sim.rep2.fa.gz"),
+            ("truth.bed", "truth.rep2.bed"),
+            ("split_dir", "split_dir.rep2"),
+            (".hidden", ".hidden.rep2"),
+        ] {
+            assert_eq!(replicate_path(Path::new(path), 2), PathBuf::from(expected));
+        }
+    }
+
+    #[test]
+    fn test_rename_map() {
+        let dir = TestDir::new("rename_map");
+        let path = dir.join("renames.tsv");
+        std::fs::write(
+            &path,
+            "# old\tnew\nchr1\thaplotype1-0000001\nchr1_pat\thaplotype2-0000001\n",
+        )
+        .unwrap();
+        let renames = RenameMap::read(&path).unwrap().with_suffix("_m");
+        assert_eq!(renames.rename("chr1"), "haplotype1-0000001");
+        assert_eq!(renames.rename("chr1_pat"), "haplotype2-0000001");
+        assert_eq!(renames.rename("chr1:1-100"), "haplotype1-0000001:1-100");
+        assert_eq!(
+            renames.rename("chr1_pat_original"),
+            "haplotype2-0000001_original"
+        );
+        assert_eq!(renames.rename("chr1_m"), "haplotype1-0000001_m");
+        assert_eq!(renames.rename("chr1_m:1-100"), "haplotype1-0000001_m:1-100");
+        assert_eq!(renames.rename("chr2"), "chr2");
+        assert_eq!(renames.output_name("chr1"), "haplotype1-0000001_m");
+        assert_eq!(renames.without_suffix("chr1_m:1-100"), "chr1:1-100");
+        assert_eq!(renames.without_suffix("chr2_original"), "chr2_original");
+        // Unmapped records named like a mapped record with another suffix aren't renamed.
+        for name in ["chr1_random", "chr1:100", "chr1_pat:1-", "chr1_pat_m1"] {
+            assert_eq!(renames.rename(name), name);
+        }
+
+        std::fs::write(&path, "chr1\thap1\nchr2\thap1\n").unwrap();
+        assert!(RenameMap::read(&path).is_err());
+    }
+
+    #[test]
+    fn test_read_bed_records() {
+        let dir = TestDir::new("read_bed_records");
+        let bed = dir.join("regions.bed");
+        std::fs::write(
+            &bed,
+            "browser position chr1:1-100\ntrack name=regions\n# header\n\nchr1\t0\t10\tname\t0\t+\nchr1\tten\t20\nchr1\t300\t200\nchr1\t100\t100\n",
+        )
+        .unwrap();
+        let mut records = read_bed_records(&bed).unwrap();
+        let record = records.next().unwrap().unwrap();
+        assert_eq!(record.end_position(), Position::new(10).unwrap());
+        assert_eq!(&record.optional_fields()[..], ["name", "0", "+"]);
+        let err = records.next().unwrap().unwrap_err();
+        assert!(format!("{err}").contains("regions.bed\":6"));
+        let err = records.next().unwrap().unwrap_err();
+        assert!(format!("{err}").contains("ends before it starts"));
+        assert!(format!("{err}").contains("regions.bed\":7"));
+        let err = records.next().unwrap().unwrap_err();
+        assert!(format!("{err}").contains("regions.bed\":8"));
+        assert!(records.next().is_none());
+    }
+
+    #[test]
+    fn test_get_regions() {
+        let dir = TestDir::new("get_regions");
+        let bed = dir.join("regions.bed");
+        std::fs::write(&bed, "chr1\t0\t10\tname\t0\t+\nchr1\t10\t20\t2\n").unwrap();
+        let regions = get_regions(&bed, false).unwrap();
+        let regions = regions["chr1"].unsorted_values().collect::<Vec<_>>();
+        assert_eq!(regions[0].weight, 1.0);
+        assert_eq!(regions[0].name.as_deref(), Some("name"));
+        assert_eq!(regions[1].weight, 1.0);
+        assert_eq!(regions[1].name.as_deref(), Some("2"));
+
+        let regions = get_regions(&bed, true).unwrap();
+        let regions = regions["chr1"].unsorted_values().collect::<Vec<_>>();
+        assert_eq!(regions[0].weight, 0.0);
+        assert_eq!(regions[1].weight, 2.0);
+        assert_eq!(regions[1].name, None);
+
+        std::fs::write(&bed, "chr1\t0\t10\tname\t-1\t+\n").unwrap();
+        let err = get_regions(&bed, true).unwrap_err();
+        assert!(format!("{err}").starts_with("Invalid weight (-1) of bed record: chr1\t0\t10"));
+
+        std::fs::write(&bed, "chr1\t30\t31\n").unwrap();
+        assert!(get_regions(&bed, false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_truth_writer_region_names() {
+        let mut writer = truth_writer(TruthFormat::Misasim);
+        let pos = |p| Position::new(p).unwrap();
+        writer.set_region_names(&Regions::from_iter([(
+            pos(10)..pos(20),
+            Region {
+                weight: 1.0,
+                name: Some("HOR_array".to_owned()),
+            },
+        )]));
+        let removed_seqs = [
+            RemovedSequence {
+                start: 12,
+                end: 14,
+                seq: b"AC",
+            },
+            RemovedSequence {
+                start: 30,
+                end: 32,
+                seq: b"GT",
+            },
+        ];
+        writer
+            .write_misassembly("chr1", SequenceType::Misjoin, removed_seqs, &[], 40)
+            .unwrap();
+        assert_eq!(
+            written(writer),
+            "chr1\t12\t14\tAC\tregion=HOR_array\nchr1\t30\t32\tGT\n"
+        );
+    }
+
+    #[test]
+    fn test_truth_writer_event_ids() {
+        let mut writer = truth_writer(TruthFormat::Misasim);
+        let pos = |p| Position::new(p).unwrap();
+        writer.set_region_names(&Regions::from_iter([(
+            pos(10)..pos(20),
+            Region {
+                weight: 1.0,
+                name: Some("HOR_array".to_owned()),
+            },
+        )]));
+        let removed_seqs = [RemovedSequence {
+            start: 12,
+            end: 14,
+            seq: b"AC",
+        }];
+        writer.set_event_ids(&[Event {
+            id: Some("misasim.1".to_owned()),
+            variant: Variant::Deletion(12..14),
+        }]);
+        writer
+            .write_misassembly("chr1", SequenceType::Misjoin, removed_seqs, &[], 40)
+            .unwrap();
+        writer.set_region_names(&Regions::default());
+        writer.set_event_ids(&[Event {
+            id: Some("misasim.2".to_owned()),
+            variant: Variant::Break { pos: 5 },
+        }]);
+        // Only the segment after the break has its ID.
+        writer
+            .write_misassembly(
+                "chr2",
+                SequenceType::Break,
+                [
+                    BrokenSequence { start: 1, end: 5 },
+                    BrokenSequence { start: 6, end: 10 },
+                ],
+                &[],
+                10,
+            )
+            .unwrap();
+        assert_eq!(
+            written(writer),
+            "chr1\t12\t14\tAC\tregion=HOR_array\tid=misasim.1\nchr2\t0\t5\tBroken\nchr2\t5\t10\tBroken\tid=misasim.2\n"
+        );
+    }
+
+    #[test]
+    fn test_truth_writer_igv() {
+        let mut writer = truth_writer(TruthFormat::Misasim)
+            .with_igv_track("misasim", "test")
+            .unwrap();
+        writer.set_event_ids(&[Event {
+            id: Some("misasim.1".to_owned()),
+            variant: Variant::Deletion(12..14),
+        }]);
+        let removed_seqs = [RemovedSequence {
+            start: 12,
+            end: 14,
+            seq: b"AC",
+        }];
+        writer
+            .write_misassembly("chr1", SequenceType::Misjoin, removed_seqs, &[], 40)
+            .unwrap();
+        assert_eq!(
+            written(writer),
+            "track name=\"misasim\" description=\"test\" itemRgb=On\nchr1\t12\t14\tmisjoin;id=misasim.1\t0\t.\t12\t14\t228,26,28\n"
+        );
+
+        let mut session = vec![];
+        write_igv_session(
+            &mut session,
+            Path::new("/out/sim.fa"),
+            &[Path::new("/out/sim.bed")],
+        )
+        .unwrap();
+        let session = String::from_utf8(session).unwrap();
+        assert!(session.contains(r#"<Session genome="/out/sim.fa" version="8">"#));
+        assert!(session.contains(r#"<Resource path="/out/sim.bed"/>"#));
+    }
+
+    #[test]
+    fn test_truth_writer_flagger() {
+        let mut writer = truth_writer(TruthFormat::Flagger);
+        writer
+            .write_misassembly::<noodles::bed::record::Builder<3>, _>(
+                "chr1",
+                SequenceType::Misjoin,
+                [],
+                &[5..5, 5..5, 24..24],
+                40,
+            )
+            .unwrap();
+        writer.write_unchanged("chr2", 10).unwrap();
+        assert_eq!(
+            written(writer),
+            [
+                "chr1\t0\t4\tHap",
+                "chr1\t4\t6\tErr",
+                "chr1\t6\t23\tHap",
+                "chr1\t23\t25\tErr",
+                "chr1\t25\t40\tHap",
+                "chr2\t0\t10\tHap\n",
+            ]
+            .join("\n")
+        );
+    }
 }
@@ -0,0 +1,1749 @@
+//! Simulate misassemblies in genome assemblies.
+//!
+//! Misassemblies are placed with [`generator::BuiltinGenerator`] and generated from the placed variants with
+//! [`misjoin::generate_deletion`], [`false_dupe::duplicate_segments`], and [`breaks::split_at_breaks`].
+//! User-defined misassembly types implement [`generator::MisassemblyGenerator`].
+//! [`simulate::simulate_records`] misassembles records lazily with any generator.
+//! [`generate_misassemblies`] runs the whole pipeline of the command-line tool from a [`config::Config`].
+//! Its stages are [`open_input`], [`select_records`], [`sampling_regions`], [`place_record`], [`generate_record`],
+//! and [`Outputs`].
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{stdin, BufWriter, IsTerminal, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+
+use eyre::bail;
+use iset::IntervalSet;
+use itertools::Itertools;
+use log::debug;
+use noodles::{
+    core::Position,
+    fasta::{self, record::Definition},
+};
+use rand::{seq::SliceRandom, Rng};
+use rayon::prelude::*;
+use regex::{self, Regex, RegexSet};
+
+pub mod apply;
+pub mod batch;
+pub mod breaks;
+pub mod cli;
+pub mod commands;
+pub mod compare;
+pub mod config;
+pub mod diploid;
+pub mod false_dupe;
+pub mod generator;
+pub mod genome;
+pub mod gfa;
+pub mod io;
+pub mod liftover;
+pub mod logging;
+pub mod misjoin;
+pub mod plan;
+pub mod reads;
+pub mod repeats;
+pub mod resume;
+pub mod revert;
+pub mod rng;
+pub mod simulate;
+pub mod stats;
+pub mod utils;
+
+#[cfg(test)]
+mod test_utils;
+
+use {
+    apply::{
+        read_exact_regions, read_replay_bed, read_vcf, write_variants, Event, EventIds, Variant,
+    },
+    breaks::{split_at_breaks, write_breaks, BrokenSequence},
+    commands::{Commands, MisassemblyCommands},
+    config::{
+        Config, InputConfig, OutputConfig, OutputFormat, PlacementConfig, SelectionConfig,
+        ShortRecordPolicy, UngroupedRecords,
+    },
+    diploid::{simulate_diploid, HeterozygosityConfig},
+    false_dupe::{check_max_duplications, duplicate_segments, DuplicateSequence},
+    generator::{BuiltinGenerator, MisassemblyGenerator},
+    genome::{generate_genome, GenomeConfig},
+    gfa::{write_gfa_misassemblies, Gfa},
+    io::{
+        create_outfile, get_outfile_writers, get_regions, read_intervals, replicate_path,
+        write_copy_number, write_igv_session, Fasta, FastaWriter, OutputStream, RenameMap,
+        TruthWriter, ORIGINAL_RECORD_SUFFIX,
+    },
+    liftover::read_events,
+    logging::Progress,
+    misjoin::{generate_deletion, DeletedSequence},
+    plan::{Plan, JUNCTION_FLANK},
+    reads::{simulate_reads, ReadConfig},
+    repeats::find_repeats,
+    resume::Completion,
+    revert::revert,
+    rng::{RngKind, SimRng},
+    stats::RunStats,
+    utils::{
+        annotate_definition, bias_intervals, check_placed_count, coverage_intervals, find_gaps,
+        hotspot_intervals, merge_intervals, record_seed, subtract_intervals, write_misassembly,
+        Regions, SequenceType,
+    },
+};
+
+/// Record waiting to be written in a batch.
+enum Pending<'a> {
+    Unchanged(&'a (String, u64)),
+    Misassembled(&'a (String, u64)),
+}
+
+/// Fetched record to misassemble.
+struct Prepared {
+    record: fasta::Record,
+    regions: Regions,
+    /// Randomly placed misassemblies. Empty if the record has given variants.
+    placed: Vec<Variant>,
+}
+
+/// Misassemblies generated for a record.
+pub enum Generated<'a> {
+    Deletion {
+        deleted_seq: DeletedSequence<'a>,
+        is_gap: bool,
+    },
+    FalseDuplication(DuplicateSequence<'a>),
+    Breaks(Vec<&'a [u8]>, Vec<BrokenSequence>),
+    /// Variants of the apply, execute, or replay commands and the note of the command. ex. "apply"
+    Variants {
+        variants: &'a [Variant],
+        note: &'static str,
+    },
+}
+
+/// Misassemble, apply variants to, or revert records of the input sequence file given by `config`.
+/// Compare and liftover commands are run separately.
+pub fn generate_misassemblies(config: Config) -> eyre::Result<()> {
+    if config.threads == 0 || config.output.compression_threads == 0 {
+        bail!("Number of threads must be at least 1.")
+    }
+    if config.replicates == 0 {
+        bail!("Number of replicates must be at least 1.")
+    }
+    if matches!(config.command, Commands::Generate { .. }) {
+        return generate_random_genome(&config);
+    }
+    if let Commands::Gfa {
+        gfa,
+        out_gfa,
+        breaks,
+        misjoins,
+        false_duplications,
+    } = &config.command
+    {
+        let output = &config.output;
+        let mut graph = Gfa::read(gfa)?;
+        let mut rng = SimRng::new(config.rng, get_seed(config.seed));
+        let misassemblies = graph.misassemble(*breaks, *misjoins, *false_duplications, &mut rng)?;
+        graph.write(BufWriter::new(create_outfile(out_gfa, output.force)?))?;
+
+        let (output_fa, _) = get_outfile_writers(
+            output.outfile.clone(),
+            None,
+            output.truth_format,
+            output.dry_run,
+            output.compression_threads,
+            output.compression_level,
+            output.force,
+        )?;
+        let mut writer_fa =
+            FastaWriter::new(output_fa, output.output_format, output.line_width, false);
+        graph.write_paths(&mut writer_fa)?;
+        writer_fa.into_inner().finish()?;
+        if let Some(outbedfile) = output.outbedfile.as_ref() {
+            let writer_bed = BufWriter::new(create_outfile(outbedfile, output.force)?);
+            write_gfa_misassemblies(writer_bed, &misassemblies)?;
+        }
+        return Ok(());
+    }
+    let runs = if config.replicates == 1 {
+        vec![config]
+    } else {
+        if !matches!(config.command, Commands::Misassembly(_)) {
+            bail!("Replicates can only be generated with a misassembly command.")
+        }
+        let output = &config.output;
+        if output.outfile.is_none() && output.split_output_dir.is_none() && !output.dry_run {
+            bail!("An output sequence file is required to generate replicates.")
+        }
+        if config.resume && config.seed.is_none() {
+            log::warn!("Resuming replicates without a seed. Remaining replicates use a new seed.");
+        }
+        let seed = config.seed.unwrap_or_else(rand::random);
+        (1..=config.replicates)
+            .map(|replicate| {
+                let mut replicate_config = config.clone();
+                replicate_config.seed = Some(seed.wrapping_add(replicate as u64 - 1));
+                let output = &mut replicate_config.output;
+                for path in [
+                    &mut output.outfile,
+                    &mut output.outbedfile,
+                    &mut output.split_output_dir,
+                    &mut output.copy_number_bedgraph,
+                    &mut output.stats_json,
+                    &mut output.igv_session,
+                ] {
+                    *path = path.as_deref().map(|path| replicate_path(path, replicate));
+                }
+                replicate_config
+            })
+            .collect_vec()
+    };
+    let num_runs = runs.len();
+    let mut runs = runs.into_iter().enumerate().collect_vec();
+    // Skip completed runs and overwrite the outputs of incomplete ones.
+    if runs.first().is_some_and(|(_, config)| config.resume) {
+        if runs
+            .iter()
+            .any(|(_, config)| Completion::path(config).is_none())
+        {
+            bail!("Resuming requires output files.")
+        }
+        runs.retain(|(i, config)| {
+            let is_complete = Completion::is_complete(config);
+            if is_complete {
+                log::info!(
+                    "Skipping run {} of {num_runs}. Outputs are complete.",
+                    i + 1
+                );
+            }
+            !is_complete
+        });
+        for (_, config) in runs.iter_mut() {
+            config.output.force = true;
+        }
+    }
+    let Some((_, first_run)) = runs.first() else {
+        return Ok(());
+    };
+
+    let mut reader_fa = open_input(&first_run.input)?;
+    for (i, mut config) in runs {
+        if num_runs > 1 {
+            log::info!("Generating replicate {} of {num_runs}.", i + 1);
+        }
+        let completion = config
+            .resume
+            .then(|| {
+                // The seed is recorded so a resumed run with it matches.
+                let seed = *config.seed.get_or_insert_with(rand::random);
+                Completion::path(&config).map(|path| (path, Completion::new(&config, seed)))
+            })
+            .flatten();
+        generate_replicate(config, &mut reader_fa)?;
+        if let Some((path, completion)) = completion {
+            completion.write(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Generate a random genome with "misasim generate".
+fn generate_random_genome(config: &Config) -> eyre::Result<()> {
+    let Commands::Generate {
+        contigs,
+        contig_length,
+        min_contig_length,
+        gc,
+        tandem_repeats,
+        tandem_repeat_length,
+        tandem_repeat_unit_length,
+        tandem_repeat_divergence,
+        segmental_duplications,
+        segmental_duplication_length,
+        segmental_duplication_divergence,
+    } = config.command
+    else {
+        bail!("{:?} doesn't generate a genome.", config.command)
+    };
+    if [
+        gc,
+        tandem_repeat_divergence,
+        segmental_duplication_divergence,
+    ]
+    .iter()
+    .any(|frac| !(0.0..=1.0).contains(frac))
+    {
+        bail!("GC content and divergences must be between 0 and 1.")
+    }
+    let min_contig_length = min_contig_length.unwrap_or(contig_length);
+    if contig_length == 0 || min_contig_length == 0 || min_contig_length > contig_length {
+        bail!("Contig lengths must be at least 1 and the minimum must be at most the length.")
+    }
+    let seed = get_seed(config.seed);
+    let mut rng = SimRng::new(config.rng, seed);
+    let lengths = (0..contigs)
+        .map(|_| rng.gen_range(min_contig_length..=contig_length))
+        .collect_vec();
+
+    let output = &config.output;
+    let (output_fa, _) = get_outfile_writers(
+        output.outfile.clone(),
+        None,
+        output.truth_format,
+        output.dry_run,
+        output.compression_threads,
+        output.compression_level,
+        output.force,
+    )?;
+    let mut writer_fa = FastaWriter::new(
+        output_fa,
+        output.output_format,
+        output.line_width,
+        output.index_outfile,
+    );
+    let mut writer_bed = output
+        .outbedfile
+        .as_ref()
+        .map(|path| create_outfile(path, output.force).map(BufWriter::new))
+        .transpose()?;
+    generate_genome(
+        &lengths,
+        GenomeConfig {
+            gc,
+            tandem_repeats,
+            tandem_repeat_length,
+            tandem_repeat_unit_length,
+            tandem_repeat_divergence,
+            segmental_duplications,
+            segmental_duplication_length,
+            segmental_duplication_divergence,
+        },
+        seed,
+        config.rng,
+        &mut writer_fa,
+        writer_bed.as_mut().map(|writer| writer as &mut dyn Write),
+    )?;
+    if let Some(mut writer_bed) = writer_bed {
+        writer_bed.flush()?;
+    }
+    writer_fa.finish(output.outfile.as_deref().filter(|_| output.index_outfile))
+}
+
+/// Get the given seed or generate one. Either is logged.
+fn get_seed(seed: Option<u64>) -> u64 {
+    if let Some(seed) = seed {
+        log::info!("Random seed: {seed}");
+        seed
+    } else {
+        let seed = rand::random();
+        log::info!("No random seed provided. Generated random seed: {seed}");
+        seed
+    }
+}
+
+/// Open the input sequence file. It's read from stdin if not given or "-".
+pub fn open_input(input: &InputConfig) -> eyre::Result<Fasta> {
+    Ok(match input.infile.clone() {
+        Some(infile) if infile != Path::new("-") && input.no_index => {
+            Fasta::from_file_in_memory(infile)?
+        }
+        Some(infile) if infile != Path::new("-") => Fasta::new(
+            infile,
+            input.mmap,
+            input.fai.as_deref(),
+            input.gzi.as_deref(),
+        )?,
+        Some(_) => Fasta::from_stdin()?,
+        None if !stdin().is_terminal() => Fasta::from_stdin()?,
+        None => bail!("No input fasta provided."),
+    })
+}
+
+/// Regions of the input bed files by record name.
+#[derive(Debug, Default)]
+pub struct InputRegions {
+    /// Regions to place misassemblies in. Merged unless `no_merge_regions`.
+    pub regions: Option<HashMap<String, Regions>>,
+    /// Regions misassemblies can't be placed in.
+    pub exclude: Option<HashMap<String, IntervalSet<Position>>>,
+    /// Regions a fraction of misassemblies are placed in.
+    pub bias: Option<HashMap<String, IntervalSet<Position>>>,
+    /// Regions weighted by their depth of coverage.
+    pub coverage: Option<HashMap<String, Regions>>,
+}
+
+impl InputRegions {
+    /// Read the region files of the input. Replayed regions are exact misassemblies rather than regions to sample from.
+    pub fn read(input: &InputConfig) -> eyre::Result<Self> {
+        let regions = input
+            .inbedfile
+            .as_ref()
+            .filter(|_| !input.replay)
+            .map(|bed| get_regions(bed, input.weighted_regions))
+            .transpose()?
+            .map(|regions| {
+                if input.no_merge_regions {
+                    return regions;
+                }
+                regions
+                    .into_iter()
+                    .map(|(name, rgns)| (name, merge_intervals(&rgns)))
+                    .collect()
+            });
+        let exclude = input
+            .exclude_bedfile
+            .as_ref()
+            .map(read_intervals)
+            .transpose()?;
+        let bias = input
+            .bias_bedfile
+            .as_ref()
+            .map(read_intervals)
+            .transpose()?;
+        // BedGraphs are BED files with the depth as a numeric 4th column.
+        let coverage = input
+            .coverage_bedgraph
+            .as_ref()
+            .map(|bed| get_regions(bed, true))
+            .transpose()?;
+        Ok(Self {
+            regions,
+            exclude,
+            bias,
+            coverage,
+        })
+    }
+}
+
+/// Read the variants to misassemble records with for the apply, execute, or replay commands.
+/// `None` if misassemblies are placed randomly.
+pub fn read_variants(
+    command: &Commands,
+    input: &InputConfig,
+    reader_fa: &mut Fasta,
+) -> eyre::Result<Option<HashMap<String, Vec<Variant>>>> {
+    Ok(match command {
+        Commands::Apply { vcf } => Some(read_vcf(vcf)?),
+        Commands::Execute { plan } => {
+            let plan = Plan::read(plan)?;
+            plan.check(reader_fa)?;
+            Some(plan.into_variants())
+        }
+        _ if input.replay || !input.at.is_empty() => {
+            let default_type = match command {
+                Commands::Misassembly(MisassemblyCommands::Misjoin { .. }) => {
+                    Some(SequenceType::Misjoin)
+                }
+                Commands::Misassembly(MisassemblyCommands::Gap { .. }) => Some(SequenceType::Gap),
+                Commands::Misassembly(MisassemblyCommands::FalseDuplication { .. }) => {
+                    Some(SequenceType::FalseDuplication)
+                }
+                Commands::Misassembly(MisassemblyCommands::Break { .. }) => {
+                    Some(SequenceType::Break)
+                }
+                _ => None,
+            };
+            if !input.at.is_empty() {
+                let Some(mtype) = default_type else {
+                    bail!("Exact regions require a misassembly command.")
+                };
+                let exact_variants = read_exact_regions(&input.at, mtype)?;
+                let lengths = reader_fa.lengths();
+                for name in exact_variants.keys() {
+                    if !lengths.iter().any(|(rec_name, _)| rec_name == name) {
+                        bail!("Record {name:?} of exact region not found in input sequence file.")
+                    }
+                }
+                Some(exact_variants)
+            } else {
+                let Some(inbedfile) = input.inbedfile.as_ref() else {
+                    bail!("Replay requires an input bed file.")
+                };
+                Some(read_replay_bed(inbedfile, default_type)?)
+            }
+        }
+        _ => None,
+    })
+}
+
+/// Records of a group and the records chosen from it to misassemble.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordGroup {
+    /// Captures of the group by pattern. `None` if the records don't match it.
+    pub captures: Option<Vec<String>>,
+    /// Names and lengths of the records sorted by name.
+    pub records: Vec<(String, u64)>,
+    pub chosen: Vec<(String, u64)>,
+}
+
+/// Group records by the captures of the group by pattern and choose the records of each group to misassemble.
+/// Records with `variants` are chosen instead if given. Only records with `regions` are chosen if given.
+/// Each group chooses records with its own generator seeded by its record names.
+pub fn select_records(
+    selection: &SelectionConfig,
+    lengths: Vec<(String, u64)>,
+    regions: Option<&HashMap<String, Regions>>,
+    variants: Option<&HashMap<String, Vec<Variant>>>,
+    seed: u64,
+    rng_kind: RngKind,
+) -> eyre::Result<Vec<RecordGroup>> {
+    let rgx = Regex::new(selection.group_by.as_deref().unwrap_or(".*?"))?;
+
+    // Group names by captured groups.
+    // ex. [chr10_mat, chr10_pat]
+    // * "^.*?_(?<hap>.*?)$" with group by haplotype.
+    // * "^(?<chr>.*?)_.*?$" will group by chromosome.
+    // * ".*?" will not group as all groups are unique.
+    // Records not matching the pattern have no group.
+    if selection.group_by.is_some() {
+        let ungrouped = lengths
+            .iter()
+            .filter(|(rec, _)| !rgx.is_match(rec))
+            .map(|(rec, _)| rec)
+            .collect_vec();
+        if !ungrouped.is_empty() {
+            log::warn!(
+                "{} record(s) don't match the group by pattern: {}",
+                ungrouped.len(),
+                ungrouped.iter().join(", ")
+            );
+        }
+    }
+    let groups = lengths
+        .into_iter()
+        // Sort first by name.
+        .sorted_by(|a, b| a.0.cmp(&b.0))
+        .chunk_by(|(rec, _)| {
+            let captures = rgx.captures(rec).map(|captures| {
+                captures
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(i, cap)| {
+                        // Skip entire string match.
+                        if i == 0 {
+                            None
+                        } else {
+                            cap.map(|c| c.as_str().to_owned())
+                        }
+                    })
+                    .collect_vec()
+            });
+            // Separate ungrouped records by their name.
+            let ungrouped_name = (captures.is_none()
+                && selection.ungrouped_records == UngroupedRecords::Separate)
+                .then(|| rec.clone());
+            (captures, ungrouped_name)
+        });
+
+    let target_rgxs = selection
+        .target_records
+        .as_ref()
+        .map(RegexSet::new)
+        .transpose()?;
+    let is_target = |name: &str| target_rgxs.as_ref().is_none_or(|rgxs| rgxs.is_match(name));
+
+    let mut record_groups = Vec::new();
+    for ((captures, _), records) in &groups {
+        let records = records.collect_vec();
+        // Misassemble records with variants rather than random records.
+        if let Some(variants) = variants {
+            let chosen = records
+                .iter()
+                .filter(|(name, _)| variants.contains_key(name) && is_target(name))
+                .cloned()
+                .collect();
+            record_groups.push(RecordGroup {
+                captures,
+                records,
+                chosen,
+            });
+            continue;
+        }
+        let mut rng = SimRng::new(
+            rng_kind,
+            record_seed(seed, &records.iter().map(|(name, _)| name).join(",")),
+        );
+        // Only records matching a target pattern can be misassembled.
+        let is_passthrough =
+            captures.is_none() && selection.ungrouped_records == UngroupedRecords::Passthrough;
+        let candidates = records
+            .iter()
+            .filter(|(name, _)| is_target(name) && !is_passthrough)
+            .collect_vec();
+        let chosen = if selection.all_records {
+            // Only records with regions if an input bed file is provided.
+            candidates
+                .into_iter()
+                .filter(|(name, _)| regions.is_none_or(|r| r.contains_key(name)))
+                .cloned()
+                .collect_vec()
+        } else {
+            let num_recs = selection.proportion.map_or(selection.per_group, |p| {
+                (candidates.len() as f64 * p).round() as usize
+            });
+            if selection.weight_by_length {
+                candidates
+                    .choose_multiple_weighted(&mut rng, num_recs, |(_, length)| *length as f64)?
+                    .map(|rec| (*rec).clone())
+                    .collect_vec()
+            } else {
+                candidates
+                    .choose_multiple(&mut rng, num_recs)
+                    .map(|rec| (*rec).clone())
+                    .collect_vec()
+            }
+        };
+        record_groups.push(RecordGroup {
+            captures,
+            records,
+            chosen,
+        });
+    }
+    Ok(record_groups)
+}
+
+/// Regions of a record in the input bed file or the entire record if it has none.
+/// Records without regions are an error if `strict_regions` unless the run has variants.
+pub fn record_regions(
+    placement: &PlacementConfig,
+    regions: Option<&HashMap<String, Regions>>,
+    record_name: &str,
+    record_length: usize,
+    has_variants: bool,
+) -> eyre::Result<Regions> {
+    let record_interval = Position::MIN..Position::try_from(record_length)?;
+    let def_record_regions = Regions::from_iter(std::iter::once((record_interval, 1.0.into())));
+    Ok(match regions.map(|r| r.get(record_name)) {
+        Some(Some(regions)) => regions.clone(),
+        // Records with variants don't need regions.
+        Some(None) if !has_variants => {
+            if placement.strict_regions {
+                bail!("No regions in input bed file for record {record_name:?}.")
+            }
+            log::warn!(
+                "No regions in input bed file for record {record_name:?}. Using the entire record."
+            );
+            def_record_regions
+        }
+        _ => def_record_regions,
+    })
+}
+
+/// Restrict the regions of a record to where misassemblies can be placed and weight them by the bias, repeat, and coverage regions.
+/// Misassemblies are clustered around hotspots if given the `number` of misassemblies.
+/// The record's sequence is fetched from `reader_fa` if needed and `record` isn't given.
+#[allow(clippy::too_many_arguments)]
+pub fn sampling_regions(
+    placement: &PlacementConfig,
+    input_regions: &InputRegions,
+    mut regions: Regions,
+    record_name: &str,
+    record_length: usize,
+    record: Option<&fasta::Record>,
+    reader_fa: &mut Fasta,
+    number: Option<usize>,
+    rng: &mut impl Rng,
+) -> eyre::Result<Regions> {
+    // Remove forbidden regions from the sampling space.
+    if let Some(record_exclude_regions) = input_regions
+        .exclude
+        .as_ref()
+        .and_then(|r| r.get(record_name))
+    {
+        regions = subtract_intervals(&regions, record_exclude_regions);
+    }
+    // Keep misassemblies away from the ends of the record.
+    if placement.edge_buffer != 0 {
+        let mut edges = IntervalSet::new();
+        for (start, end) in [
+            (1, placement.edge_buffer),
+            (
+                record_length.saturating_sub(placement.edge_buffer).max(1),
+                record_length + 1,
+            ),
+        ] {
+            if start < end {
+                edges.insert(Position::try_from(start)?..Position::try_from(end)?);
+            }
+        }
+        regions = subtract_intervals(&regions, &edges);
+    }
+    // Avoid existing assembly gaps.
+    if !placement.ignore_existing_gaps {
+        let mut gaps = IntervalSet::new();
+        let existing_gaps = match record {
+            Some(record) => find_gaps([eyre::Ok(record.sequence().as_ref())])?,
+            None => find_gaps(reader_fa.fetch_chunks(record_name, record_length))?,
+        };
+        for gap in existing_gaps {
+            let start = gap.start.saturating_sub(placement.gap_buffer).max(1);
+            let end = gap.end + placement.gap_buffer;
+            if start < end {
+                gaps.insert(Position::try_from(start)?..Position::try_from(end)?);
+            }
+        }
+        if !gaps.is_empty() {
+            debug!("Excluding {} existing gap(s).", gaps.len());
+            regions = subtract_intervals(&regions, &gaps);
+        }
+    }
+
+    // Place a fraction of misassemblies within biased regions.
+    if let (Some(bias_regions), Some(bias_fraction)) =
+        (input_regions.bias.as_ref(), placement.bias_fraction)
+    {
+        let empty_regions = IntervalSet::new();
+        let record_bias_regions = bias_regions.get(record_name).unwrap_or(&empty_regions);
+        regions = bias_intervals(&regions, record_bias_regions, bias_fraction);
+    }
+
+    // Place a fraction of misassemblies within detected repeats.
+    if let Some(repeat_bias_fraction) = placement.repeat_bias_fraction {
+        let fetched;
+        let seq = match record {
+            Some(record) => record.sequence().as_ref(),
+            None => {
+                fetched = reader_fa.fetch(record_name, 1, record_length)?;
+                fetched.sequence().as_ref()
+            }
+        };
+        let repeats = find_repeats(seq)
+            .into_iter()
+            .map(|repeat| {
+                Ok(Position::try_from(repeat.start + 1)?..Position::try_from(repeat.end + 1)?)
+            })
+            .collect::<eyre::Result<IntervalSet<Position>>>()?;
+        debug!("Found {} telomeric or satellite repeat(s).", repeats.len());
+        regions = bias_intervals(&regions, &repeats, repeat_bias_fraction);
+    }
+
+    // Place misassemblies by coverage.
+    if let Some(coverage_regions) = input_regions.coverage.as_ref() {
+        let empty_regions = Regions::new();
+        let record_coverage = coverage_regions.get(record_name).unwrap_or_else(|| {
+            log::warn!("No coverage of {record_name:?} in the coverage bedGraph.");
+            &empty_regions
+        });
+        regions = coverage_intervals(&regions, record_coverage, placement.coverage_bias);
+    }
+    // Cluster misassemblies around hotspots.
+    if let (Some(cluster_size), Some(number)) = (placement.cluster_size, number) {
+        let num_hotspots = number.div_ceil(cluster_size.max(1));
+        debug!("Clustering misassemblies around {num_hotspots} hotspot(s).");
+        regions = hotspot_intervals(&regions, num_hotspots, placement.cluster_spread, rng)?;
+    }
+    Ok(regions)
+}
+
+/// Place the misassemblies of a command within the sampling regions of a record with a [`BuiltinGenerator`].
+/// `length` replaces the length of the command if given. ex. A fraction of the record's regions.
+/// `None` if the record is skipped because the misassemblies don't fit.
+#[allow(clippy::too_many_arguments)]
+pub fn place_record(
+    placement: &PlacementConfig,
+    misassembly: &MisassemblyCommands,
+    record_name: &str,
+    record_length: usize,
+    regions: &Regions,
+    length: Option<usize>,
+    remaining_bases: Option<usize>,
+    rng: &mut impl Rng,
+) -> eyre::Result<Option<Vec<Variant>>> {
+    let randomize_length = placement.randomize_length;
+    // Longest region a misassembly can be placed in.
+    let max_region_length = regions
+        .unsorted_iter()
+        .map(|(interval, _)| usize::from(interval.end) - usize::from(interval.start))
+        .max()
+        .unwrap_or(0);
+    let mut command = misassembly.clone();
+    let mut min_length = placement.min_length;
+    match &mut command {
+        MisassemblyCommands::Misjoin {
+            length: cmd_length, ..
+        }
+        | MisassemblyCommands::Gap {
+            length: cmd_length, ..
+        }
+        | MisassemblyCommands::FalseDuplication {
+            length: cmd_length, ..
+        } => {
+            if let Some(length) = length {
+                *cmd_length = length;
+            }
+            // A start is chosen before the end of the region less the length.
+            // Randomized lengths only need to fit the minimum length.
+            let required_length = if randomize_length {
+                min_length
+            } else {
+                *cmd_length
+            };
+            if required_length >= max_region_length {
+                let msg = format!(
+                    "Misassembly length ({required_length}) doesn't fit in the longest region ({max_region_length} bp) of {record_name:?}."
+                );
+                match placement.short_record_policy {
+                    ShortRecordPolicy::Error => bail!(msg),
+                    ShortRecordPolicy::Skip => {
+                        log::warn!("{msg} Skipping.");
+                        return Ok(None);
+                    }
+                    ShortRecordPolicy::Shrink => {
+                        let fit_length = max_region_length.saturating_sub(1).max(1);
+                        *cmd_length = (*cmd_length).min(fit_length);
+                        min_length = min_length.min(fit_length);
+                        log::warn!("{msg} Shrinking to {fit_length} bp.");
+                    }
+                }
+            }
+            debug!("Misassembly length: {cmd_length}");
+        }
+        MisassemblyCommands::Break { .. } => {}
+    }
+    let generator = BuiltinGenerator {
+        command,
+        randomize_length,
+        min_length,
+        max_total_bases: remaining_bases,
+    };
+    let variants = generator.generate(record_length, regions, rng)?;
+    check_placed_count(variants.len(), misassembly.number(), placement.strict_count)?;
+    Ok(Some(variants))
+}
+
+/// Generate the placed `variants` of a misassembly command in a record's sequence.
+pub fn generate_record<'a>(
+    misassembly: &MisassemblyCommands,
+    seq: &'a [u8],
+    variants: &[Variant],
+    soft_mask: bool,
+) -> eyre::Result<Generated<'a>> {
+    let unexpected =
+        |variant: &Variant| eyre::eyre!("{variant:?} isn't placed by {misassembly:?}.");
+    match *misassembly {
+        MisassemblyCommands::Misjoin { .. } | MisassemblyCommands::Gap { .. } => {
+            let is_gap = matches!(misassembly, MisassemblyCommands::Gap { .. });
+            let ranges = variants
+                .iter()
+                .map(|variant| match variant {
+                    Variant::Deletion(range) | Variant::Gap(range) => Ok(range.clone()),
+                    _ => Err(unexpected(variant)),
+                })
+                .collect::<eyre::Result<Vec<_>>>()?;
+            // If gap, mask deletion.
+            let deleted_seq = generate_deletion(seq, &ranges, is_gap, soft_mask);
+            debug!("{} sequence(s) removed.", deleted_seq.removed_seqs.len());
+            Ok(Generated::Deletion {
+                deleted_seq,
+                is_gap,
+            })
+        }
+        MisassemblyCommands::FalseDuplication { .. } => {
+            let duplications = variants
+                .iter()
+                .map(|variant| match variant {
+                    Variant::Duplication { range, count } => Ok((range.clone(), *count)),
+                    _ => Err(unexpected(variant)),
+                })
+                .collect::<eyre::Result<Vec<_>>>()?;
+            let false_dupe_seq = duplicate_segments(seq, &duplications, soft_mask);
+            debug!(
+                "{} sequence(s) duplicated.",
+                false_dupe_seq.duplicated_seqs.len()
+            );
+            Ok(Generated::FalseDuplication(false_dupe_seq))
+        }
+        MisassemblyCommands::Break { .. } => {
+            let positions = variants
+                .iter()
+                .map(|variant| match variant {
+                    Variant::Break { pos } => Ok(*pos),
+                    _ => Err(unexpected(variant)),
+                })
+                .collect::<eyre::Result<Vec<_>>>()?;
+            let (seqs, breaks) = split_at_breaks(seq, positions);
+            Ok(Generated::Breaks(seqs, breaks))
+        }
+    }
+}
+
+/// Read the rename map of the output records, if any, with the suffix of misassembled records.
+pub fn read_rename_map(rename_map: Option<&Path>, suffix: Option<&str>) -> eyre::Result<RenameMap> {
+    let renames = rename_map
+        .map(RenameMap::read)
+        .transpose()?
+        .unwrap_or_default();
+    Ok(renames.with_suffix(suffix.unwrap_or_default()))
+}
+
+/// Open the output sequence and bed files. Records are split into files if requested.
+fn open_writers(
+    output: &OutputConfig,
+) -> eyre::Result<(FastaWriter<OutputStream>, Option<TruthWriter>)> {
+    let (output_fa, mut output_bed) = get_outfile_writers(
+        output.outfile.clone(),
+        output.outbedfile.clone(),
+        output.truth_format,
+        output.dry_run,
+        output.compression_threads,
+        output.compression_level,
+        output.force,
+    )?;
+    if output.igv {
+        let description = format!("misasim misassemblies ({:?})", output.truth_format);
+        output_bed = output_bed
+            .map(|writer| writer.with_igv_track("misasim", &description))
+            .transpose()?;
+    }
+    let mut writer_fa = FastaWriter::new(
+        output_fa,
+        output.output_format,
+        output.line_width,
+        output.index_outfile,
+    );
+    if let Some(split_output_dir) = output.split_output_dir.clone() {
+        std::fs::create_dir_all(&split_output_dir)?;
+        log::info!("Writing each output record to {split_output_dir:?}");
+        writer_fa = writer_fa.with_split_dir(split_output_dir, output.force);
+    }
+    Ok((writer_fa, output_bed))
+}
+
+/// Output files of records written to memory in parallel. Added to the outputs in order with [`Outputs::append`].
+pub struct OutputBuffers {
+    pub writer_fa: FastaWriter<Vec<u8>>,
+    pub writer_bed: Option<TruthWriter<Vec<u8>>>,
+}
+
+/// Outputs of a run and the statistics, event IDs, and plan of its misassemblies.
+pub struct Outputs {
+    pub writer_fa: FastaWriter<OutputStream>,
+    pub writer_bed: Option<TruthWriter>,
+    pub writer_cn: Option<BufWriter<File>>,
+    pub stats: RunStats,
+    pub event_ids: Option<EventIds>,
+    /// Misassemblies are added to the plan instead of written if planning.
+    pub plan: Option<Plan>,
+}
+
+impl Outputs {
+    /// Open the output files of a run. Records are renamed if requested.
+    pub fn open(output: &OutputConfig) -> eyre::Result<Self> {
+        let (writer_fa, writer_bed) = open_writers(output)?;
+        let renames = read_rename_map(output.rename_map.as_deref(), output.suffix.as_deref())?;
+        let writer_bed = writer_bed.map(|writer| writer.with_rename_map(renames.clone()));
+        let writer_fa = writer_fa.with_rename_map(renames);
+        if output.igv_session.is_some() && output.outfile.is_none() {
+            bail!("An IGV session requires an output sequence file.")
+        }
+        let writer_cn = output
+            .copy_number_bedgraph
+            .as_ref()
+            .map(|path| create_outfile(path, output.force).map(BufWriter::new))
+            .transpose()?;
+        Ok(Self {
+            writer_fa,
+            writer_bed,
+            writer_cn,
+            stats: RunStats::default(),
+            event_ids: output.event_ids.then(EventIds::default),
+            plan: None,
+        })
+    }
+
+    /// Write a record of `reader_fa` as is. Only its bed records are written if a dry run.
+    pub fn write_unchanged(
+        &mut self,
+        output: &OutputConfig,
+        reader_fa: &mut Fasta,
+        record_name: &str,
+        record_length: usize,
+    ) -> eyre::Result<()> {
+        if !output.dry_run {
+            reader_fa.copy_record(record_name, record_length, &mut self.writer_fa)?;
+        }
+        if let Some(writer_bed) = self.writer_bed.as_mut() {
+            writer_bed.write_unchanged(record_name, record_length)?;
+        }
+        if let Some(writer_cn) = self.writer_cn.as_mut() {
+            write_copy_number(writer_cn, record_name, record_length, &[])?;
+        }
+        Ok(())
+    }
+
+    /// Record the misassemblies of a generated record as events in the statistics and the plan.
+    /// `requested` is the number of misassemblies requested of the command's type.
+    /// Returns the events of the misassemblies.
+    pub fn add_generated(
+        &mut self,
+        record: &fasta::Record,
+        generated: &Generated,
+        requested: usize,
+    ) -> eyre::Result<Vec<Event>> {
+        let seq = record.sequence().as_ref();
+        let record_name = std::str::from_utf8(record.name())?;
+        let (requested, variants) = match generated {
+            Generated::Variants { variants, .. } => (
+                variants
+                    .iter()
+                    .map(|variant| (variant.sequence_type(), 1))
+                    .collect_vec(),
+                variants.to_vec(),
+            ),
+            Generated::Deletion {
+                deleted_seq,
+                is_gap,
+            } => {
+                let mtype = if *is_gap {
+                    SequenceType::Gap
+                } else {
+                    SequenceType::Misjoin
+                };
+                let variants = deleted_seq
+                    .removed_seqs
+                    .iter()
+                    .map(|rem_seq| {
+                        let range = rem_seq.start..rem_seq.end;
+                        if *is_gap {
+                            Variant::Gap(range)
+                        } else {
+                            Variant::Deletion(range)
+                        }
+                    })
+                    .collect_vec();
+                (vec![(mtype, requested)], variants)
+            }
+            Generated::FalseDuplication(false_dupe_seq) => {
+                let variants = false_dupe_seq
+                    .duplicated_seqs
+                    .iter()
+                    .map(|repeat| Variant::Duplication {
+                        range: repeat.start..repeat.start + repeat.seq.len(),
+                        count: repeat.count,
+                    })
+                    .collect_vec();
+                (vec![(SequenceType::FalseDuplication, requested)], variants)
+            }
+            Generated::Breaks(_, breaks) => {
+                // Skip the first segment which starts at the record start.
+                let variants = breaks
+                    .iter()
+                    .skip(1)
+                    .map(|broken_seq| Variant::Break {
+                        pos: broken_seq.start - 1,
+                    })
+                    .collect_vec();
+                (vec![(SequenceType::Break, requested)], variants)
+            }
+        };
+        let events = variants
+            .into_iter()
+            .map(|variant| Event {
+                id: self.event_ids.as_mut().map(EventIds::next_id),
+                variant,
+            })
+            .collect_vec();
+        self.stats
+            .add_record(record_name, requested.iter().copied(), &events);
+        if let Some(plan) = self.plan.as_mut() {
+            plan.add_record(record_name, seq, events.clone())?;
+        }
+        Ok(events)
+    }
+
+    /// Write the copy number of a misassembled record with its `events`.
+    pub fn write_copy_number(
+        &mut self,
+        record_name: &str,
+        record_length: usize,
+        events: &[Event],
+    ) -> eyre::Result<()> {
+        if let Some(writer_cn) = self.writer_cn.as_mut() {
+            let variants = events
+                .iter()
+                .map(|event| event.variant.clone())
+                .collect_vec();
+            write_copy_number(writer_cn, record_name, record_length, &variants)?;
+        }
+        Ok(())
+    }
+
+    /// Write a misassembled record with its misassemblies in `regions` or add them to the plan.
+    /// `requested` is the number of misassemblies requested of the command's type.
+    /// Returns the events of the misassemblies.
+    pub fn write_misassembled(
+        &mut self,
+        output: &OutputConfig,
+        record: &fasta::Record,
+        regions: &Regions,
+        generated: Generated,
+        requested: usize,
+    ) -> eyre::Result<Vec<Event>> {
+        let events = self.add_generated(record, &generated, requested)?;
+        let record_name = std::str::from_utf8(record.name())?;
+        self.write_copy_number(record_name, record.sequence().len(), &events)?;
+        if self.plan.is_some() {
+            return Ok(events);
+        }
+        write_generated(
+            output,
+            record,
+            regions,
+            generated,
+            &events,
+            &mut self.writer_fa,
+            &mut self.writer_bed,
+        )?;
+        Ok(events)
+    }
+
+    /// Buffers of the output files to write records to in parallel.
+    pub fn buffers(&self) -> OutputBuffers {
+        OutputBuffers {
+            writer_fa: self.writer_fa.buffer(),
+            writer_bed: self.writer_bed.as_ref().map(TruthWriter::buffer),
+        }
+    }
+
+    /// Write the records of `buffers` after the records written so far.
+    pub fn append(&mut self, buffers: OutputBuffers) -> eyre::Result<()> {
+        self.writer_fa.append(buffers.writer_fa)?;
+        if let (Some(writer_bed), Some(buffer_bed)) = (self.writer_bed.as_mut(), buffers.writer_bed)
+        {
+            writer_bed.append(buffer_bed)?;
+        }
+        Ok(())
+    }
+
+    /// Flush the outputs and write the statistics, the plan, the IGV session, and the output sequence index.
+    pub fn finish(mut self, output: &OutputConfig) -> eyre::Result<()> {
+        if let Some(writer_cn) = self.writer_cn.as_mut() {
+            writer_cn.flush()?;
+        }
+        self.stats.log();
+        if let Some(stats_json) = output.stats_json.as_ref() {
+            self.stats
+                .write_json(BufWriter::new(create_outfile(stats_json, output.force)?))?;
+        }
+        if let Some(plan) = self.plan {
+            plan.write(std::io::stdout().lock())?;
+            return Ok(());
+        }
+        if let Some((igv_session, outfile)) =
+            output.igv_session.as_ref().zip(output.outfile.as_ref())
+        {
+            let tracks = output
+                .outbedfile
+                .iter()
+                .chain(output.copy_number_bedgraph.iter())
+                .map(PathBuf::as_path)
+                .collect_vec();
+            write_igv_session(
+                BufWriter::new(create_outfile(igv_session, output.force)?),
+                outfile,
+                &tracks,
+            )?;
+        }
+        self.writer_fa
+            .finish(output.outfile.as_deref().filter(|_| output.index_outfile))
+    }
+}
+
+/// Write a misassembled record with its misassemblies in `regions` and their `events`.
+pub fn write_generated<O: Write, B: Write>(
+    output: &OutputConfig,
+    record: &fasta::Record,
+    regions: &Regions,
+    generated: Generated,
+    events: &[Event],
+    writer_fa: &mut FastaWriter<O>,
+    writer_bed: &mut Option<TruthWriter<B>>,
+) -> eyre::Result<()> {
+    let seq = record.sequence().as_ref();
+
+    // Write the untouched record alongside the misassembled one.
+    if output.keep_original {
+        let definition = Definition::new(
+            [record.name(), ORIGINAL_RECORD_SUFFIX.as_bytes()].concat(),
+            record.description().map(|desc| desc.to_vec()),
+        );
+        if let Some(writer_bed) = writer_bed.as_mut() {
+            writer_bed.write_unchanged(std::str::from_utf8(definition.name())?, seq.len())?;
+        }
+        writer_fa.write_record(&fasta::Record::new(definition, record.sequence().clone()))?;
+    }
+
+    // Misassembled records are renamed with the suffix and annotated with a note of their misassemblies.
+    let output_definition = |note: String| {
+        let definition = Definition::new(
+            [
+                record.name(),
+                output.suffix.as_deref().unwrap_or_default().as_bytes(),
+            ]
+            .concat(),
+            record.description().map(<[u8]>::to_vec),
+        );
+        if output.annotate_description {
+            annotate_definition(&definition, &note)
+        } else {
+            definition
+        }
+    };
+
+    debug!("With regions: {:?}.", regions);
+    if let Some(writer_bed) = writer_bed.as_mut() {
+        writer_bed.set_region_names(regions);
+        writer_bed.set_event_ids(events);
+    }
+    match generated {
+        Generated::Variants { variants, note } => {
+            let definition = output_definition(format!("misasim:{note}={}", variants.len()));
+            write_variants(
+                definition,
+                seq,
+                variants,
+                output.soft_mask,
+                writer_fa,
+                writer_bed,
+            )?;
+        }
+        Generated::Deletion {
+            deleted_seq,
+            is_gap,
+        } => {
+            let (note, mtype) = if is_gap {
+                ("gap", SequenceType::Gap)
+            } else {
+                ("misjoin", SequenceType::Misjoin)
+            };
+            let definition =
+                output_definition(format!("misasim:{note}={}", deleted_seq.removed_seqs.len()));
+            write_misassembly(
+                deleted_seq.seq,
+                mtype,
+                deleted_seq.removed_seqs,
+                &deleted_seq.misassembled_ranges,
+                definition,
+                writer_fa,
+                writer_bed.as_mut(),
+            )?;
+        }
+        Generated::FalseDuplication(false_dupe_seq) => {
+            let definition = output_definition(format!(
+                "misasim:false_duplication={}",
+                false_dupe_seq.duplicated_seqs.len()
+            ));
+            write_misassembly(
+                false_dupe_seq.seq,
+                SequenceType::FalseDuplication,
+                false_dupe_seq.duplicated_seqs,
+                &false_dupe_seq.misassembled_ranges,
+                definition,
+                writer_fa,
+                writer_bed.as_mut(),
+            )?;
+        }
+        Generated::Breaks(seqs, breaks) => {
+            let definition = output_definition(format!("misasim:break={}", breaks.len()));
+            write_breaks(
+                std::str::from_utf8(definition.name())?,
+                definition.description(),
+                (seqs, breaks),
+                writer_fa,
+                writer_bed,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Misassemble one replicate of the records of `reader_fa`.
+fn generate_replicate(config: Config, reader_fa: &mut Fasta) -> eyre::Result<()> {
+    let Config {
+        command,
+        input,
+        selection,
+        placement,
+        output,
+        seed,
+        rng: rng_kind,
+        threads,
+        progress_interval,
+        ..
+    } = config;
+    // Only sample misassemblies if planning.
+    let (command, plan) = match command {
+        Commands::Plan { command } => (Commands::Misassembly(command), Some(Plan::default())),
+        command => (command, None),
+    };
+    let input_regions = InputRegions::read(&input)?;
+
+    if output.index_outfile && output.outfile.is_none() && output.split_output_dir.is_none() {
+        bail!("Cannot index output fasta written to stdout.")
+    }
+    if output.index_outfile && output.output_format == OutputFormat::Fastq {
+        bail!("Cannot index fastq output.")
+    }
+    if selection
+        .proportion
+        .is_some_and(|p| !(0.0..=1.0).contains(&p))
+    {
+        bail!("Proportion must be between 0 and 1.")
+    }
+    if placement
+        .bias_fraction
+        .is_some_and(|f| !(0.0..=1.0).contains(&f))
+    {
+        bail!("Bias fraction must be between 0 and 1.")
+    }
+    if placement
+        .repeat_bias_fraction
+        .is_some_and(|f| !(0.0..=1.0).contains(&f))
+    {
+        bail!("Repeat bias fraction must be between 0 and 1.")
+    }
+    if let Commands::Misassembly(MisassemblyCommands::FalseDuplication {
+        max_duplications, ..
+    }) = &command
+    {
+        check_max_duplications(*max_duplications)?;
+    }
+    if placement
+        .length_frac
+        .is_some_and(|f| !(f > 0.0 && f <= 1.0))
+    {
+        bail!("Length fraction must be greater than 0 and at most 1.")
+    }
+    if output.compression_level > 9 {
+        bail!("Compression level must be between 0 and 9.")
+    }
+    if output.dry_run {
+        log::info!("Dry run. Not writing any sequences.");
+        if output.outbedfile.is_none() {
+            log::warn!("Dry run without an output bed file.");
+        }
+    }
+    if let Commands::Diploid {
+        hap1,
+        hap2,
+        truth_vcf,
+        heterozygosity,
+        indel_fraction,
+        max_indel_length,
+    } = &command
+    {
+        if !(0.0..=1.0).contains(heterozygosity) || !(0.0..=1.0).contains(indel_fraction) {
+            bail!("Heterozygosity and indel fraction must be between 0 and 1.")
+        }
+        let [writer_hap1, writer_hap2] = [hap1, hap2].map(|path| {
+            let (output_fa, _) = get_outfile_writers(
+                Some(path.clone()),
+                None,
+                output.truth_format,
+                false,
+                output.compression_threads,
+                output.compression_level,
+                output.force,
+            )?;
+            eyre::Ok(FastaWriter::new(
+                output_fa,
+                OutputFormat::Fasta,
+                output.line_width,
+                false,
+            ))
+        });
+        let mut writers_fa = [writer_hap1?, writer_hap2?];
+        let mut writer_vcf = truth_vcf
+            .as_ref()
+            .map(|path| create_outfile(path, output.force).map(BufWriter::new))
+            .transpose()?;
+        simulate_diploid(
+            reader_fa,
+            HeterozygosityConfig {
+                heterozygosity: *heterozygosity,
+                indel_fraction: *indel_fraction,
+                max_indel_length: *max_indel_length,
+            },
+            get_seed(seed),
+            rng_kind,
+            &mut writers_fa,
+            writer_vcf.as_mut().map(|writer| writer as &mut dyn Write),
+            output.event_ids,
+        )?;
+        for writer_fa in writers_fa {
+            writer_fa.into_inner().finish()?;
+        }
+        if let Some(mut writer_vcf) = writer_vcf {
+            writer_vcf.flush()?;
+        }
+        return Ok(());
+    }
+
+    if let Commands::Reads {
+        platform,
+        coverage,
+        mean_read_length,
+        read_length_sd,
+        error_rate,
+    } = command
+    {
+        let default_config = ReadConfig::new(platform, coverage);
+        let config = ReadConfig {
+            mean_length: mean_read_length.unwrap_or(default_config.mean_length),
+            length_sd: read_length_sd.unwrap_or(default_config.length_sd),
+            error_rate: error_rate.unwrap_or(default_config.error_rate),
+            ..default_config
+        };
+        let (output_fq, _) = get_outfile_writers(
+            output.outfile,
+            None,
+            output.truth_format,
+            output.dry_run,
+            output.compression_threads,
+            output.compression_level,
+            output.force,
+        )?;
+        let mut output_fq = BufWriter::new(output_fq);
+        let num_reads =
+            simulate_reads(reader_fa, config, get_seed(seed), rng_kind, &mut output_fq)?;
+        log::info!("Simulated {num_reads} read(s).");
+        return output_fq
+            .into_inner()
+            .map_err(|err| err.into_error())?
+            .finish();
+    }
+
+    if let Commands::Revert { truth, gaps } = &command {
+        let (mut writer_fa, _) = open_writers(&output)?;
+        let renames = read_rename_map(output.rename_map.as_deref(), output.suffix.as_deref())?;
+        revert(
+            reader_fa,
+            &read_events(truth, *gaps)?,
+            &renames,
+            &mut writer_fa,
+        )?;
+        return writer_fa.finish(output.outfile.as_deref().filter(|_| output.index_outfile));
+    }
+    let mut outputs = Outputs {
+        plan,
+        ..Outputs::open(&output)?
+    };
+
+    // Records are seeded by name from a global seed so results don't depend on record order.
+    let seed = get_seed(seed);
+    let randomize_length = placement.randomize_length;
+    log::info!("Random number generator: {rng_kind:?}");
+    log::info!("Randomizing length: {randomize_length}");
+    if randomize_length {
+        log::info!("Minimum length: {}", placement.min_length);
+    }
+
+    let lengths = reader_fa.lengths();
+    let mut progress = progress_interval.map(|secs| {
+        Progress::new(
+            lengths.len(),
+            lengths.iter().map(|(_, length)| length).sum(),
+            Duration::from_secs(secs),
+        )
+    });
+
+    // Check that regions map to records.
+    if let Some(regions) = input_regions.regions.as_ref() {
+        for name in regions.keys() {
+            if lengths.iter().any(|(rec_name, _)| rec_name == name) {
+                continue;
+            }
+            if placement.strict_regions {
+                bail!("Record {name:?} in input bed file not found in input sequence file.")
+            }
+            log::warn!(
+                "Record {name:?} in input bed file not found in input sequence file. Skipping."
+            );
+        }
+    }
+
+    let variants = read_variants(&command, &input, reader_fa)?;
+    let note = if input.replay || !input.at.is_empty() {
+        "replay"
+    } else {
+        "apply"
+    };
+    let groups = select_records(
+        &selection,
+        lengths,
+        input_regions.regions.as_ref(),
+        variants.as_ref(),
+        seed,
+        rng_kind,
+    )?;
+
+    // Bases remaining to misassemble over all records.
+    let mut remaining_bases = placement.max_total_bases;
+    // Check if a misassembly can still be placed.
+    let min_bases = match &command {
+        Commands::Misassembly(misassembly) if randomize_length => {
+            misassembly.length().map(|_| placement.min_length)
+        }
+        Commands::Misassembly(misassembly) => misassembly.length(),
+        _ => None,
+    };
+    let has_remaining_bases = |remaining_bases: Option<usize>| {
+        remaining_bases
+            .zip(min_bases)
+            .is_none_or(|(remaining_bases, min_bases)| remaining_bases >= min_bases)
+    };
+    // Number of misassemblies requested of the command.
+    let number = match &command {
+        Commands::Misassembly(misassembly) => Some(misassembly.number()),
+        _ => None,
+    };
+    // Misassembled records are processed one at a time to stay within the total bases.
+    let batch_size = if placement.max_total_bases.is_some() {
+        1
+    } else {
+        threads
+    };
+    // Records are fetched in parallel with a reader per thread.
+    let readers = (0..batch_size)
+        .map(|_| reader_fa.try_clone().map(Mutex::new))
+        .collect::<eyre::Result<Vec<_>>>()?;
+    let is_planning = outputs.plan.is_some();
+    // Fetch a record and place its misassemblies. `None` if the record is skipped.
+    let prepare_record = |record_name: &str,
+                          record_length: usize,
+                          remaining_bases: Option<usize>,
+                          reader_fa: &mut Fasta|
+     -> eyre::Result<Option<Prepared>> {
+        debug!("Misassembling {record_name:?}.");
+        let mut record_rng = SimRng::new(rng_kind, record_seed(seed, record_name));
+        // Only the misassembled ranges are needed if no sequences are written.
+        let is_sparse = output.dry_run || is_planning;
+        let record = if is_sparse {
+            None
+        } else {
+            Some(reader_fa.fetch(record_name, 1, record_length)?)
+        };
+        let regions = record_regions(
+            &placement,
+            input_regions.regions.as_ref(),
+            record_name,
+            record_length,
+            variants.is_some(),
+        )?;
+        // Scale the length to the bases of the record's regions.
+        let frac_length = placement.length_frac.map(|length_frac| {
+            let region_bases: usize = merge_intervals(&regions)
+                .unsorted_iter()
+                .map(|(interval, _)| usize::from(interval.end) - usize::from(interval.start))
+                .sum();
+            ((region_bases as f64 * length_frac).round() as usize).max(1)
+        });
+        let regions = sampling_regions(
+            &placement,
+            &input_regions,
+            regions,
+            record_name,
+            record_length,
+            record.as_ref(),
+            reader_fa,
+            number,
+            &mut record_rng,
+        )?;
+
+        let record_variants = variants.as_ref().and_then(|v| v.get(record_name));
+        let placed = if record_variants.is_some() {
+            vec![]
+        } else {
+            let Commands::Misassembly(misassembly) = &command else {
+                bail!("{command:?} doesn't generate random misassemblies.")
+            };
+            let Some(placed) = place_record(
+                &placement,
+                misassembly,
+                record_name,
+                record_length,
+                &regions,
+                frac_length,
+                remaining_bases,
+                &mut record_rng,
+            )?
+            else {
+                return Ok(None);
+            };
+            placed
+        };
+        let record = match record {
+            Some(record) => record,
+            None => {
+                // Plans include the flanks of each misassembly's junctions.
+                let flank = if is_planning { JUNCTION_FLANK } else { 0 };
+                let fetched_ranges = record_variants
+                    .unwrap_or(&placed)
+                    .iter()
+                    .map(Variant::range)
+                    .map(|range| {
+                        range.start.saturating_sub(flank)..(range.end + flank).min(record_length)
+                    })
+                    .collect_vec();
+                reader_fa.fetch_ranges(record_name, record_length, &fetched_ranges)?
+            }
+        };
+        Ok(Some(Prepared {
+            record,
+            regions,
+            placed,
+        }))
+    };
+    for group in &groups {
+        if selection.group_by.is_some() {
+            log::info!("Grouping by: {:?}", group.captures)
+        }
+        let mut batch = Vec::new();
+        let mut num_batch_misassembled = 0;
+        for (i, rec) in group.records.iter().enumerate() {
+            if let Some(progress) = progress.as_mut() {
+                progress.next(rec.1);
+            }
+
+            // If not chosen misassembled sequence, then just write record as is.
+            // Or skip it entirely if only writing misassembled records.
+            let is_misassembled = group.chosen.contains(rec)
+                && (variants.is_some() || has_remaining_bases(remaining_bases));
+            if is_misassembled {
+                batch.push(Pending::Misassembled(rec));
+                num_batch_misassembled += 1;
+            } else if !output.only_misassembled && !is_planning {
+                batch.push(Pending::Unchanged(rec));
+            }
+            if num_batch_misassembled < batch_size && i + 1 < group.records.len() {
+                continue;
+            }
+
+            // Fetch and misassemble a batch of records in parallel.
+            let prepared = batch
+                .par_iter()
+                .map(|pending| {
+                    let Pending::Misassembled((record_name, record_length)) = pending else {
+                        return Ok(None);
+                    };
+                    let reader =
+                        &readers[rayon::current_thread_index().unwrap_or(0) % readers.len()];
+                    let Ok(mut reader_fa) = reader.lock() else {
+                        bail!("Reader of another thread panicked.")
+                    };
+                    prepare_record(
+                        record_name,
+                        (*record_length).try_into()?,
+                        remaining_bases,
+                        &mut reader_fa,
+                    )
+                })
+                .collect::<eyre::Result<Vec<_>>>()?;
+            let generated = prepared
+                .par_iter()
+                .map(|prepared| {
+                    let Some(Prepared { record, placed, .. }) = prepared else {
+                        return Ok(None);
+                    };
+                    let seq = record.sequence().as_ref();
+                    let record_name = std::str::from_utf8(record.name())?;
+                    if let Some(record_variants) =
+                        variants.as_ref().and_then(|v| v.get(record_name))
+                    {
+                        return Ok(Some(Generated::Variants {
+                            variants: record_variants,
+                            note,
+                        }));
+                    }
+                    let Commands::Misassembly(misassembly) = &command else {
+                        bail!("{command:?} doesn't generate random misassemblies.")
+                    };
+                    Ok(Some(generate_record(
+                        misassembly,
+                        seq,
+                        placed,
+                        output.soft_mask,
+                    )?))
+                })
+                .collect::<eyre::Result<Vec<_>>>()?;
+
+            // Events are numbered in order.
+            let events = prepared
+                .iter()
+                .zip(&generated)
+                .map(|(prepared, generated)| {
+                    let (Some(Prepared { record, .. }), Some(generated)) = (prepared, generated)
+                    else {
+                        return Ok(None);
+                    };
+                    let events =
+                        outputs.add_generated(record, generated, number.unwrap_or_default())?;
+                    // Only randomly placed misassemblies count towards the total bases.
+                    if let Some(remaining_bases) =
+                        remaining_bases.as_mut().filter(|_| variants.is_none())
+                    {
+                        let bases: usize =
+                            events.iter().map(|event| event.variant.range().len()).sum();
+                        *remaining_bases = remaining_bases.saturating_sub(bases);
+                    }
+                    Ok(Some(events))
+                })
+                .collect::<eyre::Result<Vec<_>>>()?;
+
+            // Write misassembled records to memory in parallel.
+            let mut buffers = generated
+                .iter()
+                .map(|generated| (generated.is_some() && !is_planning).then(|| outputs.buffers()))
+                .collect_vec();
+            buffers
+                .par_iter_mut()
+                .zip(generated)
+                .zip(&prepared)
+                .zip(&events)
+                .try_for_each(|(((buffers, generated), prepared), events)| {
+                    let (Some(buffers), Some(generated), Some(prepared), Some(events)) =
+                        (buffers, generated, prepared, events)
+                    else {
+                        return Ok(());
+                    };
+                    write_generated(
+                        &output,
+                        &prepared.record,
+                        &prepared.regions,
+                        generated,
+                        events,
+                        &mut buffers.writer_fa,
+                        &mut buffers.writer_bed,
+                    )
+                })?;
+
+            // Write the batch in order.
+            for ((pending, events), buffers) in batch.iter().zip(events).zip(buffers) {
+                let (Pending::Unchanged((record_name, record_length))
+                | Pending::Misassembled((record_name, record_length))) = pending;
+                let record_length = (*record_length).try_into()?;
+                let Some(events) = events else {
+                    // Records whose misassemblies don't fit are written as is.
+                    if !output.only_misassembled && !is_planning {
+                        outputs.write_unchanged(&output, reader_fa, record_name, record_length)?;
+                    }
+                    continue;
+                };
+                outputs.write_copy_number(record_name, record_length, &events)?;
+                if let Some(buffers) = buffers {
+                    outputs.append(buffers)?;
+                }
+            }
+            batch.clear();
+            num_batch_misassembled = 0;
+        }
+    }
+    if let Some(progress) = progress.as_mut() {
+        progress.finish();
+    }
+    outputs.finish(&output)
+}
@@ -0,0 +1,468 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+};
+
+use eyre::{bail, ContextCompat};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::io::{read_bed_records, RenameMap, ID_FIELD_PREFIX, REGION_FIELD_PREFIX};
+
+/// Misassembly read from a misasim truth BED file in original coordinates.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// Removed sequence. Gaps keep their length and don't shift coordinates.
+    Deletion {
+        start: usize,
+        end: usize,
+        gap: bool,
+        seq: String,
+    },
+    /// Sequence repeated `count` times in total.
+    Duplication {
+        start: usize,
+        end: usize,
+        count: usize,
+    },
+    /// Record split into a new record.
+    Break { start: usize, end: usize },
+    /// Sequence of length `len` inserted before `pos`.
+    Insertion { pos: usize, len: usize },
+    /// Reverse complemented sequence.
+    Inversion { start: usize, end: usize },
+}
+
+impl Event {
+    fn start(&self) -> usize {
+        match self {
+            Event::Deletion { start, .. }
+            | Event::Duplication { start, .. }
+            | Event::Break { start, .. }
+            | Event::Inversion { start, .. } => *start,
+            Event::Insertion { pos, .. } => *pos,
+        }
+    }
+}
+
+/// Segment of an original record and its start on an output record.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Segment {
+    pub start: usize,
+    pub end: usize,
+    pub new_name: String,
+    pub new_start: usize,
+    /// Segment is reverse complemented.
+    pub reverse: bool,
+}
+
+/// Read misassemblies from a truth BED file written with "--truth-format misasim".
+/// Deletions are treated as gaps if `gaps` is set.
+pub fn read_events(bed: impl AsRef<Path>, gaps: bool) -> eyre::Result<HashMap<String, Vec<Event>>> {
+    let mut events: HashMap<String, Vec<Event>> = HashMap::new();
+    for rec in read_bed_records(bed)? {
+        let rec = rec?;
+        let start = usize::from(rec.start_position()) - 1;
+        let end = usize::from(rec.end_position());
+        // Skip the names of input regions and event IDs.
+        let fields = rec
+            .optional_fields()
+            .iter()
+            .filter(|field| {
+                !field.starts_with(REGION_FIELD_PREFIX) && !field.starts_with(ID_FIELD_PREFIX)
+            })
+            .collect_vec();
+        let event = match &fields[..] {
+            [label] if *label == "Broken" => Event::Break { start, end },
+            [label] if *label == "Inverted" => Event::Inversion { start, end },
+            [label, seq] if *label == "Inserted" => Event::Insertion {
+                pos: end,
+                len: seq.len(),
+            },
+            [seq] => Event::Deletion {
+                start,
+                end,
+                gap: gaps,
+                seq: seq.to_string(),
+            },
+            [count, seq] => Event::Duplication {
+                start,
+                end: start + seq.len(),
+                count: count.parse()?,
+            },
+            _ => bail!("Invalid misasim truth BED record: {rec}"),
+        };
+        // Broken records are named by their coordinates. ex. chr1:1-100
+        let name = rec.reference_sequence_name();
+        let name = match event {
+            Event::Break { start, end } => name
+                .strip_suffix(&format!(":{}-{end}", start + 1))
+                .unwrap_or(name),
+            _ => name,
+        };
+        events.entry(name.to_owned()).or_default().push(event);
+    }
+    Ok(events)
+}
+
+/// Build segments of a record mapping original coordinates to output records.
+/// `name` is the output name of the record.
+/// Segments are sorted and the last segment of unbroken records is unbounded.
+pub fn build_segments(name: &str, events: &[Event]) -> Vec<Segment> {
+    let events = events
+        .iter()
+        .sorted_by_key(|event| event.start())
+        .collect_vec();
+
+    // Broken records are split into records named by their 1-based coordinates.
+    if events
+        .iter()
+        .any(|event| matches!(event, Event::Break { .. }))
+    {
+        return events
+            .into_iter()
+            .filter_map(|event| match event {
+                Event::Break { start, end } => Some(Segment {
+                    start: *start,
+                    end: *end,
+                    new_name: format!("{name}:{}-{end}", start + 1),
+                    new_start: 0,
+                    reverse: false,
+                }),
+                _ => None,
+            })
+            .collect();
+    }
+
+    let mut segments = vec![];
+    let (mut pos, mut new_pos) = (0, 0);
+    for event in events {
+        match event {
+            Event::Deletion {
+                start,
+                end,
+                gap: false,
+                ..
+            } => {
+                segments.push(Segment {
+                    start: pos,
+                    end: *start,
+                    new_name: name.to_owned(),
+                    new_start: new_pos,
+                    reverse: false,
+                });
+                new_pos += start - pos;
+                pos = *end;
+            }
+            Event::Duplication { start, end, count } => {
+                segments.push(Segment {
+                    start: pos,
+                    end: *end,
+                    new_name: name.to_owned(),
+                    new_start: new_pos,
+                    reverse: false,
+                });
+                new_pos += (end - pos) + (end - start) * count.saturating_sub(1);
+                pos = *end;
+            }
+            Event::Insertion { pos: ins_pos, len } => {
+                segments.push(Segment {
+                    start: pos,
+                    end: *ins_pos,
+                    new_name: name.to_owned(),
+                    new_start: new_pos,
+                    reverse: false,
+                });
+                new_pos += (ins_pos - pos) + len;
+                pos = *ins_pos;
+            }
+            Event::Inversion { start, end } => {
+                for (seg_start, seg_end, reverse) in [(pos, *start, false), (*start, *end, true)] {
+                    segments.push(Segment {
+                        start: seg_start,
+                        end: seg_end,
+                        new_name: name.to_owned(),
+                        new_start: new_pos,
+                        reverse,
+                    });
+                    new_pos += seg_end - seg_start;
+                }
+                pos = *end;
+            }
+            Event::Deletion { gap: true, .. } | Event::Break { .. } => (),
+        }
+    }
+    segments.push(Segment {
+        start: pos,
+        end: usize::MAX,
+        new_name: name.to_owned(),
+        new_start: new_pos,
+        reverse: false,
+    });
+    segments
+}
+
+/// Map between coordinates of an original record and its output records.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct CoordinateMap {
+    segments: Vec<Segment>,
+}
+
+impl CoordinateMap {
+    /// Build the map of record `name` from its misassemblies.
+    pub fn new(name: &str, events: &[Event]) -> Self {
+        Self {
+            segments: build_segments(name, events),
+        }
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Output record and 0-based position of an original 0-based position.
+    /// Deleted positions have no output position.
+    pub fn to_new(&self, pos: usize) -> Option<(&str, usize)> {
+        let seg = self
+            .segments
+            .iter()
+            .find(|seg| (seg.start..seg.end).contains(&pos))?;
+        // Reverse complemented segments mirror coordinates.
+        let new_pos = if seg.reverse {
+            seg.new_start + (seg.end - pos - 1)
+        } else {
+            seg.new_start + (pos - seg.start)
+        };
+        Some((&seg.new_name, new_pos))
+    }
+
+    /// Original 0-based position of a 0-based position on output record `new_name`.
+    /// Inserted bases and extra copies of duplications have no original position.
+    pub fn to_old(&self, new_name: &str, new_pos: usize) -> Option<usize> {
+        self.segments.iter().find_map(|seg| {
+            let offset = new_pos.checked_sub(seg.new_start)?;
+            if seg.new_name != new_name || offset >= seg.end.saturating_sub(seg.start) {
+                return None;
+            }
+            Some(if seg.reverse {
+                seg.end - offset - 1
+            } else {
+                seg.start + offset
+            })
+        })
+    }
+
+    /// Lift a 0-based, half-open interval onto output records.
+    /// Parts in deletions are removed and parts on different records are split.
+    pub fn lift(&self, start: usize, end: usize) -> Vec<(String, usize, usize)> {
+        let mut lifted: Vec<(String, usize, usize)> = vec![];
+        for seg in self
+            .segments
+            .iter()
+            .filter(|seg| seg.start < end && start < seg.end)
+        {
+            let (start, end) = (start.max(seg.start), end.min(seg.end));
+            // Reverse complemented segments mirror coordinates.
+            let (new_start, new_end) = if seg.reverse {
+                (
+                    seg.new_start + (seg.end - end),
+                    seg.new_start + (seg.end - start),
+                )
+            } else {
+                (
+                    seg.new_start + (start - seg.start),
+                    seg.new_start + (end - seg.start),
+                )
+            };
+            match lifted.last_mut() {
+                Some((name, last_start, last_end)) if *name == seg.new_name => {
+                    *last_start = new_start.min(*last_start);
+                    *last_end = new_end.max(*last_end);
+                }
+                _ => lifted.push((seg.new_name.clone(), new_start, new_end)),
+            }
+        }
+        lifted
+    }
+}
+
+/// Lift annotations in a BED or GFF file onto the misassembled records.
+/// Features entirely within deletions are dropped and features spanning breaks are split.
+/// Strands of features in inversions aren't changed.
+/// Features are written on the output names of records from `renames`.
+pub fn liftover(
+    events: &HashMap<String, Vec<Event>>,
+    renames: &RenameMap,
+    annotations: impl AsRef<Path>,
+    mut writer: impl Write,
+) -> eyre::Result<()> {
+    let is_gff = annotations
+        .as_ref()
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| matches!(ext, "gff" | "gff3" | "gtf"));
+    // Columns of the start and end. GFF coordinates are 1-based and inclusive.
+    let (start_col, end_col, start_offset) = if is_gff { (3, 4, 1) } else { (1, 2, 0) };
+
+    let reader = File::open(annotations).map(BufReader::new)?;
+    let mut maps: HashMap<&str, CoordinateMap> = HashMap::new();
+    let mut num_dropped = 0;
+    for line in reader.lines() {
+        let line = line?;
+        if line.is_empty() || line.starts_with('#') || line.starts_with("track") {
+            writeln!(writer, "{line}")?;
+            continue;
+        }
+        let cols = line.split('\t').collect_vec();
+        let (Some(start), Some(end)) = (cols.get(start_col), cols.get(end_col)) else {
+            bail!("Invalid annotation: {line}")
+        };
+        let start = start.parse::<usize>()? - start_offset;
+        let end: usize = end.parse()?;
+
+        let name = cols.first().context("No record name.")?.to_owned();
+        let Some((name, events)) = events.get_key_value(name) else {
+            let new_name = renames.rename(name);
+            let mut new_cols = cols.clone();
+            new_cols[0] = &new_name;
+            writeln!(writer, "{}", new_cols.iter().join("\t"))?;
+            continue;
+        };
+        let lifted = maps
+            .entry(name)
+            .or_insert_with(|| CoordinateMap::new(&renames.output_name(name), events))
+            .lift(start, end);
+        if lifted.is_empty() {
+            num_dropped += 1;
+            continue;
+        }
+        for (new_name, new_start, new_end) in lifted {
+            let (new_start, new_end) =
+                ((new_start + start_offset).to_string(), new_end.to_string());
+            let mut new_cols = cols.clone();
+            new_cols[0] = &new_name;
+            new_cols[start_col] = &new_start;
+            new_cols[end_col] = &new_end;
+            writeln!(writer, "{}", new_cols.iter().join("\t"))?;
+        }
+    }
+    if num_dropped != 0 {
+        log::info!("Dropped {num_dropped} annotation(s) within deletions.");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_event_serde() {
+        let event = Event::Duplication {
+            start: 30,
+            end: 35,
+            count: 3,
+        };
+        let json = serde_json::to_string(&event).unwrap();
+        assert_eq!(
+            json,
+            r#"{"type":"duplication","start":30,"end":35,"count":3}"#
+        );
+        assert_eq!(serde_json::from_str::<Event>(&json).unwrap(), event);
+    }
+
+    #[test]
+    fn test_lift() {
+        let events = [
+            Event::Deletion {
+                start: 10,
+                end: 20,
+                gap: false,
+                seq: "A".repeat(10),
+            },
+            Event::Duplication {
+                start: 30,
+                end: 35,
+                count: 3,
+            },
+            Event::Deletion {
+                start: 50,
+                end: 55,
+                gap: true,
+                seq: "A".repeat(5),
+            },
+        ];
+        let map = CoordinateMap::new("chr1", &events);
+        let lift_one = |start, end| {
+            map.lift(start, end)
+                .into_iter()
+                .map(|(_, s, e)| (s, e))
+                .collect_vec()
+        };
+        // Before and after the deletion.
+        assert_eq!(lift_one(0, 5), [(0, 5)]);
+        assert_eq!(lift_one(20, 25), [(10, 15)]);
+        // Within the deletion.
+        assert_eq!(lift_one(12, 18), []);
+        // Truncated by the deletion.
+        assert_eq!(lift_one(5, 25), [(5, 15)]);
+        // Within the duplicated sequence and after the two extra copies.
+        assert_eq!(lift_one(31, 33), [(21, 23)]);
+        assert_eq!(lift_one(36, 40), [(36, 40)]);
+        // Gaps don't shift coordinates.
+        assert_eq!(lift_one(60, 70), [(60, 70)]);
+
+        assert_eq!(map.to_new(5), Some(("chr1", 5)));
+        assert_eq!(map.to_new(15), None);
+        assert_eq!(map.to_new(36), Some(("chr1", 36)));
+        assert_eq!(map.to_old("chr1", 21), Some(31));
+        // Within the extra copies.
+        assert_eq!(map.to_old("chr1", 27), None);
+        assert_eq!(map.to_old("chr1", 36), Some(36));
+        assert_eq!(map.to_old("chr2", 5), None);
+    }
+
+    #[test]
+    fn test_lift_insertion_inversion() {
+        let events = [
+            Event::Insertion { pos: 10, len: 5 },
+            Event::Inversion { start: 20, end: 30 },
+        ];
+        let map = CoordinateMap::new("chr1", &events);
+        let lift_one = |start, end| {
+            map.lift(start, end)
+                .into_iter()
+                .map(|(_, s, e)| (s, e))
+                .collect_vec()
+        };
+        assert_eq!(lift_one(0, 10), [(0, 10)]);
+        assert_eq!(lift_one(10, 15), [(15, 20)]);
+        // Mirrored within the inversion.
+        assert_eq!(lift_one(20, 22), [(33, 35)]);
+        assert_eq!(lift_one(15, 40), [(20, 45)]);
+
+        assert_eq!(map.to_new(20), Some(("chr1", 34)));
+        assert_eq!(map.to_old("chr1", 34), Some(20));
+        // Within the insertion.
+        assert_eq!(map.to_old("chr1", 12), None);
+    }
+
+    #[test]
+    fn test_lift_break() {
+        let events = [
+            Event::Break { start: 0, end: 16 },
+            Event::Break { start: 16, end: 46 },
+        ];
+        let map = CoordinateMap::new("chr1", &events);
+        assert_eq!(
+            map.lift(10, 20),
+            [
+                ("chr1:1-16".to_owned(), 10, 16),
+                ("chr1:17-46".to_owned(), 0, 4)
+            ]
+        );
+    }
+}
@@ -0,0 +1,156 @@
+use std::{
+    io::Write,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use log::{LevelFilter, Log, Metadata, Record};
+use serde::Serialize;
+
+/// Logger writing each log event to stderr as a JSON line.
+pub struct JsonLogger {
+    level: LevelFilter,
+}
+
+#[derive(Debug, Serialize)]
+struct LogEvent<'a> {
+    /// Milliseconds since the Unix epoch.
+    timestamp_ms: u128,
+    level: &'a str,
+    target: &'a str,
+    message: String,
+}
+
+impl<'a> LogEvent<'a> {
+    fn new(record: &'a Record) -> Self {
+        LogEvent {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_millis()),
+            level: record.level().as_str(),
+            target: record.target(),
+            message: record.args().to_string(),
+        }
+    }
+}
+
+impl JsonLogger {
+    pub fn init(level: LevelFilter) -> eyre::Result<()> {
+        log::set_boxed_logger(Box::new(JsonLogger { level }))?;
+        log::set_max_level(level);
+        Ok(())
+    }
+}
+
+impl Log for JsonLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let Ok(line) = serde_json::to_string(&LogEvent::new(record)) else {
+            return;
+        };
+        _ = writeln!(std::io::stderr().lock(), "{line}");
+    }
+
+    fn flush(&self) {
+        _ = std::io::stderr().flush();
+    }
+}
+
+/// Periodically log the number of records and bases processed.
+pub struct Progress {
+    interval: Duration,
+    last_logged: Instant,
+    total_records: usize,
+    total_bases: u64,
+    records: usize,
+    bases: u64,
+    /// Length of the record being processed.
+    pending: Option<u64>,
+}
+
+impl Progress {
+    pub fn new(total_records: usize, total_bases: u64, interval: Duration) -> Self {
+        Progress {
+            interval,
+            last_logged: Instant::now(),
+            total_records,
+            total_bases,
+            records: 0,
+            bases: 0,
+            pending: None,
+        }
+    }
+
+    /// Start processing a record of `length` bases, completing the previous record.
+    /// Progress is logged if the interval has passed since it was last logged.
+    pub fn next(&mut self, length: u64) {
+        self.complete_pending();
+        self.pending = Some(length);
+        if self.last_logged.elapsed() >= self.interval {
+            self.log();
+        }
+    }
+
+    /// Complete the last record and log progress.
+    pub fn finish(&mut self) {
+        self.complete_pending();
+        self.log();
+    }
+
+    fn complete_pending(&mut self) {
+        if let Some(length) = self.pending.take() {
+            self.records += 1;
+            self.bases += length;
+        }
+    }
+
+    fn log(&mut self) {
+        log::info!(
+            "Processed {}/{} record(s) and {}/{} bp.",
+            self.records,
+            self.total_records,
+            self.bases,
+            self.total_bases
+        );
+        self.last_logged = Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use log::Level;
+
+    use super::*;
+
+    #[test]
+    fn test_log_event() {
+        let json = serde_json::to_value(LogEvent::new(
+            &Record::builder()
+                .args(format_args!("Random seed: {}", 42))
+                .level(Level::Info)
+                .target("misasim")
+                .build(),
+        ))
+        .unwrap();
+        assert_eq!(json["level"], "INFO");
+        assert_eq!(json["target"], "misasim");
+        assert_eq!(json["message"], "Random seed: 42");
+        assert!(json["timestamp_ms"].is_u64());
+    }
+
+    #[test]
+    fn test_progress() {
+        let mut progress = Progress::new(3, 60, Duration::MAX);
+        progress.next(10);
+        progress.next(20);
+        assert_eq!((progress.records, progress.bases), (1, 10));
+        progress.next(30);
+        progress.finish();
+        assert_eq!((progress.records, progress.bases), (3, 60));
+    }
+}
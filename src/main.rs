@@ -1,198 +1,23 @@
-use std::{fs::File, io::BufReader};
-
-use clap::Parser;
-use eyre::bail;
-use iset::IntervalSet;
-use itertools::Itertools;
-use log::{info, LevelFilter};
-use noodles::{
-    bed,
-    core::Position,
-    fasta::{self},
-};
-use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
-use regex::{self, Regex};
+use log::{debug, info};
 use simple_logger::SimpleLogger;
 
-mod breaks;
-mod cli;
-mod false_dupe;
-mod io;
-mod misjoin;
-mod utils;
-
-use {
-    breaks::{generate_breaks, write_breaks},
-    cli::Cli,
-    false_dupe::generate_false_duplication,
-    io::{get_outfile_writers, get_regions, Fasta},
-    misjoin::generate_deletion,
-    utils::write_misassembly,
+use misasim::{
+    batch::{read_samplesheet, run_samples},
+    cli::{Cli, Commands, LogFormat},
+    compare::{compare, read_annotations, write_stats, OverlapCriteria},
+    config::{Config, InputConfig, OutputConfig, PlacementConfig, SelectionConfig},
+    generate_misassemblies,
+    liftover::{liftover, read_events},
+    logging::JsonLogger,
+    read_rename_map,
 };
 
-fn generate_misassemblies(cli: cli::Cli) -> eyre::Result<()> {
-    let command = cli.command;
-
-    let Some(infile) = cli.infile else {
-        bail!("No input fasta provided.")
-    };
-    let mut reader_fa = Fasta::new(infile)?;
-
-    // https://rust-cli.github.io/book/in-depth/machine-communication.html
-    let reader_bed = cli
-        .inbedfile
-        .as_ref()
-        .map(File::open)
-        .and_then(|f| f.map(BufReader::new).ok())
-        .map(bed::Reader::new);
-    let input_regions = get_regions(reader_bed);
-
-    let (output_fa, mut output_bed) = get_outfile_writers(cli.outfile, cli.outbedfile)?;
-    let mut writer_fa = fasta::Writer::new(output_fa);
-
-    let seed = cli.seed;
-    let randomize_length = cli.randomize_length;
-    if let Some(seed) = seed {
-        log::info!("Random seed: {seed:?}");
-    } else {
-        log::info!("No random seed provided. Generating a random seed per record.");
-    }
-    log::info!("Randomizing length: {randomize_length}");
-
-    let record_groups = reader_fa.lengths();
-
-    let rgx = cli
-        .group_by
-        .as_deref()
-        .map(|rgx| Regex::new(rgx).unwrap())
-        .unwrap_or_else(|| Regex::new(".*?").unwrap());
-
-    // Group names by captured groups.
-    // ex. [chr10_mat, chr10_pat]
-    // * "^.*?_(?<hap>.*?)$" with group by haplotype.
-    // * "^(?<chr>.*?)_.*?$" will group by chromosome.
-    // * ".*?" will not group as all groups are unique.
-    let groups = record_groups
-        .into_iter()
-        // Sort first by name.
-        .sorted_by(|a, b| a.0.cmp(&b.0))
-        .chunk_by(|(rec, _)| {
-            rgx.captures(rec).map(|captures| {
-                captures
-                    .iter()
-                    .enumerate()
-                    .flat_map(|(i, cap)| {
-                        // Skip entire string match.
-                        if i == 0 {
-                            None
-                        } else {
-                            cap.map(|c| c.as_str().to_owned())
-                        }
-                    })
-                    .collect_vec()
-            })
-        });
-
-    let mut rng = seed.map_or(StdRng::from_entropy(), StdRng::seed_from_u64);
-    for (grp, grps) in &groups {
-        if cli.group_by.is_some() {
-            log::info!("Grouping by: {grp:?}")
-        }
-        let grps = grps.collect_vec();
-        // Choose one record per group to generate misassemblies.
-        let Some(misasm_rec) = grps.choose(&mut rng) else {
-            continue;
-        };
-        for rec in grps.iter() {
-            let record_name = &rec.0;
-            let record_length: u32 = rec.1.try_into()?;
-            let record = reader_fa.fetch(record_name, 1, record_length)?;
-
-            // If not chosen misassembled sequence, then just write record as is.
-            if rec != misasm_rec {
-                writer_fa.write_record(&record)?;
-                continue;
-            }
-
-            let record_interval =
-                Position::new(1).unwrap()..Position::new(record_length.try_into()?).unwrap();
-            let def_record_regions = IntervalSet::from_iter(std::iter::once(record_interval));
-            let record_regions = input_regions
-                .as_ref()
-                .and_then(|r| r.get(record_name))
-                .unwrap_or(&def_record_regions);
-
-            info!("Processing record: {:?}.", record_name);
-            info!("With regions: {:?}.", record_regions);
-
-            let seq = std::str::from_utf8(record.sequence().as_ref())?;
-
-            match command {
-                cli::Commands::Misjoin { number, length }
-                | cli::Commands::Gap { number, length } => {
-                    let is_gap = std::mem::discriminant(&command)
-                        == std::mem::discriminant(&cli::Commands::Gap { number, length });
-                    let deleted_seq = generate_deletion(
-                        seq,
-                        record_regions,
-                        length,
-                        number,
-                        // If gap, mask deletion.
-                        is_gap,
-                        seed,
-                        randomize_length,
-                    )?;
-                    info!("{} sequence(s) removed.", deleted_seq.removed_seqs.len());
-
-                    write_misassembly(
-                        deleted_seq.seq.into_bytes(),
-                        deleted_seq.removed_seqs,
-                        record.definition().clone(),
-                        &mut writer_fa,
-                        output_bed.as_mut(),
-                    )?;
-                }
-                cli::Commands::FalseDuplication {
-                    number,
-                    length,
-                    max_duplications,
-                } => {
-                    let false_dupe_seq = generate_false_duplication(
-                        seq,
-                        record_regions,
-                        length,
-                        number,
-                        max_duplications,
-                        seed,
-                        randomize_length,
-                    )?;
-                    info!(
-                        "{} sequence(s) duplicated.",
-                        false_dupe_seq.duplicated_seqs.len()
-                    );
-
-                    write_misassembly(
-                        false_dupe_seq.seq.into_bytes(),
-                        false_dupe_seq.duplicated_seqs,
-                        record.definition().clone(),
-                        &mut writer_fa,
-                        output_bed.as_mut(),
-                    )?;
-                }
-                cli::Commands::Break { number, .. } => {
-                    let seq_breaks = generate_breaks(seq, record_regions, number, seed)?;
-                    write_breaks(record_name, seq_breaks, &mut writer_fa, &mut output_bed)?;
-                }
-            }
-        }
-    }
-
-    Ok(())
-}
-
 fn main() -> eyre::Result<()> {
-    SimpleLogger::new().with_level(LevelFilter::Debug).init()?;
-    let cli = Cli::parse();
+    let cli = Cli::parse_with_preset();
+    match cli.log_format {
+        LogFormat::Text => SimpleLogger::new().with_level(cli.level_filter()).init()?,
+        LogFormat::Json => JsonLogger::init(cli.level_filter())?,
+    }
     // let cli = if std::env::var("DEBUG").map_or(false, |v| v == "1" || v == "true") {
     //     Cli {
     //         command: Commands::Break {
@@ -208,9 +33,157 @@ fn main() -> eyre::Result<()> {
     // } else {
     //     Cli::parse()
     // };
-    info!("Running the following command:\n{:#?}", cli.command);
+    debug!("Running the following command:\n{:#?}", cli.command);
+
+    if let Commands::Compare {
+        truth,
+        calls,
+        slop,
+        min_overlap_bp,
+        reciprocal_overlap,
+        match_type,
+    } = &cli.command
+    {
+        let criteria = OverlapCriteria {
+            slop: *slop,
+            min_overlap_bp: *min_overlap_bp,
+            reciprocal_overlap: *reciprocal_overlap,
+            match_type: *match_type,
+        };
+        let stats = compare(
+            &read_annotations(truth)?,
+            &read_annotations(calls)?,
+            criteria,
+        );
+        write_stats(&stats, std::io::stdout().lock())?;
+        info!("Completed comparing misassemblies.");
+        return Ok(());
+    }
+    // Misassembled records are generated in parallel.
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(cli.threads)
+        .build_global()?;
+    if let Commands::Batch {
+        samplesheet,
+        parallel,
+    } = &cli.command
+    {
+        let samples = read_samplesheet(samplesheet)?;
+        // Check every config before writing anything.
+        let configs = samples
+            .iter()
+            .map(|sample| sample.cli(cli.force, cli.resume).map(config))
+            .collect::<eyre::Result<Vec<_>>>()?;
+        run_samples(&samples, configs, *parallel)?;
+        info!("Completed misassembling samples.");
+        return Ok(());
+    }
+    if let Commands::Liftover {
+        truth,
+        annotations,
+        gaps,
+    } = &cli.command
+    {
+        let events = read_events(truth, *gaps)?;
+        let renames = read_rename_map(cli.rename_map.as_deref(), cli.suffix.as_deref())?;
+        liftover(&events, &renames, annotations, std::io::stdout().lock())?;
+        info!("Completed lifting over annotations.");
+        return Ok(());
+    }
 
-    generate_misassemblies(cli)?;
+    generate_misassemblies(config(cli))?;
     info!("Completed generating misassemblies.");
     Ok(())
 }
+
+/// Configuration of a run from its arguments.
+fn config(cli: Cli) -> Config {
+    Config {
+        command: cli.command,
+        input: InputConfig {
+            infile: cli.infile,
+            mmap: cli.mmap,
+            fai: cli.fai,
+            gzi: cli.gzi,
+            no_index: cli.no_index,
+            inbedfile: cli.inbedfile,
+            weighted_regions: cli.weighted_regions,
+            no_merge_regions: cli.no_merge_regions,
+            exclude_bedfile: cli.exclude_bedfile,
+            bias_bedfile: cli.bias_bedfile,
+            coverage_bedgraph: cli.coverage_bedgraph,
+            replay: cli.replay,
+            at: cli.at,
+        },
+        selection: SelectionConfig {
+            all_records: cli.all_records,
+            per_group: cli.per_group,
+            proportion: cli.proportion,
+            target_records: cli.target_records,
+            weight_by_length: cli.weight_by_length,
+            group_by: cli.group_by,
+            ungrouped_records: cli.ungrouped_records,
+        },
+        placement: PlacementConfig {
+            randomize_length: cli.randomize_length,
+            min_length: cli.min_length,
+            length_frac: cli.length_frac,
+            short_record_policy: cli.short_record_policy,
+            max_total_bases: cli.max_total_bases,
+            strict_regions: cli.strict_regions,
+            strict_count: cli.strict_count,
+            bias_fraction: cli.bias_fraction,
+            repeat_bias_fraction: cli.repeat_bias_fraction,
+            cluster_size: cli.cluster_size,
+            cluster_spread: cli.cluster_spread,
+            coverage_bias: cli.coverage_bias,
+            edge_buffer: cli.edge_buffer,
+            ignore_existing_gaps: cli.ignore_existing_gaps,
+            gap_buffer: cli.gap_buffer,
+        },
+        output: OutputConfig {
+            outfile: cli.outfile,
+            compression_threads: cli.compression_threads,
+            compression_level: cli.compression_level,
+            split_output_dir: cli.split_output_dir,
+            only_misassembled: cli.only_misassembled,
+            keep_original: cli.keep_original,
+            outbedfile: cli.outbedfile,
+            copy_number_bedgraph: cli.copy_number_bedgraph,
+            stats_json: cli.stats_json,
+            suffix: cli.suffix,
+            rename_map: cli.rename_map,
+            force: cli.force,
+            output_format: cli.output_format,
+            line_width: cli.line_width,
+            index_outfile: cli.index_outfile,
+            dry_run: cli.dry_run,
+            soft_mask: cli.soft_mask,
+            annotate_description: cli.annotate_description,
+            truth_format: cli.truth_format,
+            event_ids: cli.event_ids,
+            igv: cli.igv,
+            igv_session: cli.igv_session,
+        },
+        seed: cli.seed,
+        rng: cli.rng,
+        replicates: cli.replicates,
+        resume: cli.resume,
+        threads: cli.threads,
+        progress_interval: cli.progress_interval,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use clap::Parser;
+
+    use super::*;
+
+    #[test]
+    fn test_config_defaults() {
+        let cli = Cli::try_parse_from(["misasim", "misjoin"]).unwrap();
+        let command = cli.command.clone();
+        assert_eq!(config(cli), Config::new(command));
+    }
+}
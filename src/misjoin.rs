@@ -1,6 +1,6 @@
+use std::ops::Range;
+
 use eyre::ContextCompat;
-use iset::IntervalSet;
-use itertools::Itertools;
 use noodles::{
     bed::{
         record::{Builder, OptionalFields},
@@ -9,13 +9,13 @@ use noodles::{
     core::Position,
 };
 
-use crate::utils::generate_random_seq_ranges;
+use crate::utils::SegmentedSequence;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct RemovedSequence<'a> {
     pub start: usize,
     pub end: usize,
-    pub seq: &'a str,
+    pub seq: &'a [u8],
 }
 
 impl<'a> TryFrom<RemovedSequence<'a>> for Builder<3> {
@@ -23,51 +23,50 @@ impl<'a> TryFrom<RemovedSequence<'a>> for Builder<3> {
 
     fn try_from(rem_seq: RemovedSequence) -> Result<Self, eyre::Error> {
         Ok(Record::builder()
-            .set_start_position(Position::new(rem_seq.start).context("Zero start position")?)
+            .set_start_position(Position::new(rem_seq.start + 1).context("Zero start position")?)
             .set_end_position(Position::new(rem_seq.end).context("Zero end position")?)
-            .set_optional_fields(OptionalFields::from(vec![rem_seq.seq.to_owned()])))
+            .set_optional_fields(OptionalFields::from(vec![String::from_utf8_lossy(
+                rem_seq.seq,
+            )
+            .into_owned()])))
     }
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct DeletedSequence<'a> {
-    pub seq: String,
+    pub seq: SegmentedSequence<'a>,
     pub removed_seqs: Vec<RemovedSequence<'a>>,
+    /// Ranges of misassembled bases in the new sequence. Empty for misjoins.
+    pub misassembled_ranges: Vec<Range<usize>>,
 }
 
+/// Delete each sorted, non-overlapping range of `seq`. Deletions are replaced with Ns if `mask_del`.
 pub fn generate_deletion<'a>(
-    seq: &'a str,
-    regions: &IntervalSet<Position>,
-    length: usize,
-    number_dels: usize,
+    seq: &'a [u8],
+    seq_segments: &[Range<usize>],
     mask_del: bool,
-    seed: Option<u64>,
-    randomize_length: bool,
-) -> eyre::Result<DeletedSequence<'a>> {
-    let mut new_seq = String::with_capacity(seq.len());
-    let mut removed_seqs: Vec<RemovedSequence> = Vec::with_capacity(number_dels);
-    let seq_segments = generate_random_seq_ranges(
-        seq.len(),
-        regions,
-        length,
-        number_dels,
-        seed,
-        randomize_length,
-    )?
-    .context("No sequence segments")?
-    .collect_vec();
-
-    let mut seq_iter = seq_segments.into_iter().peekable();
-    // Add starting sequence before first position.
-    if let Some((_, _, del_range)) = seq_iter.peek() {
-        new_seq.push_str(&seq[..del_range.start]);
-    };
-
-    while let Some((_, _, rrange)) = seq_iter.next() {
+    soft_mask: bool,
+) -> DeletedSequence<'a> {
+    let mut new_seq = SegmentedSequence::default();
+    let mut removed_seqs: Vec<RemovedSequence> = Vec::with_capacity(seq_segments.len());
+    let mut misassembled_ranges = Vec::with_capacity(seq_segments.len());
+
+    let mut seq_iter = seq_segments.iter().peekable();
+    // Add starting sequence before first position or the whole sequence if there are none.
+    new_seq.push(
+        &seq[..seq_iter
+            .peek()
+            .map_or(seq.len(), |del_range| del_range.start)],
+    );
+
+    while let Some(rrange) = seq_iter.next() {
         let del_seq = &seq[rrange.clone()];
+        let new_start = new_seq.len();
         if mask_del {
-            new_seq.push_str(&"N".repeat(del_seq.len()));
+            let mask = if soft_mask { b'n' } else { b'N' };
+            new_seq.push(vec![mask; del_seq.len()]);
         }
+        misassembled_ranges.push(new_start..new_seq.len());
 
         removed_seqs.push(RemovedSequence {
             start: rrange.start,
@@ -75,42 +74,65 @@ pub fn generate_deletion<'a>(
             seq: del_seq,
         });
 
-        let remaining_seq = if let Some((_, _, next_rrange)) = seq_iter.peek() {
+        let remaining_seq = if let Some(next_rrange) = seq_iter.peek() {
             &seq[rrange.end..next_rrange.start]
         } else {
             &seq[rrange.end..seq.len()]
         };
 
-        new_seq.push_str(remaining_seq);
+        new_seq.push(remaining_seq);
     }
 
-    Ok(DeletedSequence {
+    DeletedSequence {
         seq: new_seq,
         removed_seqs,
-    })
+        misassembled_ranges,
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::utils::{place_misassemblies, Regions};
 
     #[test]
     fn test_generate_misjoin() {
-        let seq = "AAAGGCCCGGCCCGGGGATTTTATTTTGGGCCGCCCAATTTAATTT";
-        let regions = IntervalSet::from_iter(std::iter::once(
+        let seq = b"AAAGGCCCGGCCCGGGGATTTTATTTTGGGCCGCCCAATTTAATTT";
+        let regions = Regions::from_iter(std::iter::once((
             Position::new(1).unwrap()..Position::new(seq.len()).unwrap(),
-        ));
-        let new_seq = generate_deletion(seq, &regions, 10, 1, false, Some(42), true).unwrap();
+            1.0.into(),
+        )));
+        let new_seq = generate_deletion(
+            seq,
+            &place_misassemblies(
+                seq.len(),
+                &regions,
+                10,
+                1,
+                &mut StdRng::seed_from_u64(42),
+                true,
+                1,
+                None,
+            )
+            .unwrap(),
+            false,
+            false,
+        );
 
         assert_eq!(
             DeletedSequence {
-                seq: "AAAGGCCCGGCCCGGGGATTTTATGGGCCGCCCAATTTAATTT".to_string(),
+                seq: b"AAAGGCCCGGCCCGGGGATTTTATGGGCCGCCCAATTTAATTT"
+                    .as_slice()
+                    .into(),
                 removed_seqs: [RemovedSequence {
                     start: 24,
                     end: 27,
-                    seq: "TTT"
+                    seq: b"TTT"
                 }]
-                .to_vec()
+                .to_vec(),
+                misassembled_ranges: std::iter::once(24..24).collect(),
             },
             new_seq
         );
@@ -118,33 +140,50 @@ mod test {
 
     #[test]
     fn test_generate_misjoin_multiple() {
-        let seq = "AAAGGCCCGGCCCGGGGATTTTATTTTGGGCCGCCCAATTTAATTT";
-        let regions = IntervalSet::from_iter(std::iter::once(
+        let seq = b"AAAGGCCCGGCCCGGGGATTTTATTTTGGGCCGCCCAATTTAATTT";
+        let regions = Regions::from_iter(std::iter::once((
             Position::new(1).unwrap()..Position::new(seq.len()).unwrap(),
-        ));
-        let new_seq = generate_deletion(seq, &regions, 10, 3, false, Some(42), true).unwrap();
+            1.0.into(),
+        )));
+        let new_seq = generate_deletion(
+            seq,
+            &place_misassemblies(
+                seq.len(),
+                &regions,
+                10,
+                3,
+                &mut StdRng::seed_from_u64(42),
+                true,
+                1,
+                None,
+            )
+            .unwrap(),
+            false,
+            false,
+        );
 
         assert_eq!(
             DeletedSequence {
-                seq: "AAAGGCCCGGCCCGGGGGGCCGCCCAATTTAATT".to_string(),
+                seq: b"AAAGGCCCGGCCCGGGGGGCCGCCCAATTTAATT".as_slice().into(),
                 removed_seqs: [
                     RemovedSequence {
                         start: 16,
                         end: 24,
-                        seq: "GATTTTAT"
+                        seq: b"GATTTTAT"
                     },
                     RemovedSequence {
                         start: 24,
                         end: 27,
-                        seq: "TTT"
+                        seq: b"TTT"
                     },
                     RemovedSequence {
                         start: 44,
                         end: 45,
-                        seq: "T"
+                        seq: b"T"
                     }
                 ]
-                .to_vec()
+                .to_vec(),
+                misassembled_ranges: vec![16..16, 16..16, 33..33],
             },
             new_seq
         );
@@ -152,35 +191,92 @@ mod test {
 
     #[test]
     fn test_generate_gap_multiple() {
-        let seq = "AAAGGCCCGGCCCGGGGATTTTATTTTGGGCCGCCCAATTTAATTT";
-        let regions = IntervalSet::from_iter(std::iter::once(
+        let seq = b"AAAGGCCCGGCCCGGGGATTTTATTTTGGGCCGCCCAATTTAATTT";
+        let regions = Regions::from_iter(std::iter::once((
             Position::new(1).unwrap()..Position::new(seq.len()).unwrap(),
-        ));
-        let new_seq = generate_deletion(seq, &regions, 10, 3, true, Some(42), true).unwrap();
+            1.0.into(),
+        )));
+        let new_seq = generate_deletion(
+            seq,
+            &place_misassemblies(
+                seq.len(),
+                &regions,
+                10,
+                3,
+                &mut StdRng::seed_from_u64(42),
+                true,
+                1,
+                None,
+            )
+            .unwrap(),
+            true,
+            false,
+        );
 
         assert_eq!(
             DeletedSequence {
-                seq: "AAAGGCCCGGCCCGGGNNNNNNNNNNNGGGCCGCCCAATTTAATNT".to_string(),
+                seq: b"AAAGGCCCGGCCCGGGNNNNNNNNNNNGGGCCGCCCAATTTAATNT"
+                    .as_slice()
+                    .into(),
                 removed_seqs: [
                     RemovedSequence {
                         start: 16,
                         end: 24,
-                        seq: "GATTTTAT"
+                        seq: b"GATTTTAT"
                     },
                     RemovedSequence {
                         start: 24,
                         end: 27,
-                        seq: "TTT"
+                        seq: b"TTT"
                     },
                     RemovedSequence {
                         start: 44,
                         end: 45,
-                        seq: "T"
+                        seq: b"T"
                     }
                 ]
-                .to_vec()
+                .to_vec(),
+                misassembled_ranges: vec![16..24, 24..27, 44..45],
             },
             new_seq
         )
     }
+
+    #[test]
+    fn test_generate_gap_soft_mask() {
+        let seq = b"AAAGGCCCGGCCCGGGGATTTTATTTTGGGCCGCCCAATTTAATTT";
+        let regions = Regions::from_iter(std::iter::once((
+            Position::new(1).unwrap()..Position::new(seq.len()).unwrap(),
+            1.0.into(),
+        )));
+        let new_seq = generate_deletion(
+            seq,
+            &place_misassemblies(
+                seq.len(),
+                &regions,
+                10,
+                1,
+                &mut StdRng::seed_from_u64(42),
+                true,
+                1,
+                None,
+            )
+            .unwrap(),
+            true,
+            true,
+        );
+
+        assert_eq!(
+            new_seq.seq,
+            SegmentedSequence::from(b"AAAGGCCCGGCCCGGGGATTTTATnnnGGGCCGCCCAATTTAATTT".as_slice())
+        );
+    }
+
+    #[test]
+    fn test_generate_misjoin_none() {
+        let seq = b"AAAGGCCCGGCCCGGGGATTTTATTTTGGGCCGCCCAATTTAATTT";
+        let new_seq = generate_deletion(seq, &[], false, false);
+        assert_eq!(new_seq.seq, SegmentedSequence::from(seq.as_slice()));
+        assert!(new_seq.removed_seqs.is_empty());
+    }
 }
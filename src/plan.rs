@@ -0,0 +1,228 @@
+use std::{collections::HashMap, fs::File, io::Write, path::Path};
+
+use eyre::{bail, Context};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    apply::{apply_variants, Event, Variant},
+    io::Fasta,
+};
+
+/// Bases on each side of a junction in the planned sequence.
+pub const JUNCTION_FLANK: usize = 10;
+
+/// Misassemblies sampled for each record without modifying any sequence.
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Plan {
+    pub records: Vec<PlannedRecord>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedRecord {
+    pub name: String,
+    pub variants: Vec<PlannedVariant>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlannedVariant {
+    /// Unique ID of the event, if any. ex. misasim.3
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(flatten)]
+    pub variant: Variant,
+    /// Original sequence of the variant. Checked before executing the plan.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ref_seq: Option<String>,
+    /// Misassembled sequence of [`JUNCTION_FLANK`] bases on each side of the variant's junctions. Gaps are Ns.
+    /// Checked before executing the plan.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub junctions: Vec<String>,
+}
+
+impl Plan {
+    /// Add events of a record with sequence, `seq`.
+    /// Only the bases of each variant and its junction flanks need to be in `seq`.
+    pub fn add_record(
+        &mut self,
+        name: &str,
+        seq: &[u8],
+        events: impl IntoIterator<Item = Event>,
+    ) -> eyre::Result<()> {
+        let (ids, variants): (Vec<_>, Vec<_>) = events
+            .into_iter()
+            .map(|Event { id, variant }| (id, variant))
+            .unzip();
+        let junctions = junctions(seq, &variants)?;
+        let variants = ids
+            .into_iter()
+            .zip(variants)
+            .zip(junctions)
+            .map(|((id, variant), junctions)| {
+                let range = variant.range();
+                PlannedVariant {
+                    id,
+                    ref_seq: (!range.is_empty())
+                        .then(|| String::from_utf8_lossy(&seq[range]).into_owned()),
+                    variant,
+                    junctions,
+                }
+            })
+            .collect();
+        self.records.push(PlannedRecord {
+            name: name.to_owned(),
+            variants,
+        });
+        Ok(())
+    }
+
+    /// Read a plan written by [`Plan::write`].
+    pub fn read(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("Cannot open plan: {path:?}"))?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    /// Write the plan as pretty-printed JSON.
+    pub fn write(&self, writer: impl Write) -> eyre::Result<()> {
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+
+    /// Check that the original sequences and junctions of variants match the input sequence file.
+    pub fn check(&self, reader_fa: &mut Fasta) -> eyre::Result<()> {
+        let lengths: HashMap<String, u64> = reader_fa.lengths().into_iter().collect();
+        for record in self.records.iter() {
+            let Some(length) = lengths.get(&record.name) else {
+                bail!("Planned record not found: {}", record.name)
+            };
+            let seq = reader_fa.fetch(&record.name, 1, (*length).try_into()?)?;
+            for planned in record.variants.iter() {
+                let Some(ref_seq) = planned.ref_seq.as_ref() else {
+                    continue;
+                };
+                if seq.sequence().as_ref().get(planned.variant.range()) != Some(ref_seq.as_bytes())
+                {
+                    bail!(
+                        "Planned variant doesn't match the sequence of {}: {:?}",
+                        record.name,
+                        planned.variant
+                    )
+                }
+            }
+            let variants = record
+                .variants
+                .iter()
+                .map(|planned| planned.variant.clone())
+                .collect_vec();
+            let junctions = junctions(seq.sequence().as_ref(), &variants)?;
+            for (planned, junctions) in record.variants.iter().zip(junctions) {
+                if !planned.junctions.is_empty() && planned.junctions != junctions {
+                    bail!(
+                        "Planned junctions don't match the sequence of {}: {:?}",
+                        record.name,
+                        planned.variant
+                    )
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Variants by record name.
+    pub fn into_variants(self) -> HashMap<String, Vec<Variant>> {
+        self.records
+            .into_iter()
+            .map(|record| {
+                (
+                    record.name,
+                    record.variants.into_iter().map(|v| v.variant).collect(),
+                )
+            })
+            .collect()
+    }
+}
+
+/// Junctions of each variant applied to `seq` with [`JUNCTION_FLANK`] bases on each side.
+/// Variants replacing bases have a junction at each end. Breaks split records so have none.
+fn junctions(seq: &[u8], variants: &[Variant]) -> eyre::Result<Vec<Vec<String>>> {
+    let mut junctions = vec![vec![]; variants.len()];
+    // Variants are applied in order of their position.
+    let joined = (0..variants.len())
+        .filter(|i| !matches!(variants[*i], Variant::Break { .. }))
+        .sorted_by_key(|i| {
+            let range = variants[*i].range();
+            (range.start, range.end)
+        })
+        .collect_vec();
+    if joined.is_empty() {
+        return Ok(junctions);
+    }
+    let joined_variants = joined.iter().map(|i| variants[*i].clone()).collect_vec();
+    let applied_seq = apply_variants(seq, &joined_variants, false)?;
+    for (i, (_, range)) in joined
+        .into_iter()
+        .zip(applied_seq.misassembled_ranges.iter())
+    {
+        junctions[i] = [range.start, range.end]
+            .into_iter()
+            .dedup()
+            .map(|pos| {
+                let flanks = applied_seq
+                    .seq
+                    .slice(pos.saturating_sub(JUNCTION_FLANK)..pos + JUNCTION_FLANK);
+                String::from_utf8_lossy(&flanks).into_owned()
+            })
+            .collect();
+    }
+    Ok(junctions)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_plan_round_trip() {
+        let mut plan = Plan::default();
+        plan.add_record(
+            "chr1",
+            b"AAAAGGGGCCCCTTTT",
+            [
+                Variant::Deletion(2..4),
+                Variant::Duplication {
+                    range: 8..12,
+                    count: 3,
+                },
+                Variant::Break { pos: 14 },
+            ]
+            .into_iter()
+            .enumerate()
+            .map(|(i, variant)| Event {
+                id: Some(format!("misasim.{}", i + 1)),
+                variant,
+            }),
+        )
+        .unwrap();
+        let junctions = plan.records[0]
+            .variants
+            .iter()
+            .map(|planned| planned.junctions.clone())
+            .collect_vec();
+        assert_eq!(
+            junctions,
+            [
+                vec!["AAGGGGCCCCCC".to_owned()],
+                vec!["AAGGGGCCCCCCCCCC".to_owned(), "CCCCCCCCCCTTTT".to_owned()],
+                vec![],
+            ]
+        );
+        let mut buf = vec![];
+        plan.write(&mut buf).unwrap();
+        let json = String::from_utf8(buf).unwrap();
+        assert!(json.contains(r#""type": "duplication""#));
+        assert!(json.contains(r#""ref_seq": "CCCC""#));
+        assert!(json.contains(r#""id": "misasim.2""#));
+        assert_eq!(serde_json::from_str::<Plan>(&json).unwrap(), plan);
+    }
+}
@@ -0,0 +1,219 @@
+use std::io::Write;
+
+use clap::ValueEnum;
+use eyre::bail;
+use rand::{seq::SliceRandom, Rng};
+use rand_distr::{Distribution, LogNormal};
+
+use crate::{
+    io::Fasta,
+    rng::{RngKind, SimRng},
+    utils::{record_seed, reverse_complement},
+};
+
+const BASES: &[u8; 4] = b"ACGT";
+/// Highest quality written to fastq.
+const MAX_QUALITY: u8 = 93;
+
+/// Sequencing platform of simulated reads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReadPlatform {
+    /// PacBio HiFi. ~15 kbp reads with few errors.
+    Hifi,
+    /// Oxford Nanopore. Longer reads with a wider length distribution and more errors.
+    Ont,
+}
+
+impl ReadPlatform {
+    pub fn mean_length(&self) -> usize {
+        match self {
+            ReadPlatform::Hifi => 15_000,
+            ReadPlatform::Ont => 20_000,
+        }
+    }
+
+    pub fn length_sd(&self) -> usize {
+        match self {
+            ReadPlatform::Hifi => 3_000,
+            ReadPlatform::Ont => 15_000,
+        }
+    }
+
+    pub fn error_rate(&self) -> f64 {
+        match self {
+            ReadPlatform::Hifi => 0.001,
+            ReadPlatform::Ont => 0.05,
+        }
+    }
+}
+
+/// Options of [`simulate_reads`].
+#[derive(Debug, Clone, Copy)]
+pub struct ReadConfig {
+    /// Mean depth of coverage of each record.
+    pub coverage: f64,
+    pub mean_length: usize,
+    pub length_sd: usize,
+    /// Fraction of bases with an error, split equally between substitutions, insertions, and deletions.
+    pub error_rate: f64,
+}
+
+impl ReadConfig {
+    /// Default read length and error model of a platform.
+    pub fn new(platform: ReadPlatform, coverage: f64) -> Self {
+        Self {
+            coverage,
+            mean_length: platform.mean_length(),
+            length_sd: platform.length_sd(),
+            error_rate: platform.error_rate(),
+        }
+    }
+
+    /// Log-normal read length distribution with the mean and standard deviation of the config.
+    fn length_distribution(&self) -> eyre::Result<LogNormal<f64>> {
+        if self.mean_length == 0 {
+            bail!("Mean read length must be at least 1.")
+        }
+        let (mean, sd) = (self.mean_length as f64, self.length_sd as f64);
+        let sigma_sq = (1.0 + (sd * sd) / (mean * mean)).ln();
+        Ok(LogNormal::new(mean.ln() - sigma_sq / 2.0, sigma_sq.sqrt())?)
+    }
+
+    /// Phred quality of every base.
+    fn quality(&self) -> u8 {
+        if self.error_rate <= 0.0 {
+            return MAX_QUALITY;
+        }
+        (-10.0 * self.error_rate.log10())
+            .round()
+            .clamp(0.0, MAX_QUALITY as f64) as u8
+    }
+}
+
+/// Add substitutions, insertions, and deletions to a read at an error rate.
+pub fn add_errors(seq: &[u8], error_rate: f64, rng: &mut impl Rng) -> Vec<u8> {
+    let mut new_seq = Vec::with_capacity(seq.len());
+    for base in seq.iter().copied() {
+        if !rng.gen_bool(error_rate) {
+            new_seq.push(base);
+            continue;
+        }
+        match rng.gen_range(0..3) {
+            0 => new_seq.push(
+                **BASES
+                    .iter()
+                    .filter(|b| **b != base.to_ascii_uppercase())
+                    .collect::<Vec<_>>()
+                    .choose(rng)
+                    .unwrap(),
+            ),
+            1 => new_seq.extend([base, *BASES.choose(rng).unwrap()]),
+            _ => {}
+        }
+    }
+    new_seq
+}
+
+/// Sample reads from each record of `reader_fa` until it has the mean coverage and write them as fastq.
+/// Reads are named `{record}_read{i}` with their original position and strand in the description.
+/// Each record is seeded by its name from `seed`. Returns the number of reads.
+pub fn simulate_reads(
+    reader_fa: &mut Fasta,
+    config: ReadConfig,
+    seed: u64,
+    rng_kind: RngKind,
+    mut writer: impl Write,
+) -> eyre::Result<usize> {
+    if !(0.0..=1.0).contains(&config.error_rate) {
+        bail!("Error rate must be between 0 and 1.")
+    }
+    let lengths = config.length_distribution()?;
+    let quality = char::from(config.quality() + b'!');
+    let mut num_reads = 0;
+    for (name, length) in reader_fa.lengths() {
+        let length: usize = length.try_into()?;
+        if length == 0 {
+            continue;
+        }
+        let record = reader_fa.fetch(&name, 1, length)?;
+        let seq = record.sequence().as_ref();
+        let mut rng = SimRng::new(rng_kind, record_seed(seed, &name));
+        let target_bases = (config.coverage * length as f64) as usize;
+        let (mut num_bases, mut i) = (0, 0);
+        while num_bases < target_bases {
+            let read_length = (lengths.sample(&mut rng) as usize).clamp(1, length);
+            let start = rng.gen_range(0..=length - read_length);
+            let end = start + read_length;
+            let is_reverse = rng.gen_bool(0.5);
+            let read = if is_reverse {
+                reverse_complement(&seq[start..end])
+            } else {
+                seq[start..end].to_vec()
+            };
+            let read = add_errors(&read, config.error_rate, &mut rng);
+            i += 1;
+            writeln!(
+                writer,
+                "@{name}_read{i} pos={name}:{}-{end} strand={}",
+                start + 1,
+                if is_reverse { '-' } else { '+' }
+            )?;
+            writer.write_all(&read)?;
+            writeln!(writer, "\n+\n{}", quality.to_string().repeat(read.len()))?;
+            num_bases += read_length;
+        }
+        log::debug!("Simulated {i} read(s) from {name:?}.");
+        num_reads += i;
+    }
+    writer.flush()?;
+    Ok(num_reads)
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn test_simulate_reads() {
+        let mut reader =
+            Fasta::from_bytes(b">chr1\nAAAAAAAAAACCCCCCCCCC\n>chr2\nGGGGGGGGGG\n".to_vec())
+                .unwrap();
+        let config = ReadConfig {
+            coverage: 5.0,
+            mean_length: 5,
+            length_sd: 1,
+            error_rate: 0.0,
+        };
+        let mut fastq = vec![];
+        let num_reads = simulate_reads(&mut reader, config, 42, RngKind::Std, &mut fastq).unwrap();
+        let fastq = String::from_utf8(fastq).unwrap();
+        let lines = fastq.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), num_reads * 4);
+        assert!(lines[0].starts_with("@chr1_read1 pos=chr1:"));
+        // No errors so every read matches the record it's from.
+        for read in lines.chunks(4) {
+            let seq = read[1];
+            let record_seq = if read[0].contains("chr1") {
+                "AAAAAAAAAACCCCCCCCCC"
+            } else {
+                "GGGGGGGGGG"
+            };
+            let rc = String::from_utf8(reverse_complement(seq.as_bytes())).unwrap();
+            assert!(record_seq.contains(seq) || record_seq.contains(&rc));
+            assert_eq!(read[3], "~".repeat(seq.len()));
+        }
+    }
+
+    #[test]
+    fn test_add_errors() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let seq = b"ACGT".repeat(1000);
+        assert_eq!(add_errors(&seq, 0.0, &mut rng), seq);
+        let read = add_errors(&seq, 0.1, &mut rng);
+        assert_ne!(read, seq);
+        assert!((3800..4200).contains(&read.len()));
+        assert_eq!(ReadConfig::new(ReadPlatform::Hifi, 30.0).quality(), 30);
+    }
+}
@@ -0,0 +1,131 @@
+use std::ops::{Range, RangeInclusive};
+
+/// Telomeric repeat motif and its reverse complement.
+const TELOMERE_MOTIFS: [&[u8]; 2] = [b"TTAGGG", b"CCCTAA"];
+/// Length of windows scanned for telomeric repeats.
+const TELOMERE_WINDOW: usize = 500;
+/// Minimum fraction of a window covered by telomeric motifs.
+const MIN_TELOMERE_FRACTION: f64 = 0.5;
+
+/// Alpha-satellite monomer lengths compared. ~171 bp with some tolerance for indels.
+const SATELLITE_PERIODS: RangeInclusive<usize> = 169..=173;
+/// Length of windows scanned for alpha-satellite-like repeats.
+const SATELLITE_WINDOW: usize = 2000;
+/// Minimum identity between a window and itself shifted by one monomer.
+/// Unrelated sequence has ~25% identity.
+const MIN_SATELLITE_IDENTITY: f64 = 0.6;
+
+/// Find telomeric repeats, runs of (TTAGGG)n or (CCCTAA)n, as sorted, 0-based ranges of whole windows.
+pub fn find_telomeres(seq: &[u8]) -> Vec<Range<usize>> {
+    let motif_len = TELOMERE_MOTIFS[0].len();
+    let mut is_motif = vec![false; seq.len()];
+    for (i, kmer) in seq.windows(motif_len).enumerate() {
+        if TELOMERE_MOTIFS
+            .iter()
+            .any(|motif| kmer.eq_ignore_ascii_case(motif))
+        {
+            is_motif[i..i + motif_len].fill(true);
+        }
+    }
+    find_windows(seq.len(), TELOMERE_WINDOW, |window| {
+        let num_motif_bases = is_motif[window.clone()].iter().filter(|b| **b).count();
+        num_motif_bases as f64 / window.len() as f64 >= MIN_TELOMERE_FRACTION
+    })
+}
+
+/// Find alpha-satellite-like repeats, tandem repeats of ~171 bp monomers, as sorted, 0-based ranges of whole windows.
+pub fn find_satellites(seq: &[u8]) -> Vec<Range<usize>> {
+    find_windows(seq.len(), SATELLITE_WINDOW, |window| {
+        SATELLITE_PERIODS.clone().any(|period| {
+            let pairs = window
+                .clone()
+                .filter(|i| i + period < seq.len())
+                .map(|i| (seq[i], seq[i + period]))
+                .filter(|(a, b)| !a.eq_ignore_ascii_case(&b'N') && !b.eq_ignore_ascii_case(&b'N'));
+            let (mut num_identical, mut num_compared) = (0usize, 0usize);
+            for (a, b) in pairs {
+                num_compared += 1;
+                num_identical += usize::from(a.eq_ignore_ascii_case(&b));
+            }
+            // Require at least a full monomer to compare.
+            num_compared > *SATELLITE_PERIODS.end()
+                && num_identical as f64 / num_compared as f64 >= MIN_SATELLITE_IDENTITY
+        })
+    })
+}
+
+/// Find telomeric and alpha-satellite-like repeats as sorted, merged, 0-based ranges.
+pub fn find_repeats(seq: &[u8]) -> Vec<Range<usize>> {
+    let mut repeats = find_telomeres(seq);
+    repeats.extend(find_satellites(seq));
+    repeats.sort_by_key(|repeat| repeat.start);
+    let mut merged: Vec<Range<usize>> = vec![];
+    for repeat in repeats {
+        match merged.last_mut() {
+            Some(last) if repeat.start <= last.end => last.end = last.end.max(repeat.end),
+            _ => merged.push(repeat),
+        }
+    }
+    merged
+}
+
+/// Merge adjacent windows of a sequence passing a check.
+fn find_windows(
+    length: usize,
+    window_size: usize,
+    is_repeat: impl Fn(&Range<usize>) -> bool,
+) -> Vec<Range<usize>> {
+    let mut repeats: Vec<Range<usize>> = vec![];
+    for start in (0..length).step_by(window_size) {
+        let window = start..(start + window_size).min(length);
+        if !is_repeat(&window) {
+            continue;
+        }
+        match repeats.last_mut() {
+            Some(repeat) if repeat.end == window.start => repeat.end = window.end,
+            _ => repeats.push(window),
+        }
+    }
+    repeats
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+    use super::*;
+
+    fn random_seq(length: usize, rng: &mut StdRng) -> Vec<u8> {
+        (0..length).map(|_| *b"ACGT".choose(rng).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_find_repeats() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let monomer = random_seq(171, &mut rng);
+        // 5 kbp of telomere, 5 kbp of unique sequence, and ~10 kbp of satellite with a mismatch every 10 bp.
+        let mut seq = b"ttaggg".repeat(5000 / 6 + 1)[..5000].to_vec();
+        seq.extend(random_seq(5000, &mut rng));
+        let satellite_start = seq.len();
+        for i in 0..59 {
+            let mut copy = monomer.clone();
+            copy[i % 10] = b'N';
+            for base in copy.iter_mut().skip(i % 10 + 10).step_by(10) {
+                *base = *b"ACGT".choose(&mut rng).unwrap();
+            }
+            seq.extend(copy);
+        }
+        let satellite_end = seq.len();
+        seq.extend(random_seq(5000, &mut rng));
+
+        assert_eq!(find_telomeres(&seq), vec![0..5000]);
+        let satellites = find_satellites(&seq);
+        assert_eq!(satellites.len(), 1);
+        let satellite = &satellites[0];
+        assert!(satellite.start <= satellite_start + SATELLITE_WINDOW);
+        assert!(satellite.end >= satellite_end - SATELLITE_WINDOW);
+        assert!(satellite.end <= satellite_end + SATELLITE_WINDOW);
+        assert_eq!(find_repeats(&seq), [0..5000, satellite.clone()]);
+        assert!(find_repeats(&random_seq(10_000, &mut rng)).is_empty());
+    }
+}
@@ -0,0 +1,177 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use eyre::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+/// Suffix of the file marking a completed run. ex. out.fa.misasim.json
+pub const COMPLETION_SUFFIX: &str = ".misasim.json";
+
+/// Record of a completed run written next to its outputs with "--resume".
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Completion {
+    pub version: String,
+    /// Seed of the run, given or generated.
+    pub seed: u64,
+    /// Digest of the settings of the run that change its outputs.
+    pub config_digest: u64,
+    pub outputs: Vec<PathBuf>,
+}
+
+impl Completion {
+    pub fn new(config: &Config, seed: u64) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            seed,
+            config_digest: config_digest(config),
+            outputs: outputs(config),
+        }
+    }
+
+    /// Path of the completion file of a run, {output}.misasim.json, from its first output.
+    /// `None` if the run has no output files.
+    pub fn path(config: &Config) -> Option<PathBuf> {
+        let mut path = outputs(config).into_iter().next()?.into_os_string();
+        path.push(COMPLETION_SUFFIX);
+        Some(path.into())
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("Cannot open {path:?}"))?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> eyre::Result<()> {
+        let path = path.as_ref();
+        let file = File::create(path).with_context(|| format!("Cannot create {path:?}"))?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    /// Check if a run completed. Its completion file must list the same outputs, settings, and seed, if given,
+    /// and every output must exist.
+    pub fn is_complete(config: &Config) -> bool {
+        let Some(path) = Completion::path(config) else {
+            return false;
+        };
+        Completion::read(path).is_ok_and(|completion| {
+            completion.outputs == outputs(config)
+                && completion.config_digest == config_digest(config)
+                // Runs without a seed keep the seed of the completed run.
+                && config.seed.is_none_or(|seed| seed == completion.seed)
+                && completion.outputs.iter().all(|output| output.exists())
+        })
+    }
+}
+
+/// Digest of the settings of a run that change its outputs.
+/// The seed is compared separately as runs without one generate it.
+fn config_digest(config: &Config) -> u64 {
+    let mut config = config.clone();
+    config.seed = None;
+    config.replicates = 1;
+    config.resume = false;
+    config.threads = 1;
+    config.progress_interval = None;
+    config.output.force = false;
+    config.output.compression_threads = 1;
+    // FNV-1a as the hashers of std aren't stable across releases.
+    format!("{config:?}")
+        .bytes()
+        .fold(0xcbf29ce484222325, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+        })
+}
+
+/// Output files and directories of a run.
+fn outputs(config: &Config) -> Vec<PathBuf> {
+    let output = &config.output;
+    [
+        output.outfile.as_ref().filter(|_| !output.dry_run),
+        output.split_output_dir.as_ref().filter(|_| !output.dry_run),
+        output.outbedfile.as_ref(),
+        output.copy_number_bedgraph.as_ref(),
+        output.stats_json.as_ref(),
+        output.igv_session.as_ref(),
+    ]
+    .into_iter()
+    .flatten()
+    .cloned()
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        commands::{Commands, MisassemblyCommands},
+        config::OutputConfig,
+        test_utils::TestDir,
+    };
+
+    #[test]
+    fn test_completion() {
+        let dir = TestDir::new("completion");
+        let outfile = dir.join("out.fa");
+        let outbedfile = dir.join("out.bed");
+        let config = Config {
+            output: OutputConfig {
+                outfile: Some(outfile.clone()),
+                outbedfile: Some(outbedfile.clone()),
+                ..Default::default()
+            },
+            seed: Some(42),
+            ..Config::new(Commands::Misassembly(MisassemblyCommands::Misjoin {
+                number: 1,
+                length: 5000,
+            }))
+        };
+        let path = Completion::path(&config).unwrap();
+        assert_eq!(path, dir.join("out.fa.misasim.json"));
+        assert!(!Completion::is_complete(&config));
+
+        std::fs::write(&outfile, ">chr1\nACGT\n").unwrap();
+        Completion::new(&config, 42).write(&path).unwrap();
+        // Missing bed file.
+        assert!(!Completion::is_complete(&config));
+        std::fs::write(&outbedfile, "").unwrap();
+        assert!(Completion::is_complete(&config));
+        assert_eq!(Completion::read(&path).unwrap().seed, 42);
+
+        // Settings that don't change the outputs and runs without a seed.
+        for config in [
+            Config {
+                threads: 4,
+                resume: true,
+                ..config.clone()
+            },
+            Config {
+                seed: None,
+                ..config.clone()
+            },
+        ] {
+            assert!(Completion::is_complete(&config));
+        }
+        // Other seeds and settings.
+        for config in [
+            Config {
+                seed: Some(7),
+                ..config.clone()
+            },
+            Config {
+                command: Commands::Misassembly(MisassemblyCommands::Misjoin {
+                    number: 2,
+                    length: 5000,
+                }),
+                ..config.clone()
+            },
+        ] {
+            assert!(!Completion::is_complete(&config));
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::Write,
+};
+
+use eyre::{bail, ContextCompat};
+use itertools::Itertools;
+use noodles::fasta::{
+    self,
+    record::{Definition, Sequence},
+};
+
+use crate::{
+    io::{Fasta, FastaWriter, RenameMap},
+    liftover::{build_segments, Event, Segment},
+    utils::reverse_complement,
+};
+
+/// Prefix of notes added to descriptions with "--annotate-description".
+const NOTE_PREFIX: &str = "misasim:";
+
+/// Revert misassembled records to their original sequence.
+/// Misassembled records are found by their output name from `renames`.
+/// Broken records are fused and records without misassemblies are written as is.
+pub fn revert<O: Write>(
+    reader_fa: &mut Fasta,
+    events: &HashMap<String, Vec<Event>>,
+    renames: &RenameMap,
+    writer_fa: &mut FastaWriter<O>,
+) -> eyre::Result<()> {
+    let segments: HashMap<&str, Vec<Segment>> = events
+        .iter()
+        .map(|(name, record_events)| {
+            (
+                name.as_str(),
+                build_segments(&renames.output_name(name), record_events),
+            )
+        })
+        .collect();
+    // Output record names and the original record they're from.
+    let original_names: HashMap<&str, &str> = segments
+        .iter()
+        .flat_map(|(name, segs)| segs.iter().map(|seg| (seg.new_name.as_str(), *name)))
+        .collect();
+
+    let mut reverted = HashSet::new();
+    for (name, length) in reader_fa.lengths() {
+        let Some(original_name) = original_names.get(name.as_str()) else {
+            reader_fa.copy_record(&name, length.try_into()?, writer_fa)?;
+            continue;
+        };
+        // Only write an original record once. ex. Broken records
+        if !reverted.insert(*original_name) {
+            continue;
+        }
+        let record = revert_record(
+            reader_fa,
+            original_name,
+            &segments[original_name],
+            &events[*original_name],
+        )?;
+        writer_fa.write_record(&record)?;
+    }
+    Ok(())
+}
+
+fn revert_record(
+    reader_fa: &mut Fasta,
+    name: &str,
+    segments: &[Segment],
+    events: &[Event],
+) -> eyre::Result<fasta::Record> {
+    let deletions: HashMap<usize, (usize, &str, bool)> = events
+        .iter()
+        .filter_map(|event| match event {
+            Event::Deletion {
+                start,
+                end,
+                gap,
+                seq,
+            } => Some((*start, (*end, seq.as_str(), *gap))),
+            _ => None,
+        })
+        .collect();
+
+    let mut records: HashMap<&str, fasta::Record> = HashMap::new();
+    let mut seq: Vec<u8> = vec![];
+    for seg in segments {
+        // Add removed sequence back.
+        if seg.start > seq.len() {
+            let Some((_, del_seq, _)) = deletions.get(&seq.len()) else {
+                bail!("No deleted sequence at {name}:{}", seq.len())
+            };
+            seq.extend(del_seq.bytes());
+        }
+        if !records.contains_key(seg.new_name.as_str()) {
+            let length = reader_fa
+                .lengths()
+                .into_iter()
+                .find_map(|(rec_name, length)| (rec_name == seg.new_name).then_some(length))
+                .with_context(|| format!("Misassembled record not found: {}", seg.new_name))?;
+            let record = reader_fa.fetch(&seg.new_name, 1, length.try_into()?)?;
+            records.insert(&seg.new_name, record);
+        }
+        let record = &records[seg.new_name.as_str()];
+        let new_seq = record.sequence().as_ref();
+        let seg_len = if seg.end == usize::MAX {
+            new_seq.len().saturating_sub(seg.new_start)
+        } else {
+            seg.end - seg.start
+        };
+        let segment = new_seq
+            .get(seg.new_start..seg.new_start + seg_len)
+            .with_context(|| format!("Segment out of bounds of {}: {seg:?}", seg.new_name))?;
+        if seg.reverse {
+            seq.extend(reverse_complement(segment));
+        } else {
+            seq.extend(segment);
+        }
+    }
+    // Replace gaps with their original sequence.
+    for (start, (end, del_seq, _)) in deletions.iter().filter(|(_, (_, _, gap))| *gap) {
+        let Some(gap) = seq.get_mut(*start..*end) else {
+            bail!("Gap out of bounds: {name}:{start}-{end}")
+        };
+        gap.copy_from_slice(del_seq.as_bytes());
+    }
+
+    // Remove notes added by misasim.
+    let description = segments
+        .first()
+        .and_then(|seg| records.get(seg.new_name.as_str()))
+        .and_then(|record| record.description())
+        .map(|desc| {
+            String::from_utf8_lossy(desc)
+                .split(' ')
+                .filter(|word| !word.starts_with(NOTE_PREFIX))
+                .join(" ")
+        })
+        .filter(|desc| !desc.is_empty());
+
+    Ok(fasta::Record::new(
+        Definition::new(name, description.map(String::into_bytes)),
+        Sequence::from(seq),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::apply::{apply_variants, Variant};
+
+    #[test]
+    fn test_revert() {
+        let seq = b"AAAAGGGGCCCCTTTTACGTACGT";
+        let variants = [
+            Variant::Deletion(2..6),
+            Variant::Gap(8..10),
+            Variant::Duplication {
+                range: 12..16,
+                count: 3,
+            },
+            Variant::Inversion(18..22),
+        ];
+        let applied = apply_variants(seq, &variants, false).unwrap();
+        let events = HashMap::from([(
+            "chr1".to_owned(),
+            vec![
+                Event::Deletion {
+                    start: 2,
+                    end: 6,
+                    gap: false,
+                    seq: String::from_utf8_lossy(&seq[2..6]).into_owned(),
+                },
+                Event::Deletion {
+                    start: 8,
+                    end: 10,
+                    gap: true,
+                    seq: String::from_utf8_lossy(&seq[8..10]).into_owned(),
+                },
+                Event::Duplication {
+                    start: 12,
+                    end: 16,
+                    count: 3,
+                },
+                Event::Inversion { start: 18, end: 22 },
+            ],
+        )]);
+
+        let mut reader_fa = Fasta::from_bytes(
+            [
+                b">chr2\nACGT\n>chr1 desc misasim:apply=4\n".as_slice(),
+                &applied.seq.segments().concat(),
+                b"\n",
+            ]
+            .concat(),
+        )
+        .unwrap();
+        let record_events = &events["chr1"];
+        let segments = build_segments("chr1", record_events);
+        let record = revert_record(&mut reader_fa, "chr1", &segments, record_events).unwrap();
+        assert_eq!(record.sequence().as_ref(), seq);
+        assert_eq!(record.description(), Some(b"desc".as_slice()));
+    }
+}
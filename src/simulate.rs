@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+
+use itertools::Itertools;
+use noodles::{
+    core::Position,
+    fasta::{
+        self,
+        record::{Definition, Sequence},
+    },
+};
+use rand::RngCore;
+
+use crate::{
+    apply::{apply_variants, Variant},
+    breaks::split_at_breaks,
+    generator::MisassemblyGenerator,
+    io::Fasta,
+    utils::Regions,
+};
+
+/// Record misassembled by [`simulate_records`].
+#[derive(Debug)]
+pub struct SimulatedRecord {
+    /// Name of the original record.
+    pub name: String,
+    /// Output records. Broken records are split into records named `{name}:{start}-{end}`.
+    pub records: Vec<fasta::Record>,
+    /// Misassemblies in original coordinates.
+    pub variants: Vec<Variant>,
+}
+
+/// Options of [`simulate_records`].
+pub struct SimulationConfig<'a> {
+    pub generator: &'a dyn MisassemblyGenerator,
+    /// Regions to misassemble by record name. Records without regions are unchanged.
+    /// Whole records are misassembled if `None`.
+    pub regions: Option<&'a HashMap<String, Regions>>,
+    /// Soft-mask (lowercase) misassembled sequence.
+    pub soft_mask: bool,
+}
+
+/// Misassemble each record of `reader` in order, fetching one record at a time.
+pub fn simulate_records<'a>(
+    reader: &'a mut Fasta,
+    config: SimulationConfig<'a>,
+    rng: &'a mut dyn RngCore,
+) -> impl Iterator<Item = eyre::Result<SimulatedRecord>> + 'a {
+    reader.lengths().into_iter().map(move |(name, length)| {
+        let length: usize = length.try_into()?;
+        let record = reader.fetch(&name, 1, length)?;
+        let regions = match config.regions.map(|regions| regions.get(&name)) {
+            Some(Some(regions)) => regions.clone(),
+            Some(None) => {
+                return Ok(SimulatedRecord {
+                    name,
+                    records: vec![record],
+                    variants: vec![],
+                })
+            }
+            None => Regions::from_iter(std::iter::once((
+                Position::MIN..Position::try_from(length)?,
+                1.0.into(),
+            ))),
+        };
+        let variants = config.generator.generate(length, &regions, rng)?;
+        let records = misassemble_record(&name, &record, &variants, config.soft_mask)?;
+        Ok(SimulatedRecord {
+            name,
+            records,
+            variants,
+        })
+    })
+}
+
+/// Apply variants to a record. Records with only breaks are split instead.
+fn misassemble_record(
+    name: &str,
+    record: &fasta::Record,
+    variants: &[Variant],
+    soft_mask: bool,
+) -> eyre::Result<Vec<fasta::Record>> {
+    let seq = record.sequence().as_ref();
+    let description = record.description().map(|desc| desc.to_vec());
+    if !variants.is_empty() && variants.iter().all(|v| matches!(v, Variant::Break { .. })) {
+        let positions = variants.iter().map(|v| v.range().start).sorted().dedup();
+        let (seqs, breaks) = split_at_breaks(seq, positions);
+        return Ok(seqs
+            .into_iter()
+            .zip(breaks)
+            .map(|(seq, brk)| {
+                fasta::Record::new(
+                    Definition::new(
+                        format!("{name}:{}-{}", brk.start, brk.end),
+                        description.clone(),
+                    ),
+                    Sequence::from(seq.to_vec()),
+                )
+            })
+            .collect());
+    }
+    let applied = apply_variants(seq, variants, soft_mask)?;
+    Ok(vec![fasta::Record::new(
+        record.definition().clone(),
+        Sequence::from(applied.seq.segments().concat()),
+    )])
+}
+
+#[cfg(test)]
+mod test {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{commands::MisassemblyCommands, generator::BuiltinGenerator};
+
+    fn generator(command: MisassemblyCommands) -> BuiltinGenerator {
+        BuiltinGenerator {
+            command,
+            randomize_length: false,
+            min_length: 1,
+            max_total_bases: None,
+        }
+    }
+
+    #[test]
+    fn test_simulate_records() {
+        let mut reader = Fasta::from_bytes(
+            b">chr1 hap1\nAAAAAAAAAACCCCCCCCCC\n>chr2\nGGGGGGGGGGTTTTTTTTTT\n".to_vec(),
+        )
+        .unwrap();
+        let misjoin = generator(MisassemblyCommands::Misjoin {
+            number: 2,
+            length: 3,
+        });
+        // Only misassemble chr1.
+        let regions = HashMap::from([(
+            "chr1".to_owned(),
+            Regions::from_iter(std::iter::once((
+                Position::MIN..Position::new(20).unwrap(),
+                1.0.into(),
+            ))),
+        )]);
+        let mut rng = StdRng::seed_from_u64(42);
+        let simulated: Vec<SimulatedRecord> = simulate_records(
+            &mut reader,
+            SimulationConfig {
+                generator: &misjoin,
+                regions: Some(&regions),
+                soft_mask: false,
+            },
+            &mut rng,
+        )
+        .try_collect()
+        .unwrap();
+
+        assert_eq!(simulated[0].name, "chr1");
+        assert_eq!(simulated[0].variants.len(), 2);
+        assert_eq!(simulated[0].records[0].sequence().len(), 14);
+        assert_eq!(simulated[0].records[0].description(), Some(&b"hap1"[..]));
+        assert!(simulated[1].variants.is_empty());
+        assert_eq!(simulated[1].records[0].sequence().len(), 20);
+    }
+
+    #[test]
+    fn test_simulate_records_break() {
+        let mut reader = Fasta::from_bytes(b">chr1\nAAAAAAAAAACCCCCCCCCC\n".to_vec()).unwrap();
+        let breaks = generator(MisassemblyCommands::Break { number: 1 });
+        let mut rng = StdRng::seed_from_u64(42);
+        let simulated: Vec<SimulatedRecord> = simulate_records(
+            &mut reader,
+            SimulationConfig {
+                generator: &breaks,
+                regions: None,
+                soft_mask: false,
+            },
+            &mut rng,
+        )
+        .try_collect()
+        .unwrap();
+
+        let [Variant::Break { pos }] = simulated[0].variants[..] else {
+            panic!("Expected one break: {:?}", simulated[0].variants)
+        };
+        let names = simulated[0]
+            .records
+            .iter()
+            .map(|record| String::from_utf8_lossy(record.name()).into_owned())
+            .collect_vec();
+        assert_eq!(
+            names,
+            [format!("chr1:1-{pos}"), format!("chr1:{}-20", pos + 1)]
+        );
+    }
+}
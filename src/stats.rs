@@ -0,0 +1,222 @@
+use std::{collections::BTreeMap, io::Write};
+
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::{apply::Event, utils::SequenceType};
+
+/// Number of misassemblies requested and placed by record and type over a run.
+#[derive(Debug, Default)]
+pub struct RunStats {
+    records: Vec<(String, Tally, Vec<Event>)>,
+    types: BTreeMap<&'static str, Tally>,
+}
+
+#[derive(Debug, Default)]
+struct Tally {
+    requested: usize,
+    lengths: Vec<usize>,
+}
+
+/// Summary of the misassemblies of a record or type.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EventStats {
+    pub requested: usize,
+    pub placed: usize,
+    /// Total original bases affected or inserted.
+    pub bases: usize,
+    /// `None` if no misassemblies were placed.
+    pub lengths: Option<LengthQuantiles>,
+}
+
+/// Nearest-rank quantiles of misassembly lengths.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct LengthQuantiles {
+    pub min: usize,
+    pub q25: usize,
+    pub median: usize,
+    pub q75: usize,
+    pub max: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RecordStats {
+    pub name: String,
+    #[serde(flatten)]
+    pub stats: EventStats,
+    /// Placed events with IDs.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<Event>,
+}
+
+/// Statistics of a run written as JSON.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StatsReport {
+    pub records: Vec<RecordStats>,
+    pub types: BTreeMap<&'static str, EventStats>,
+}
+
+impl LengthQuantiles {
+    pub fn new(lengths: &[usize]) -> Option<Self> {
+        let lengths = lengths.iter().copied().sorted().collect_vec();
+        let quantile = |q: f64| -> Option<usize> {
+            let i = ((lengths.len().checked_sub(1)?) as f64 * q).round() as usize;
+            Some(lengths[i])
+        };
+        Some(Self {
+            min: quantile(0.0)?,
+            q25: quantile(0.25)?,
+            median: quantile(0.5)?,
+            q75: quantile(0.75)?,
+            max: quantile(1.0)?,
+        })
+    }
+}
+
+impl Tally {
+    fn summary(&self) -> EventStats {
+        EventStats {
+            requested: self.requested,
+            placed: self.lengths.len(),
+            bases: self.lengths.iter().sum(),
+            lengths: LengthQuantiles::new(&self.lengths),
+        }
+    }
+}
+
+impl RunStats {
+    /// Add a misassembled record with the number of misassemblies requested of each type and the events placed.
+    pub fn add_record(
+        &mut self,
+        name: &str,
+        requested: impl IntoIterator<Item = (SequenceType, usize)>,
+        placed: &[Event],
+    ) {
+        let mut record = Tally::default();
+        for (mtype, number) in requested {
+            record.requested += number;
+            self.types.entry(mtype.name()).or_default().requested += number;
+        }
+        for Event { variant, .. } in placed {
+            record.lengths.push(variant.length());
+            self.types
+                .entry(variant.sequence_type().name())
+                .or_default()
+                .lengths
+                .push(variant.length());
+        }
+        let events = placed
+            .iter()
+            .filter(|event| event.id.is_some())
+            .cloned()
+            .collect();
+        self.records.push((name.to_owned(), record, events));
+    }
+
+    pub fn report(&self) -> StatsReport {
+        StatsReport {
+            records: self
+                .records
+                .iter()
+                .map(|(name, tally, events)| RecordStats {
+                    name: name.clone(),
+                    stats: tally.summary(),
+                    events: events.clone(),
+                })
+                .collect(),
+            types: self
+                .types
+                .iter()
+                .map(|(mtype, tally)| (*mtype, tally.summary()))
+                .collect(),
+        }
+    }
+
+    /// Log the statistics of each misassembled record and type.
+    pub fn log(&self) {
+        let report = self.report();
+        log::info!("Misassembled {} record(s).", report.records.len());
+        let describe = |stats: &EventStats| {
+            let lengths = stats.lengths.map_or(String::new(), |lengths| {
+                format!(
+                    " Lengths (min/q25/median/q75/max): {}/{}/{}/{}/{} bp.",
+                    lengths.min, lengths.q25, lengths.median, lengths.q75, lengths.max
+                )
+            });
+            format!(
+                "{} of {} placed over {} bp.{lengths}",
+                stats.placed, stats.requested, stats.bases
+            )
+        };
+        for record in report.records.iter() {
+            log::info!("{}: {}", record.name, describe(&record.stats));
+        }
+        for (mtype, stats) in report.types.iter() {
+            log::info!("{mtype}: {}", describe(stats));
+        }
+    }
+
+    pub fn write_json(&self, mut writer: impl Write) -> eyre::Result<()> {
+        serde_json::to_writer_pretty(&mut writer, &self.report())?;
+        writeln!(writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::apply::Variant;
+
+    #[test]
+    fn test_run_stats() {
+        let event = |variant, id: Option<&str>| Event {
+            id: id.map(str::to_owned),
+            variant,
+        };
+        let mut stats = RunStats::default();
+        stats.add_record(
+            "chr1",
+            [(SequenceType::Misjoin, 3)],
+            &[
+                event(Variant::Deletion(0..10), None),
+                event(Variant::Deletion(20..50), None),
+            ],
+        );
+        stats.add_record(
+            "chr2",
+            [(SequenceType::Misjoin, 1), (SequenceType::Break, 1)],
+            &[
+                event(Variant::Deletion(0..20), Some("misasim.1")),
+                event(Variant::Break { pos: 5 }, Some("misasim.2")),
+            ],
+        );
+        let report = stats.report();
+        assert!(report.records[0].events.is_empty());
+        assert_eq!(report.records[1].events[1].id.as_deref(), Some("misasim.2"));
+        let json = serde_json::to_string(&report).unwrap();
+        assert!(json.contains(r#"{"id":"misasim.1","type":"deletion","start":0,"end":20}"#));
+        assert_eq!(
+            report.records[0].stats,
+            EventStats {
+                requested: 3,
+                placed: 2,
+                bases: 40,
+                lengths: Some(LengthQuantiles {
+                    min: 10,
+                    q25: 10,
+                    median: 30,
+                    q75: 30,
+                    max: 30
+                })
+            }
+        );
+        assert_eq!(report.types["misjoin"].requested, 4);
+        assert_eq!(report.types["misjoin"].placed, 3);
+        assert_eq!(report.types["misjoin"].lengths.unwrap().median, 20);
+        assert_eq!(report.types["break"].bases, 0);
+        assert!(LengthQuantiles::new(&[]).is_none());
+    }
+}
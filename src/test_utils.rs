@@ -0,0 +1,27 @@
+//! Helpers shared by tests.
+
+use std::path::{Path, PathBuf};
+
+/// Temporary directory of a test's files. Removed when dropped, including when the test fails.
+pub struct TestDir(PathBuf);
+
+impl TestDir {
+    /// Create a directory unique to the test `name` and this process.
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("misasim_test_{name}_{}", std::process::id()));
+        // Left by a killed run with the same process id.
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+
+    pub fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.0.join(path)
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
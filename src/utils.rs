@@ -1,148 +1,1105 @@
-use std::{fs::File, io::Write, ops::Range};
+use std::{borrow::Cow, io::Write, ops::Range, str::FromStr};
 
-use eyre::bail;
+use eyre::{bail, Context};
 use iset::{IntervalMap, IntervalSet};
-use noodles::{
-    bed::{self, record::Builder},
-    core::Position,
-    fasta::{
-        self,
-        record::{Definition, Sequence},
-        Writer,
-    },
+use noodles::{bed::record::Builder, core::Position, fasta::record::Definition};
+use rand::{
+    distributions::{Distribution, WeightedIndex},
+    seq::IteratorRandom,
+    Rng,
 };
-use rand::{rngs::StdRng, seq::IteratorRandom, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::CoverageBias,
+    io::{FastaWriter, TruthWriter},
+};
+
+/// Number of attempts to place a misassembly before giving up.
+const MAX_PLACEMENT_ATTEMPTS: usize = 1_000;
+
+/// Regions to place misassemblies in.
+pub type Regions = IntervalMap<Position, Region>;
+
+/// Sampling weight and name of a region.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Region {
+    pub weight: f64,
+    /// Name from the 4th column of the input bed file.
+    pub name: Option<String>,
+}
+
+impl From<f64> for Region {
+    fn from(weight: f64) -> Self {
+        Self { weight, name: None }
+    }
+}
+
+/// Sequence of slices of an original sequence and new bases.
+#[derive(Debug, Clone, Default)]
+pub struct SegmentedSequence<'a> {
+    segments: Vec<Cow<'a, [u8]>>,
+    len: usize,
+}
+
+impl<'a> SegmentedSequence<'a> {
+    /// Append a segment. Empty segments are skipped.
+    pub fn push(&mut self, segment: impl Into<Cow<'a, [u8]>>) {
+        let segment = segment.into();
+        if segment.is_empty() {
+            return;
+        }
+        self.len += segment.len();
+        self.segments.push(segment);
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn segments(&self) -> &[Cow<'a, [u8]>] {
+        &self.segments
+    }
+
+    /// Copy the bases of a range of the sequence. The range is clamped to the sequence.
+    pub fn slice(&self, range: Range<usize>) -> Vec<u8> {
+        let mut bases = Vec::new();
+        let mut start = 0;
+        for segment in self.segments.iter() {
+            let end = start + segment.len();
+            let (overlap_start, overlap_end) = (range.start.max(start), range.end.min(end));
+            if overlap_start < overlap_end {
+                bases.extend_from_slice(&segment[overlap_start - start..overlap_end - start]);
+            }
+            start = end;
+        }
+        bases
+    }
+}
+
+impl<'a> From<&'a [u8]> for SegmentedSequence<'a> {
+    fn from(seq: &'a [u8]) -> Self {
+        let mut segmented = SegmentedSequence::default();
+        segmented.push(seq);
+        segmented
+    }
+}
+
+impl PartialEq for SegmentedSequence<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len
+            && self
+                .segments
+                .iter()
+                .flat_map(|seg| seg.iter())
+                .eq(other.segments.iter().flat_map(|seg| seg.iter()))
+    }
+}
+
+impl Eq for SegmentedSequence<'_> {}
+
+/// Type of misassembly introduced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SequenceType {
+    Misjoin,
+    Gap,
+    #[serde(alias = "false_dupe")]
+    FalseDuplication,
+    Break,
+    Insertion,
+    Inversion,
+}
+
+impl SequenceType {
+    /// Name of the type in truth files. ex. false_duplication
+    pub fn name(&self) -> &'static str {
+        match self {
+            SequenceType::Misjoin => "misjoin",
+            SequenceType::Gap => "gap",
+            SequenceType::FalseDuplication => "false_duplication",
+            SequenceType::Break => "break",
+            SequenceType::Insertion => "insertion",
+            SequenceType::Inversion => "inversion",
+        }
+    }
+
+    /// Equivalent NucFlag misassembly category. Breaks split records and have no equivalent.
+    pub fn nucflag_category(&self) -> Option<&'static str> {
+        match self {
+            SequenceType::Misjoin => Some("misjoin"),
+            SequenceType::Gap => Some("gap"),
+            SequenceType::FalseDuplication => Some("false_dupe"),
+            SequenceType::Insertion => Some("indel"),
+            // Inversions appear as misjoins at their breakpoints.
+            SequenceType::Inversion => Some("misjoin"),
+            SequenceType::Break => None,
+        }
+    }
+
+    /// Equivalent Flagger label. Breaks split records and have no equivalent.
+    pub fn flagger_label(&self) -> Option<&'static str> {
+        match self {
+            SequenceType::Misjoin
+            | SequenceType::Gap
+            | SequenceType::Insertion
+            | SequenceType::Inversion => Some("Err"),
+            SequenceType::FalseDuplication => Some("Dup"),
+            SequenceType::Break => None,
+        }
+    }
+}
+
+impl FromStr for SequenceType {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "misjoin" => SequenceType::Misjoin,
+            "gap" => SequenceType::Gap,
+            "false_duplication" | "false_dupe" => SequenceType::FalseDuplication,
+            "break" => SequenceType::Break,
+            "insertion" => SequenceType::Insertion,
+            "inversion" => SequenceType::Inversion,
+            _ => bail!("Invalid misassembly type: {s}"),
+        })
+    }
+}
+
+/// Bases of a misassembled range in a sequence of length `seq_len`.
+/// Empty ranges, like misjoins, are expanded to their flanking bases.
+pub fn misassembled_bases(range: &Range<usize>, seq_len: usize) -> Range<usize> {
+    let range = if range.is_empty() {
+        range.start.saturating_sub(1)..range.start + 1
+    } else {
+        range.clone()
+    };
+    range.start.min(seq_len)..range.end.min(seq_len)
+}
 
 /// Generate random sequence segments ranges.
 ///
 /// # Arguments
 /// * `seq_len` - Length of sequence to clamp regions.
-/// * `regions` - Positions to choose segments from.
+/// * `regions` - Positions to choose segments from. Chosen by weight if any weight isn't 1.
 /// * `length` - The maximum length of a generate segment.
 /// * `number` - The number of segments to generate.
-/// * `seed` - The random seed to use.
+/// * `rng` - The random number generator to use.
+/// * `randomize_length` - Randomize the length of each segment.
+/// * `min_length` - The minimum length of a randomized segment.
+/// * `max_total_bases` - The maximum total length of all segments.
 ///
 /// # Returns
 /// An iterator of tuples containing the start, stop, and a random length range starting at the start of the segment.
+/// Fewer than `number` segments are returned if no more can be placed.
 ///
+#[allow(clippy::too_many_arguments)]
 pub fn generate_random_seq_ranges(
     seq_len: usize,
-    regions: &IntervalSet<Position>,
+    regions: &Regions,
     length: usize,
     number: usize,
-    seed: Option<u64>,
+    rng: &mut impl Rng,
     randomize_length: bool,
-) -> eyre::Result<Option<impl Iterator<Item = (usize, usize, Range<usize>)>>> {
-    let mut rng = seed.map_or(StdRng::from_entropy(), StdRng::seed_from_u64);
+    min_length: usize,
+    max_total_bases: Option<usize>,
+) -> eyre::Result<impl Iterator<Item = (usize, usize, Range<usize>)>> {
+    if randomize_length && !(1..=length).contains(&min_length) {
+        bail!("Minimum length ({min_length}) must be between 1 and the length ({length}).")
+    }
     let mut remaining_segments = number;
     let mut positions = IntervalMap::new();
 
+    // Sample regions by their weight if weighted.
+    let weighted_regions = if regions.unsorted_values().any(|region| region.weight != 1.0) {
+        let (intervals, weights): (Vec<Range<Position>>, Vec<f64>) = regions
+            .unsorted_iter()
+            .map(|(interval, region)| (interval, region.weight))
+            .unzip();
+        // ex. A record without biased regions and a bias fraction of 1.
+        if weights.iter().all(|weight| *weight == 0.0) {
+            log::warn!("No regions have any weight to place misassemblies in.");
+            remaining_segments = 0;
+            None
+        } else {
+            let index = WeightedIndex::new(weights)
+                .context("Weights of regions must sum to a positive, finite value.")?;
+            Some((intervals, index))
+        }
+    } else {
+        None
+    };
+
     // Keep going until required number of segments generated
+    let mut attempts = 0;
+    let mut total_bases = 0;
     while remaining_segments > 0 {
-        // Choose a starting position within the provided region set. ex. bed file.
-        let Some(pos) = regions.unsorted_iter().choose(&mut rng) else {
+        // Give up on the remaining segments if no position found.
+        if attempts == MAX_PLACEMENT_ATTEMPTS {
+            for i in number - remaining_segments + 1..=number {
+                log::warn!(
+                    "Could not place misassembly {i} of {number} after {MAX_PLACEMENT_ATTEMPTS} attempts."
+                );
+            }
             break;
+        }
+        attempts += 1;
+
+        // Choose a starting position within the provided region set. ex. bed file.
+        let pos = if let Some((intervals, weights)) = weighted_regions.as_ref() {
+            intervals[weights.sample(rng)].clone()
+        } else {
+            let Some((pos, _)) = regions.unsorted_iter().choose(rng) else {
+                break;
+            };
+            pos
         };
-        let (start, stop) = (Into::<usize>::into(pos.start), pos.end.into());
+        let (start, stop): (usize, usize) = (pos.start.into(), pos.end.into());
         // Then if randomizing length, choose a starting position within the selected region.
         // Choose a random ending position.
-        let (region_start, region_stop) = if randomize_length {
-            let Some(region_start) = (start..stop).choose(&mut rng) else {
-                bail!("Invalid pos: {pos:?}")
+        let (region_start, mut region_stop) = if randomize_length {
+            // Leave enough room for the minimum length. Otherwise, try another region.
+            let Some(region_start) = (start..(stop + 1).saturating_sub(min_length)).choose(rng)
+            else {
+                continue;
             };
-            let region_stop = (region_start + 1..region_start + length + 1)
-                .choose(&mut rng)
-                .map(|stop| stop.clamp(1, seq_len))
+            // Keep within the selected region.
+            let region_stop = (region_start + min_length..region_start + length + 1)
+                .choose(rng)
+                .map(|region_stop| region_stop.clamp(1, stop.min(seq_len)))
                 .unwrap();
             (region_start, region_stop)
         } else {
             // Choose a starting position within the range shortened by the desired length.
             // Use the randomly selected starting position and add the length.
-            let stop = stop - length;
-            let Some(region_start) = (start..stop).choose(&mut rng) else {
-                bail!("Invalid pos: {pos:?}")
+            let stop = stop.saturating_sub(length);
+            let Some(region_start) = (start..stop).choose(rng) else {
+                continue;
             };
             (region_start, region_start + length)
         };
 
+        // Shrink the segment or stop once the maximum total bases is reached.
+        if let Some(max_total_bases) = max_total_bases {
+            let remaining_bases = max_total_bases.saturating_sub(total_bases);
+            if region_stop - region_start > remaining_bases {
+                if randomize_length && remaining_bases >= min_length {
+                    region_stop = region_start + remaining_bases;
+                } else {
+                    log::warn!(
+                        "Reached the maximum total bases ({max_total_bases}). Placed {} of {number} misassemblies.",
+                        number - remaining_segments
+                    );
+                    break;
+                }
+            }
+        }
+
         // Ensure no overlaps.
         // Keep iterating until a valid position found.
         if positions.has_overlap(region_start..region_stop) {
             continue;
         }
         positions.insert(region_start..region_stop, (start, stop));
-        remaining_segments -= 1
+        remaining_segments -= 1;
+        total_bases += region_stop - region_start;
+        attempts = 0;
+    }
+
+    Ok(positions
+        .into_iter(..)
+        .map(move |(range, (start, stop))| (start, stop, range)))
+}
+
+/// Check the number of placed misassemblies against the number requested.
+/// Fails if strict and fewer were placed. Otherwise, warns.
+pub fn check_placed_count(placed: usize, number: usize, strict: bool) -> eyre::Result<()> {
+    if placed < number {
+        let msg = format!("Only {placed} of {number} misassemblies could be placed.");
+        if strict {
+            bail!(msg)
+        }
+        log::warn!("{msg}");
+    }
+    Ok(())
+}
+
+/// Place up to `number` misassemblies in a sequence of `seq_len` bases.
+/// Same as [`generate_random_seq_ranges`] but only the sorted, 0-based ranges are returned.
+#[allow(clippy::too_many_arguments)]
+pub fn place_misassemblies(
+    seq_len: usize,
+    regions: &Regions,
+    length: usize,
+    number: usize,
+    rng: &mut impl Rng,
+    randomize_length: bool,
+    min_length: usize,
+    max_total_bases: Option<usize>,
+) -> eyre::Result<Vec<Range<usize>>> {
+    Ok(generate_random_seq_ranges(
+        seq_len,
+        regions,
+        length,
+        number,
+        rng,
+        randomize_length,
+        min_length,
+        max_total_bases,
+    )?
+    .map(|(_, _, range)| range)
+    .collect())
+}
+
+/// Find runs of Ns in a sequence read in chunks as 0-based, half-open ranges.
+pub fn find_gaps<S: AsRef<[u8]>>(
+    chunks: impl IntoIterator<Item = eyre::Result<S>>,
+) -> eyre::Result<Vec<Range<usize>>> {
+    let mut gaps: Vec<Range<usize>> = vec![];
+    let mut offset = 0;
+    for chunk in chunks {
+        let chunk = chunk?;
+        let chunk = chunk.as_ref();
+        for (i, _) in chunk
+            .iter()
+            .enumerate()
+            .filter(|(_, base)| base.eq_ignore_ascii_case(&b'N'))
+        {
+            let i = offset + i;
+            match gaps.last_mut() {
+                Some(gap) if gap.end == i => gap.end += 1,
+                _ => gaps.push(i..i + 1),
+            }
+        }
+        offset += chunk.len();
+    }
+    Ok(gaps)
+}
+
+/// Merge overlapping and bookended regions.
+/// Merged regions have the maximum weight and the unique names of their regions.
+pub fn merge_intervals(regions: &Regions) -> Regions {
+    let mut merged: Vec<(Range<Position>, Region)> = vec![];
+    for (interval, region) in regions.iter(..) {
+        match merged.last_mut() {
+            // Bookended bed records start one past the end of the previous record.
+            Some((last_interval, last_region))
+                if usize::from(interval.start) <= usize::from(last_interval.end) + 1 =>
+            {
+                last_interval.end = last_interval.end.max(interval.end);
+                last_region.weight = last_region.weight.max(region.weight);
+                let Some(name) = region.name.as_ref() else {
+                    continue;
+                };
+                match last_region.name.as_mut() {
+                    Some(last_name) if !last_name.split(',').any(|n| n == name) => {
+                        last_name.push(',');
+                        last_name.push_str(name);
+                    }
+                    Some(_) => (),
+                    None => last_region.name = Some(name.clone()),
+                }
+            }
+            _ => merged.push((interval, region.clone())),
+        }
+    }
+    Regions::from_iter(merged)
+}
+
+/// Subtract intervals from regions, splitting regions around excluded intervals.
+pub fn subtract_intervals<V: Clone>(
+    regions: &IntervalMap<Position, V>,
+    exclude: &IntervalSet<Position>,
+) -> IntervalMap<Position, V> {
+    let mut new_regions = IntervalMap::new();
+    for (region, value) in regions.unsorted_iter() {
+        let mut start = region.start;
+        for excl in exclude.iter(region.clone()) {
+            if excl.start > start {
+                new_regions.insert(start..excl.start, value.clone());
+            }
+            start = start.max(excl.end);
+        }
+        if start < region.end {
+            new_regions.insert(start..region.end, value.clone());
+        }
+    }
+    new_regions
+}
+
+/// Intersect regions with intervals.
+pub fn intersect_intervals<V: Clone>(
+    regions: &IntervalMap<Position, V>,
+    intervals: &IntervalSet<Position>,
+) -> IntervalMap<Position, V> {
+    let mut new_regions = IntervalMap::new();
+    for (region, value) in regions.unsorted_iter() {
+        // Skip already covered bases of overlapping intervals.
+        let mut pos = region.start;
+        for interval in intervals.iter(region.clone()) {
+            let (start, end) = (pos.max(interval.start), region.end.min(interval.end));
+            if start < end {
+                new_regions.insert(start..end, value.clone());
+                pos = end;
+            }
+        }
+    }
+    new_regions
+}
+
+/// Weight regions so that a fraction of the total weight is within the biased intervals.
+/// Regions within and outside of the biased intervals are weighted by their length.
+pub fn bias_intervals(regions: &Regions, bias: &IntervalSet<Position>, fraction: f64) -> Regions {
+    let region_len = |region: Range<Position>| usize::from(region.end) - usize::from(region.start);
+    let inside = intersect_intervals(regions, bias);
+    let outside = subtract_intervals(regions, bias);
+    let inside_len: usize = inside.unsorted_intervals().map(region_len).sum();
+    let outside_len: usize = outside.unsorted_intervals().map(region_len).sum();
+    if inside_len == 0 {
+        log::warn!("No regions within biased intervals.");
+    } else if outside_len == 0 {
+        log::warn!("No regions outside of biased intervals.");
     }
 
-    let Some(end) = positions.largest().map(|(p, _)| p.end) else {
-        bail!("No positions found.")
+    let mut new_regions = Regions::new();
+    for (rgns, frac, total_len) in [
+        (inside, fraction, inside_len),
+        (outside, 1.0 - fraction, outside_len),
+    ] {
+        for (interval, region) in rgns.unsorted_iter() {
+            let len = region_len(interval.clone());
+            let weight = region.weight * frac * len as f64 / total_len as f64;
+            new_regions.insert(
+                interval,
+                Region {
+                    weight,
+                    ..region.clone()
+                },
+            );
+        }
+    }
+    new_regions
+}
+
+/// Weight regions by coverage with the depth as the weight of each coverage interval.
+/// The weight of each region is scaled by the mean weight of its bases' depths.
+/// Low coverage is weighted by `1 / (1 + depth)` and high coverage by `depth`. Uncovered bases have a depth of 0.
+pub fn coverage_intervals(regions: &Regions, coverage: &Regions, bias: CoverageBias) -> Regions {
+    let depth_weight = |depth: f64| match bias {
+        CoverageBias::Low => 1.0 / (1.0 + depth),
+        CoverageBias::High => depth,
     };
+    let mut new_regions = Regions::new();
+    for (interval, region) in regions.unsorted_iter() {
+        let region_len = (usize::from(interval.end) - usize::from(interval.start)) as f64;
+        let mut add_region = |start: Position, end: Position, depth: f64| {
+            if start < end {
+                let len = (usize::from(end) - usize::from(start)) as f64;
+                let weight = region.weight * depth_weight(depth) * len / region_len;
+                new_regions.insert(
+                    start..end,
+                    Region {
+                        weight,
+                        ..region.clone()
+                    },
+                );
+            }
+        };
+        // Bases without coverage are added between covered intervals.
+        let mut pos = interval.start;
+        for (cov_interval, depth) in coverage.iter(interval.clone()) {
+            let (start, end) = (
+                pos.max(cov_interval.start),
+                interval.end.min(cov_interval.end),
+            );
+            if start < end {
+                add_region(pos, start, 0.0);
+                add_region(start, end, depth.weight);
+                pos = end;
+            }
+        }
+        add_region(pos, interval.end, 0.0);
+    }
+    new_regions
+}
+
+/// Restrict regions to windows around randomly placed hotspots.
+/// Hotspots are placed by region length and weight and each hotspot has an equal weight.
+pub fn hotspot_intervals(
+    regions: &Regions,
+    num_hotspots: usize,
+    spread: usize,
+    rng: &mut impl Rng,
+) -> eyre::Result<Regions> {
+    let region_len = |region: &Range<Position>| usize::from(region.end) - usize::from(region.start);
+    let (intervals, weights): (Vec<Range<Position>>, Vec<f64>) = regions
+        .unsorted_iter()
+        .map(|(interval, region)| {
+            let weight = region.weight * region_len(&interval) as f64;
+            (interval, weight)
+        })
+        .unzip();
+    let weights = WeightedIndex::new(weights)?;
 
-    // let last_pos = positions.last().context("No positions found.")?;
-    Ok(Some(
-        positions
-            .into_iter(0..end)
-            .map(move |(range, (start, stop))| (start, stop, range)),
-    ))
+    let mut new_regions = Regions::new();
+    for _ in 0..num_hotspots {
+        let interval = &intervals[weights.sample(rng)];
+        let hotspot = rng.gen_range(usize::from(interval.start)..usize::from(interval.end));
+        let window = IntervalSet::from_iter(std::iter::once(
+            Position::try_from(hotspot.saturating_sub(spread).max(1))?
+                ..Position::try_from(hotspot + spread + 1)?,
+        ));
+        let hotspot_regions = intersect_intervals(regions, &window);
+        let total_weight: f64 = hotspot_regions
+            .unsorted_iter()
+            .map(|(interval, region)| region.weight * region_len(&interval) as f64)
+            .sum();
+        for (interval, region) in hotspot_regions.unsorted_iter() {
+            let weight = region.weight * region_len(&interval) as f64 / total_weight;
+            new_regions.insert(
+                interval,
+                Region {
+                    weight,
+                    ..region.clone()
+                },
+            );
+        }
+    }
+    Ok(new_regions)
 }
 
-pub fn write_misassembly<O, R, I>(
-    seq: Vec<u8>,
+/// Derive the seed of a record from a global seed and the record name.
+/// Uses FNV-1a and a SplitMix64 finalizer so seeds are stable across platforms, releases, and record order.
+pub fn record_seed(seed: u64, name: &str) -> u64 {
+    const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+    let hash = seed
+        .to_le_bytes()
+        .iter()
+        .chain(name.as_bytes())
+        .fold(FNV_OFFSET, |hash, b| {
+            (hash ^ u64::from(*b)).wrapping_mul(FNV_PRIME)
+        });
+    let mut z = hash.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Reverse complement a sequence. IUPAC ambiguity codes and case are preserved.
+pub fn reverse_complement(seq: &[u8]) -> Vec<u8> {
+    seq.iter()
+        .rev()
+        .map(|b| {
+            let comp = match b.to_ascii_uppercase() {
+                b'A' => b'T',
+                b'T' | b'U' => b'A',
+                b'G' => b'C',
+                b'C' => b'G',
+                b'R' => b'Y',
+                b'Y' => b'R',
+                b'K' => b'M',
+                b'M' => b'K',
+                b'B' => b'V',
+                b'V' => b'B',
+                b'D' => b'H',
+                b'H' => b'D',
+                other => other,
+            };
+            if b.is_ascii_lowercase() {
+                comp.to_ascii_lowercase()
+            } else {
+                comp
+            }
+        })
+        .collect()
+}
+
+/// Append a note to the description of a definition.
+pub fn annotate_definition(definition: &Definition, note: &str) -> Definition {
+    let description = if let Some(desc) = definition.description() {
+        [desc, b" ", note.as_bytes()].concat()
+    } else {
+        note.as_bytes().to_vec()
+    };
+    Definition::new(definition.name(), Some(description))
+}
+
+/// Write a misassembled record and its misassemblies to the truth bed file.
+pub fn write_misassembly<O, B, R, I>(
+    seq: SegmentedSequence,
+    mtype: SequenceType,
     regions: I,
+    misassembled_ranges: &[Range<usize>],
     definition: Definition,
-    output_fa: &mut Writer<O>,
-    output_bed: Option<&mut bed::Writer<File>>,
+    output_fa: &mut FastaWriter<O>,
+    output_bed: Option<&mut TruthWriter<B>>,
 ) -> eyre::Result<()>
 where
     O: Write,
+    B: Write,
     R: TryInto<Builder<3>>,
     I: IntoIterator<Item = R>,
 {
     let record_name = std::str::from_utf8(definition.name())?;
     // Write the BED file if provided.
     if let Some(writer_bed) = output_bed {
-        for builder in regions
-            .into_iter()
-            .flat_map(|r| TryInto::<Builder<3>>::try_into(r))
-        {
-            let record = builder.set_reference_sequence_name(record_name).build()?;
-            writer_bed.write_record(&record)?;
-        }
+        writer_bed.write_misassembly(
+            record_name,
+            mtype,
+            regions,
+            misassembled_ranges,
+            seq.len(),
+        )?;
     };
 
-    output_fa.write_record(&fasta::Record::new(definition, Sequence::from(seq)))?;
-    Ok(())
+    output_fa.write_record_chunks(
+        &definition,
+        seq.len(),
+        seq.segments().iter().map(eyre::Ok),
+        misassembled_ranges,
+    )
 }
 
 #[cfg(test)]
 mod test {
     use iset::IntervalSet;
     use itertools::Itertools;
-    use noodles::core::Position;
+    use noodles::{bed, core::Position};
+    use rand::{rngs::StdRng, SeedableRng};
 
-    use super::generate_random_seq_ranges;
+    use crate::config::CoverageBias;
+
+    use super::{
+        bias_intervals, check_placed_count, coverage_intervals, find_gaps,
+        generate_random_seq_ranges, hotspot_intervals, intersect_intervals, merge_intervals,
+        place_misassemblies, record_seed, reverse_complement, subtract_intervals, Region, Regions,
+        SegmentedSequence, SequenceType,
+    };
+
+    #[test]
+    fn test_sequence_type_serde() {
+        let json = serde_json::to_string(&SequenceType::FalseDuplication).unwrap();
+        assert_eq!(json, r#""false_duplication""#);
+        for json in [json.as_str(), r#""false_dupe""#] {
+            assert_eq!(
+                serde_json::from_str::<SequenceType>(json).unwrap(),
+                SequenceType::FalseDuplication
+            );
+        }
+    }
 
     #[test]
     fn test_generate_random_seq_ranges() {
-        let positions = vec![Position::new(1).unwrap()..Position::new(10).unwrap()];
-        let regions = IntervalSet::from_iter(positions);
-        let segments = generate_random_seq_ranges(40, &regions, 10, 2, Some(42), true)
-            .unwrap()
-            .unwrap()
-            .collect_vec();
+        let positions = vec![(
+            Position::new(1).unwrap()..Position::new(10).unwrap(),
+            1.0.into(),
+        )];
+        let regions = Regions::from_iter(positions);
+        let segments = generate_random_seq_ranges(
+            40,
+            &regions,
+            10,
+            2,
+            &mut StdRng::seed_from_u64(42),
+            true,
+            1,
+            None,
+        )
+        .unwrap()
+        .collect_vec();
 
         assert_eq!(segments, [(1, 10, 2..3), (1, 10, 3..9)])
     }
 
     #[test]
     fn test_generate_random_seq_ranges_static_length() {
-        let positions = vec![Position::new(1).unwrap()..Position::new(10).unwrap()];
-        let regions = IntervalSet::from_iter(positions);
+        let positions = vec![(
+            Position::new(1).unwrap()..Position::new(10).unwrap(),
+            1.0.into(),
+        )];
+        let regions = Regions::from_iter(positions);
         // Generate two regions of length 2.
-        let segments = generate_random_seq_ranges(40, &regions, 2, 2, Some(42), false)
-            .unwrap()
-            .unwrap()
-            .collect_vec();
+        let segments = generate_random_seq_ranges(
+            40,
+            &regions,
+            2,
+            2,
+            &mut StdRng::seed_from_u64(42),
+            false,
+            1,
+            None,
+        )
+        .unwrap()
+        .collect_vec();
         assert_eq!(segments, [(1, 10, 4..6), (1, 10, 7..9)])
     }
+
+    #[test]
+    fn test_generate_random_seq_ranges_min_length() {
+        let positions = vec![(
+            Position::new(1).unwrap()..Position::new(30).unwrap(),
+            1.0.into(),
+        )];
+        let regions = Regions::from_iter(positions);
+        let segments = generate_random_seq_ranges(
+            40,
+            &regions,
+            10,
+            3,
+            &mut StdRng::seed_from_u64(42),
+            true,
+            5,
+            None,
+        )
+        .unwrap()
+        .collect_vec();
+        assert!(segments.iter().all(|(_, _, range)| range.len() >= 5));
+        assert!(generate_random_seq_ranges(
+            40,
+            &regions,
+            10,
+            3,
+            &mut StdRng::seed_from_u64(42),
+            true,
+            11,
+            None
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_generate_random_seq_ranges_weighted() {
+        let pos = |p| Position::new(p).unwrap();
+        let regions = Regions::from_iter([
+            (pos(1)..pos(20), 0.0.into()),
+            (pos(20)..pos(40), 1.5.into()),
+        ]);
+        let segments = generate_random_seq_ranges(
+            40,
+            &regions,
+            2,
+            4,
+            &mut StdRng::seed_from_u64(42),
+            false,
+            1,
+            None,
+        )
+        .unwrap()
+        .collect_vec();
+        assert!(segments.iter().all(|(start, _, _)| *start == 20));
+    }
+
+    #[test]
+    fn test_generate_random_seq_ranges_retry() {
+        let pos = |p| Position::new(p).unwrap();
+        // Too small for any segment.
+        let regions = Regions::from_iter([(pos(1)..pos(5), 1.0.into())]);
+        let segments = generate_random_seq_ranges(
+            40,
+            &regions,
+            10,
+            1,
+            &mut StdRng::seed_from_u64(42),
+            false,
+            1,
+            None,
+        )
+        .unwrap()
+        .collect_vec();
+        assert!(segments.is_empty());
+        assert!(check_placed_count(segments.len(), 1, false).is_ok());
+        assert!(check_placed_count(segments.len(), 1, true).is_err());
+        // Segments placed in the other region.
+        let regions =
+            Regions::from_iter([(pos(1)..pos(5), 1.0.into()), (pos(10)..pos(40), 1.0.into())]);
+        let segments = generate_random_seq_ranges(
+            40,
+            &regions,
+            10,
+            2,
+            &mut StdRng::seed_from_u64(42),
+            false,
+            1,
+            None,
+        )
+        .unwrap()
+        .collect_vec();
+        assert_eq!(segments.len(), 2);
+        // Only room for three segments.
+        let segments = generate_random_seq_ranges(
+            40,
+            &regions,
+            10,
+            5,
+            &mut StdRng::seed_from_u64(42),
+            false,
+            1,
+            None,
+        )
+        .unwrap()
+        .collect_vec();
+        assert!(segments.len() < 5);
+    }
+
+    #[test]
+    fn test_generate_random_seq_ranges_max_total_bases() {
+        let positions = vec![(
+            Position::new(1).unwrap()..Position::new(100).unwrap(),
+            1.0.into(),
+        )];
+        let regions = Regions::from_iter(positions);
+        let segments = generate_random_seq_ranges(
+            100,
+            &regions,
+            10,
+            5,
+            &mut StdRng::seed_from_u64(42),
+            false,
+            1,
+            Some(25),
+        )
+        .unwrap()
+        .collect_vec();
+        assert_eq!(segments.len(), 2);
+        // Last segment shrunk to fit.
+        let segments = generate_random_seq_ranges(
+            100,
+            &regions,
+            10,
+            5,
+            &mut StdRng::seed_from_u64(42),
+            true,
+            1,
+            Some(25),
+        )
+        .unwrap()
+        .collect_vec();
+        assert!(
+            segments
+                .iter()
+                .map(|(_, _, range)| range.len())
+                .sum::<usize>()
+                <= 25
+        );
+    }
+
+    #[test]
+    fn test_find_gaps() {
+        let seq = b"NNACGTnnNAC";
+        assert_eq!(find_gaps([eyre::Ok(seq)]).unwrap(), [0..2, 6..9]);
+        // Gaps spanning chunks are merged.
+        let chunks = [&seq[..1], &seq[1..7], &seq[7..]].map(eyre::Ok);
+        assert_eq!(find_gaps(chunks).unwrap(), [0..2, 6..9]);
+        assert!(find_gaps([eyre::Ok(b"ACGT")]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_merge_intervals() {
+        let regions = Regions::from_iter(
+            [
+                "chr1\t0\t10\ta\t1",
+                "chr1\t4\t20\tb\t2",
+                "chr1\t20\t30\t.\t1",
+                "chr1\t30\t40\tc\t1",
+                "chr1\t45\t50\td\t1",
+            ]
+            .map(|line| {
+                let rec: bed::Record<5> = line.parse().unwrap();
+                let region = Region {
+                    weight: rec.score().map_or(1.0, |score| u16::from(score).into()),
+                    name: rec.name().map(ToString::to_string),
+                };
+                (rec.start_position()..rec.end_position(), region)
+            }),
+        );
+        let pos = |p| Position::new(p).unwrap();
+        let named = |weight, name: &str| Region {
+            weight,
+            name: Some(name.to_owned()),
+        };
+        assert_eq!(
+            merge_intervals(&regions).iter(..).collect_vec(),
+            [
+                (pos(1)..pos(40), &named(2.0, "a,b,c")),
+                (pos(46)..pos(50), &named(1.0, "d"))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_subtract_intervals() {
+        let pos = |p| Position::new(p).unwrap();
+        let regions = Regions::from_iter([
+            (pos(1)..pos(100), 1.0.into()),
+            (pos(200)..pos(300), 2.0.into()),
+        ]);
+        let exclude =
+            IntervalSet::from_iter([pos(10)..pos(20), pos(15)..pos(30), pos(250)..pos(400)]);
+        let new_regions = subtract_intervals(&regions, &exclude);
+        assert_eq!(
+            new_regions
+                .iter(..)
+                .map(|(interval, region)| (interval, region.weight))
+                .collect_vec(),
+            [
+                (pos(1)..pos(10), 1.0),
+                (pos(30)..pos(100), 1.0),
+                (pos(200)..pos(250), 2.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_intersect_intervals() {
+        let pos = |p| Position::new(p).unwrap();
+        let regions = Regions::from_iter([
+            (pos(1)..pos(100), 1.0.into()),
+            (pos(200)..pos(300), 2.0.into()),
+        ]);
+        let intervals =
+            IntervalSet::from_iter([pos(10)..pos(20), pos(15)..pos(30), pos(250)..pos(400)]);
+        let new_regions = intersect_intervals(&regions, &intervals);
+        assert_eq!(
+            new_regions
+                .iter(..)
+                .map(|(interval, region)| (interval, region.weight))
+                .collect_vec(),
+            [
+                (pos(10)..pos(20), 1.0),
+                (pos(20)..pos(30), 1.0),
+                (pos(250)..pos(300), 2.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bias_intervals() {
+        let pos = |p| Position::new(p).unwrap();
+        let regions = Regions::from_iter([(pos(1)..pos(101), 1.0.into())]);
+        let bias = IntervalSet::from_iter([pos(1)..pos(11)]);
+        let new_regions = bias_intervals(&regions, &bias, 0.75);
+        assert_eq!(
+            new_regions
+                .iter(..)
+                .map(|(interval, region)| (interval, region.weight))
+                .collect_vec(),
+            [(pos(1)..pos(11), 0.75), (pos(11)..pos(101), 0.25)]
+        );
+
+        // No misassemblies are placed if all of them must be in missing biased regions.
+        let new_regions = bias_intervals(&regions, &IntervalSet::new(), 1.0);
+        let ranges = place_misassemblies(
+            100,
+            &new_regions,
+            10,
+            2,
+            &mut StdRng::seed_from_u64(42),
+            false,
+            1,
+            None,
+        )
+        .unwrap();
+        assert!(ranges.is_empty());
+    }
+
+    #[test]
+    fn test_coverage_intervals() {
+        let pos = |p| Position::new(p).unwrap();
+        let regions = Regions::from_iter([(pos(1)..pos(101), 2.0.into())]);
+        let coverage = Regions::from_iter([
+            (pos(1)..pos(51), 3.0.into()),
+            (pos(76)..pos(201), 1.0.into()),
+        ]);
+        let weights = |bias| {
+            coverage_intervals(&regions, &coverage, bias)
+                .iter(..)
+                .map(|(interval, region)| (interval, region.weight))
+                .collect_vec()
+        };
+        assert_eq!(
+            weights(CoverageBias::Low),
+            [
+                (pos(1)..pos(51), 0.25),
+                (pos(51)..pos(76), 0.5),
+                (pos(76)..pos(101), 0.25)
+            ]
+        );
+        assert_eq!(
+            weights(CoverageBias::High),
+            [
+                (pos(1)..pos(51), 3.0),
+                (pos(51)..pos(76), 0.0),
+                (pos(76)..pos(101), 0.5)
+            ]
+        );
+
+        // No misassemblies are placed in records without coverage if biased towards high coverage.
+        let new_regions = coverage_intervals(&regions, &Regions::new(), CoverageBias::High);
+        let ranges = place_misassemblies(
+            100,
+            &new_regions,
+            10,
+            2,
+            &mut StdRng::seed_from_u64(42),
+            false,
+            1,
+            None,
+        )
+        .unwrap();
+        assert!(ranges.is_empty());
+    }
+
+    #[test]
+    fn test_hotspot_intervals() {
+        let pos = |p| Position::new(p).unwrap();
+        let regions = Regions::from_iter([
+            (pos(1)..pos(1000), 1.0.into()),
+            (pos(2000)..pos(3000), 1.0.into()),
+        ]);
+        let mut rng = StdRng::seed_from_u64(42);
+        let new_regions = hotspot_intervals(&regions, 2, 10, &mut rng).unwrap();
+        assert!(new_regions
+            .unsorted_intervals()
+            .all(|region| usize::from(region.end) - usize::from(region.start) <= 21));
+        assert!(new_regions
+            .unsorted_intervals()
+            .all(|region| regions.has_overlap(region)));
+        let total_weight: f64 = new_regions
+            .unsorted_values()
+            .map(|region| region.weight)
+            .sum();
+        assert!((total_weight - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_record_seed() {
+        // Must not change between releases or archived seeds stop reproducing.
+        assert_eq!(record_seed(42, "chr1"), 163974981944494243);
+        assert_ne!(record_seed(42, "chr1"), record_seed(42, "chr2"));
+        assert_ne!(record_seed(42, "chr1"), record_seed(43, "chr1"));
+    }
+
+    #[test]
+    fn test_reverse_complement() {
+        assert_eq!(reverse_complement(b"ACGTNacgtn"), b"nacgtNACGT");
+        assert_eq!(reverse_complement(b"RYKMSWBDHV"), b"BDHVWSKMRY");
+    }
+
+    #[test]
+    fn test_segmented_sequence() {
+        let seq = b"AAAACCCC";
+        let mut segmented = SegmentedSequence::default();
+        segmented.push(&seq[..4]);
+        segmented.push(&seq[4..4]);
+        segmented.push(b"nn".to_vec());
+        segmented.push(&seq[4..]);
+        assert_eq!(segmented.len(), 10);
+        assert_eq!(segmented.segments().len(), 3);
+        assert_eq!(segmented, SegmentedSequence::from(b"AAAAnnCCCC".as_slice()));
+    }
 }